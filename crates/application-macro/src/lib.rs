@@ -16,6 +16,7 @@ pub fn main(_attr: TokenStream, item: TokenStream) -> TokenStream {
             let args = Parser::parse();
             if let Err(err) = #ident.main(args) {
                 eprintln!("{err}");
+                std::process::exit(1);
             }
         }
     }
@@ -1,8 +1,91 @@
+use std::{
+    env,
+    error::Error,
+    io::{self, Write as _},
+};
+
 use application::clap;
 
-use crate::Execute;
+use crate::{
+    object::{GitObject, ObjectType},
+    odb::{self, ObjectStore as _},
+    repo::RealRepo,
+    rev_parse::resolve,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum CatFileError {
+    #[error("{0}: expected {1}, found {2}")]
+    TypeMismatch(String, ObjectType, ObjectType),
+    #[error("only one of -t, -s, -p or <type> may be given")]
+    Usage,
+}
 
 #[derive(Debug, clap::Args)]
-pub(crate) struct Args;
+pub(crate) struct Args {
+    /// Show the object type
+    #[arg(short = 't', group = "mode")]
+    show_type: bool,
+    /// Show the object size
+    #[arg(short = 's', group = "mode")]
+    show_size: bool,
+    /// Pretty-print the object's content
+    #[arg(short = 'p', group = "mode")]
+    pretty: bool,
+    /// Exit with zero status if the object exists
+    #[arg(short = 'e', group = "mode")]
+    exists: bool,
+    /// `[<type>] <object>`
+    #[arg(required = true, num_args = 1..=2, value_name = "object")]
+    names: Vec<String>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let has_mode = self.show_type || self.show_size || self.pretty || self.exists;
+        let (kind, name) = match (has_mode, self.names.as_slice()) {
+            (true, [name]) => (None, name),
+            (false, [kind, name]) => (Some(kind.parse::<ObjectType>()?), name),
+            _ => return Err(CatFileError::Usage.into()),
+        };
+
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let objects = repo.objects();
+        // like git, any revision will do, but a bad one is reported as a bad
+        // object name
+        let id = resolve(&repo, name).map_err(|_| odb::Error::InvalidName(name.clone()))?;
+        if self.exists {
+            if !objects.contains(&id) {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        let object = objects.read(&id)?;
+
+        let mut stdout = io::stdout().lock();
+        if self.show_type {
+            writeln!(stdout, "{}", object.kind)?;
+        } else if self.show_size {
+            writeln!(stdout, "{}", object.data.len())?;
+        } else if let Some(kind) = kind {
+            if kind != object.kind {
+                return Err(CatFileError::TypeMismatch(name.clone(), kind, object.kind).into());
+            }
+            stdout.write_all(&object.data)?;
+        } else {
+            match GitObject::parse(object.kind, &object.data, objects.algorithm())? {
+                GitObject::Tree(tree) => write!(stdout, "{tree}")?,
+                _ => stdout.write_all(&object.data)?,
+            }
+        }
 
-impl Execute for Args {}
+        Ok(())
+    }
+}
@@ -0,0 +1,1892 @@
+use std::{
+    env, fmt, fs, io,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+use crate::{repo::Config, wildmatch::wildmatch};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Bad config line {line} in {file}")]
+    Syntax { file: String, line: usize },
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Invalid section name: {0}")]
+    InvalidSection(String),
+    #[error("Missing config key {0}")]
+    MissingEnvKey(String),
+    #[error("Missing config value {0}")]
+    MissingEnvValue(String),
+    #[error("Bogus format in GIT_CONFIG_PARAMETERS")]
+    BogusParameters,
+    #[error("Exceeded maximum include depth ({MAX_INCLUDE_DEPTH}) while including {0}")]
+    IncludeDepth(PathBuf),
+    #[error("Include cycle detected at {0}")]
+    IncludeCycle(PathBuf),
+    #[error("Configuration file is missing: {0}")]
+    FileMissing(PathBuf),
+    #[error("{0} has multiple values")]
+    MultipleValues(String),
+    #[error("Invalid GIT_CONFIG_COUNT: {0}")]
+    InvalidCount(String),
+    #[error("Missing value for '{section}.{key}'")]
+    Missing { section: String, key: String },
+    #[error("Bad boolean config value '{value}' for '{section}.{key}'")]
+    InvalidBool {
+        section: String,
+        key: String,
+        value: String,
+    },
+    #[error("Bad numeric config value '{value}' for '{section}.{key}'")]
+    InvalidInt {
+        section: String,
+        key: String,
+        value: String,
+    },
+    #[error("Invalid path '{value}' for '{section}.{key}'")]
+    InvalidPath {
+        section: String,
+        key: String,
+        value: String,
+    },
+    #[error("Invalid color value '{value}' for '{section}.{key}'")]
+    InvalidColor {
+        section: String,
+        key: String,
+        value: String,
+    },
+    #[error("Invalid expiry date '{value}' for '{section}.{key}'")]
+    InvalidExpiryDate {
+        section: String,
+        key: String,
+        value: String,
+    },
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// Which layer of the configuration a value belongs to, from lowest to highest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Scope {
+    System,
+    Global,
+    Local,
+    Worktree,
+    Command,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::System => "system",
+            Scope::Global => "global",
+            Scope::Local => "local",
+            Scope::Worktree => "worktree",
+            Scope::Command => "command",
+        })
+    }
+}
+
+/// Where a value was defined, for `--show-origin`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Origin {
+    pub(crate) scope: Scope,
+    /// `None` for values given with `-c` or through the environment.
+    pub(crate) file: Option<PathBuf>,
+    pub(crate) line: Option<usize>,
+}
+
+impl fmt::Display for Origin {
+    /// The format git uses: `file:<path>` or `command line:`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "file:{}", file.display()),
+            None => write!(f, "command line:"),
+        }
+    }
+}
+
+/// One `key = value` line.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ConfigEntry {
+    /// Lowercased, as section names are case-insensitive.
+    pub(crate) section: String,
+    /// Case-sensitive, unless written with the legacy `[section.subsection]` syntax.
+    pub(crate) subsection: Option<String>,
+    /// Lowercased, as variable names are case-insensitive.
+    pub(crate) name: String,
+    /// `None` for a bare `key` without `=`, which means boolean true.
+    pub(crate) value: Option<String>,
+    pub(crate) origin: Origin,
+}
+
+impl ConfigEntry {
+    /// The canonical `section[.subsection].name` form.
+    pub(crate) fn key(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("{}.{subsection}.{}", self.section, self.name),
+            None => format!("{}.{}", self.section, self.name),
+        }
+    }
+
+    fn matches(&self, key: &Key) -> bool {
+        self.section == key.section && self.subsection == key.subsection && self.name == key.name
+    }
+}
+
+/// A `section[.subsection].name` key split into its parts.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Key {
+    pub(crate) section: String,
+    pub(crate) subsection: Option<String>,
+    pub(crate) name: String,
+}
+
+impl Key {
+    /// Splits on the first and last dot, like git: the subsection may contain dots.
+    pub(crate) fn parse(key: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidKey(key.to_owned());
+        let (section, rest) = key.split_once('.').ok_or_else(invalid)?;
+        let (subsection, name) = match rest.rsplit_once('.') {
+            Some((subsection, name)) => (Some(subsection.to_owned()), name),
+            None => (None, rest),
+        };
+        if !is_valid_section(section) || !is_valid_name(name) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            section: section.to_ascii_lowercase(),
+            subsection,
+            name: name.to_ascii_lowercase(),
+        })
+    }
+}
+
+fn is_valid_section(section: &str) -> bool {
+    !section.is_empty()
+        && section
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// How deeply `include.path` may nest, as in git.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Every value from every configuration layer, in the order they were read.
+///
+/// Later values override earlier ones for single-valued lookups, and all of
+/// them are kept for multi-valued keys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ConfigSet {
+    entries: Vec<ConfigEntry>,
+    /// What `includeIf` conditions are evaluated against, when the set was
+    /// loaded for a repository.
+    includes: Option<IncludeContext>,
+}
+
+/// What `includeIf` conditions are evaluated against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct IncludeContext {
+    /// For `gitdir:` and `gitdir/i:`.
+    pub(crate) gitdir: Option<PathBuf>,
+    /// The checked-out branch without `refs/heads/`, for `onbranch:`.
+    pub(crate) branch: Option<String>,
+    /// Every `remote.*.url`, for `hasconfig:remote.*.url:`.
+    pub(crate) remote_urls: Vec<String>,
+}
+
+impl ConfigSet {
+    /// Loads every layer in `sources`, skipping files that do not exist, and
+    /// following `include` and `includeIf` if `includes` is set.
+    pub(crate) fn load_sources(sources: &ConfigSources, includes: bool) -> Result<Self, Error> {
+        let set = Self::load_layers(sources, sources.includes.clone(), includes)?;
+        if !includes {
+            return Ok(set);
+        }
+
+        // `hasconfig:` depends on the URLs in the rest of the configuration,
+        // so read everything again once they are known
+        let remote_urls: Vec<String> = set
+            .entries
+            .iter()
+            .filter(|entry| entry.section == "remote" && entry.name == "url")
+            .filter_map(|entry| entry.value.clone())
+            .collect();
+        if remote_urls.is_empty() {
+            return Ok(set);
+        }
+        let includes = IncludeContext {
+            remote_urls,
+            ..sources.includes.clone()
+        };
+        Self::load_layers(sources, includes, true)
+    }
+
+    fn load_layers(
+        sources: &ConfigSources,
+        context: IncludeContext,
+        includes: bool,
+    ) -> Result<Self, Error> {
+        let mut set = Self {
+            entries: Vec::new(),
+            includes: Some(context),
+        };
+        let files = sources
+            .system
+            .iter()
+            .map(|file| (Scope::System, file))
+            .chain(sources.global.iter().map(|file| (Scope::Global, file)))
+            .chain(sources.local.iter().map(|file| (Scope::Local, file)))
+            .chain(sources.worktree.iter().map(|file| (Scope::Worktree, file)));
+        for (scope, file) in files {
+            set.add_file(file, scope, false, includes)?;
+        }
+        for (key, value) in &sources.command_line {
+            set.add_pair(key, value.clone())?;
+        }
+        Ok(set)
+    }
+
+    /// Reads the entries of `file` into the `scope` layer, and if `includes`
+    /// is set, those of the files it includes.
+    pub(crate) fn add_file(
+        &mut self,
+        file: &Path,
+        scope: Scope,
+        required: bool,
+        includes: bool,
+    ) -> Result<(), Error> {
+        if required && !file.exists() {
+            return Err(Error::FileMissing(file.to_owned()));
+        }
+        // without a repository, no `includeIf` condition holds
+        let context = includes.then(|| self.includes.clone().unwrap_or_default());
+        self.add_included(file, scope, context.as_ref(), &mut Vec::new())
+    }
+
+    /// Reads `file`, splicing in the files it includes where they are included.
+    ///
+    /// `stack` holds the files currently being read, to detect cycles.
+    fn add_included(
+        &mut self,
+        file: &Path,
+        scope: Scope,
+        context: Option<&IncludeContext>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), Error> {
+        let Some(config) = ConfigFile::read(file)? else {
+            // like git, includes of missing files are ignored
+            return Ok(());
+        };
+        if stack.len() > MAX_INCLUDE_DEPTH {
+            return Err(Error::IncludeDepth(file.to_owned()));
+        }
+        let canonical = file
+            .canonicalize()
+            .map_err(|err| Error::Io(err.to_string()))?;
+        if stack.contains(&canonical) {
+            return Err(Error::IncludeCycle(file.to_owned()));
+        }
+
+        stack.push(canonical);
+        for entry in config.entries(scope) {
+            let include = Self::include_path(context, &entry, file)?;
+            self.entries.push(entry);
+            if let Some(include) = include {
+                self.add_included(&include, scope, context, stack)?;
+            }
+        }
+        stack.pop();
+        Ok(())
+    }
+
+    /// The file that `entry` includes, if it is an include whose condition
+    /// holds in `context`; without one, nothing is included.
+    fn include_path(
+        context: Option<&IncludeContext>,
+        entry: &ConfigEntry,
+        file: &Path,
+    ) -> Result<Option<PathBuf>, Error> {
+        let Some(includes) = context else {
+            return Ok(None);
+        };
+        let applies = match (entry.section.as_str(), &entry.subsection) {
+            ("include", None) => true,
+            ("includeif", Some(condition)) => includes.matches(condition, file),
+            _ => false,
+        };
+        if !applies || entry.name != "path" {
+            return Ok(None);
+        }
+
+        let missing = || Error::Missing {
+            section: entry.section.clone(),
+            key: entry.name.clone(),
+        };
+        let value = entry.value.as_deref().ok_or_else(missing)?;
+        let path = parse_path(value).ok_or_else(|| Error::InvalidPath {
+            section: entry.section.clone(),
+            key: entry.name.clone(),
+            value: value.to_owned(),
+        })?;
+        // relative includes are relative to the including file
+        Ok(Some(match file.parent() {
+            Some(dir) if path.is_relative() => dir.join(path),
+            _ => path,
+        }))
+    }
+
+    /// Adds a `-c key[=value]` style override.
+    pub(crate) fn add_pair(&mut self, key: &str, value: Option<String>) -> Result<(), Error> {
+        let key = Key::parse(key)?;
+        self.entries.push(ConfigEntry {
+            section: key.section,
+            subsection: key.subsection,
+            name: key.name,
+            value,
+            origin: Origin {
+                scope: Scope::Command,
+                file: None,
+                line: None,
+            },
+        });
+        Ok(())
+    }
+
+    pub(crate) fn entries(&self) -> &[ConfigEntry] {
+        &self.entries
+    }
+
+    /// The value that wins for `key`: the last one read.
+    pub(crate) fn get(&self, key: &str) -> Option<&ConfigEntry> {
+        let key = Key::parse(key).ok()?;
+        self.entries.iter().rev().find(|entry| entry.matches(&key))
+    }
+
+    /// All values for a multi-valued `key`, in the order they were read.
+    pub(crate) fn get_all(&self, key: &str) -> Vec<&ConfigEntry> {
+        let Ok(key) = Key::parse(key) else {
+            return Vec::new();
+        };
+        self.entries
+            .iter()
+            .filter(|entry| entry.matches(&key))
+            .collect()
+    }
+}
+
+impl Config for ConfigSet {
+    type Error = Error;
+
+    fn load<P>(path: P) -> Result<Self, Self::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut set = Self::default();
+        set.add_file(path.as_ref(), Scope::Local, true, false)?;
+        Ok(set)
+    }
+
+    fn get_value(&self, section: &str, key: &str) -> Option<Option<&str>> {
+        self.get(&format!("{section}.{key}"))
+            .map(|entry| entry.value.as_deref())
+    }
+
+    fn section_keys(&self, section: &str) -> Vec<String> {
+        section_keys(&self.entries, section)
+    }
+}
+
+/// The distinct names of the keys in `section`, in the order they first appear.
+pub(crate) fn section_keys(entries: &[ConfigEntry], section: &str) -> Vec<String> {
+    let (section, subsection) = match section.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection)),
+        None => (section, None),
+    };
+    let mut keys: Vec<String> = Vec::new();
+    for entry in entries {
+        if entry.section.eq_ignore_ascii_case(section)
+            && entry.subsection.as_deref() == subsection
+            && !keys.contains(&entry.name)
+        {
+            keys.push(entry.name.clone());
+        }
+    }
+    keys
+}
+
+/// The files and overrides that make up a repository's configuration.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ConfigSources {
+    /// `/etc/gitconfig`, unless `GIT_CONFIG_NOSYSTEM` is set.
+    pub(crate) system: Option<PathBuf>,
+    /// `$XDG_CONFIG_HOME/git/config` and `~/.gitconfig`, in that order.
+    pub(crate) global: Vec<PathBuf>,
+    /// `config` in the commondir.
+    pub(crate) local: Option<PathBuf>,
+    /// `config.worktree` in the gitdir, when `extensions.worktreeConfig` is set.
+    pub(crate) worktree: Option<PathBuf>,
+    /// `GIT_CONFIG_COUNT` pairs followed by `-c` options.
+    pub(crate) command_line: Vec<(String, Option<String>)>,
+    /// What `includeIf` conditions are evaluated against.
+    pub(crate) includes: IncludeContext,
+}
+
+impl ConfigSources {
+    /// The system, global and command-line layers, as git finds them.
+    pub(crate) fn from_env() -> Result<Self, Error> {
+        let system = match env::var_os("GIT_CONFIG_NOSYSTEM") {
+            Some(_) => None,
+            None => Some(
+                env::var_os("GIT_CONFIG_SYSTEM")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| PathBuf::from("/etc/gitconfig")),
+            ),
+        };
+
+        let global = match env::var_os("GIT_CONFIG_GLOBAL") {
+            Some(file) => vec![PathBuf::from(file)],
+            None => {
+                let home = env::var_os("HOME").map(PathBuf::from);
+                let xdg = env::var_os("XDG_CONFIG_HOME")
+                    .map(PathBuf::from)
+                    .filter(|dir| !dir.as_os_str().is_empty())
+                    .or_else(|| home.as_ref().map(|home| home.join(".config")));
+                xdg.map(|dir| dir.join("git/config"))
+                    .into_iter()
+                    .chain(home.map(|home| home.join(".gitconfig")))
+                    .collect()
+            }
+        };
+
+        let mut command_line = env_pairs(|name| env::var(name).ok())?;
+        if let Ok(parameters) = env::var("GIT_CONFIG_PARAMETERS") {
+            command_line.extend(parse_parameters(&parameters)?);
+        }
+
+        Ok(Self {
+            system,
+            global,
+            local: None,
+            worktree: None,
+            command_line,
+            includes: IncludeContext::default(),
+        })
+    }
+}
+
+impl IncludeContext {
+    /// Evaluates an `includeIf` condition; unknown conditions are false.
+    fn matches(&self, condition: &str, file: &Path) -> bool {
+        if let Some(pattern) = condition.strip_prefix("gitdir:") {
+            self.gitdir_matches(pattern, file, false)
+        } else if let Some(pattern) = condition.strip_prefix("gitdir/i:") {
+            self.gitdir_matches(pattern, file, true)
+        } else if let Some(pattern) = condition.strip_prefix("onbranch:") {
+            let pattern = with_trailing_glob(pattern.to_owned());
+            self.branch
+                .as_ref()
+                .is_some_and(|branch| wildmatch(&pattern, branch, false))
+        } else if let Some(pattern) = condition.strip_prefix("hasconfig:remote.*.url:") {
+            self.remote_urls
+                .iter()
+                .any(|url| wildmatch(pattern, url, false))
+        } else {
+            false
+        }
+    }
+
+    fn gitdir_matches(&self, pattern: &str, file: &Path, case_insensitive: bool) -> bool {
+        let Some(gitdir) = &self.gitdir else {
+            return false;
+        };
+        let pattern = if let Some(rest) = pattern.strip_prefix("./") {
+            match file.parent() {
+                Some(dir) => format!("{}/{rest}", dir.display()),
+                None => return false,
+            }
+        } else if pattern.starts_with('~') {
+            match parse_path(pattern) {
+                Some(path) => path.display().to_string(),
+                None => return false,
+            }
+        } else if pattern.starts_with('/') || pattern.starts_with("**/") {
+            pattern.to_owned()
+        } else {
+            format!("**/{pattern}")
+        };
+        let pattern = with_trailing_glob(pattern);
+
+        // like git, try both the path as given and with symlinks resolved
+        let matches =
+            |gitdir: &Path| wildmatch(&pattern, &gitdir.display().to_string(), case_insensitive);
+        matches(gitdir) || gitdir.canonicalize().is_ok_and(|real| matches(&real))
+    }
+}
+
+/// A pattern ending in `/` matches everything below that directory.
+fn with_trailing_glob(mut pattern: String) -> String {
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+    pattern
+}
+
+/// Reads the `GIT_CONFIG_COUNT` / `GIT_CONFIG_KEY_<n>` / `GIT_CONFIG_VALUE_<n>` protocol.
+fn env_pairs<F>(var: F) -> Result<Vec<(String, Option<String>)>, Error>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let Some(count) = var("GIT_CONFIG_COUNT").filter(|count| !count.is_empty()) else {
+        return Ok(Vec::new());
+    };
+    let count: usize = count
+        .parse()
+        .map_err(|_| Error::InvalidCount(count.clone()))?;
+
+    (0..count)
+        .map(|i| {
+            let key_var = format!("GIT_CONFIG_KEY_{i}");
+            let value_var = format!("GIT_CONFIG_VALUE_{i}");
+            let key = var(&key_var).ok_or(Error::MissingEnvKey(key_var))?;
+            let value = var(&value_var).ok_or(Error::MissingEnvValue(value_var))?;
+            Ok((key, Some(value)))
+        })
+        .collect()
+}
+
+/// Quotes `-c` options for `GIT_CONFIG_PARAMETERS` the way git does: `'key'='value'`.
+pub(crate) fn format_parameter(key: &str, value: Option<&str>) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', r"'\''"));
+    match value {
+        Some(value) => format!("{}={}", quote(key), quote(value)),
+        None => quote(key),
+    }
+}
+
+/// Parses `GIT_CONFIG_PARAMETERS`, accepting both `'key'='value'` and the older `'key=value'`.
+fn parse_parameters(parameters: &str) -> Result<Vec<(String, Option<String>)>, Error> {
+    let mut chars = parameters.chars().peekable();
+    let mut pairs = Vec::new();
+    loop {
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(pairs);
+        }
+        let key = single_quoted(&mut chars)?;
+        if chars.next_if_eq(&'=').is_some() {
+            pairs.push((key, Some(single_quoted(&mut chars)?)));
+        } else {
+            match key.split_once('=') {
+                Some((key, value)) => pairs.push((key.to_owned(), Some(value.to_owned()))),
+                None => pairs.push((key, None)),
+            }
+        }
+    }
+}
+
+/// Reads one shell-quoted word, where `'\''` stands for a quote inside it.
+fn single_quoted(chars: &mut Peekable<Chars<'_>>) -> Result<String, Error> {
+    let mut word = String::new();
+    loop {
+        if chars.next() != Some('\'') {
+            return Err(Error::BogusParameters);
+        }
+        loop {
+            match chars.next() {
+                Some('\'') => break,
+                Some(c) => word.push(c),
+                None => return Err(Error::BogusParameters),
+            }
+        }
+        if chars.next_if_eq(&'\\').is_none() {
+            return Ok(word);
+        }
+        word.push(chars.next().ok_or(Error::BogusParameters)?);
+    }
+}
+
+/// Parses a boolean the way git does: `true/yes/on`, `false/no/off`, the empty
+/// string, or any integer.
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Some(true),
+        "false" | "no" | "off" | "" => Some(false),
+        _ => parse_int(value).map(|value| value != 0),
+    }
+}
+
+/// Parses an integer with an optional `k`, `m` or `g` suffix.
+pub(crate) fn parse_int(value: &str) -> Option<i64> {
+    let (digits, factor) = match value.char_indices().last()? {
+        (i, 'k' | 'K') => (&value[..i], 1 << 10),
+        (i, 'm' | 'M') => (&value[..i], 1 << 20),
+        (i, 'g' | 'G') => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits.trim_start().parse::<i64>().ok()?.checked_mul(factor)
+}
+
+/// Expands a leading `~/` to `$HOME` and `%(prefix)/` to the installation prefix.
+pub(crate) fn parse_path(value: &str) -> Option<PathBuf> {
+    if value == "~" || value.starts_with("~/") {
+        let home = env::var_os("HOME")?;
+        return Some(PathBuf::from(home).join(value[1..].trim_start_matches('/')));
+    }
+    if let Some(rest) = value.strip_prefix("%(prefix)/") {
+        // the executable lives in `<prefix>/bin`
+        let exe = env::current_exe().ok()?;
+        return Some(exe.parent()?.parent()?.join(rest));
+    }
+    // `~user/` needs the password database, which is not supported
+    (!value.starts_with('~')).then(|| PathBuf::from(value))
+}
+
+/// Parses an expiry such as `2.weeks.ago`, `90 days`, `2024-01-31` or `never`.
+///
+/// Anything older than the returned timestamp has expired, so `never` is 0 and
+/// `now` (or `all`) is `u64::MAX`.
+pub(crate) fn parse_expiry_date(value: &str, now: u64) -> Option<u64> {
+    match value {
+        "never" | "false" => return Some(0),
+        "now" | "all" => return Some(u64::MAX),
+        _ => {}
+    }
+    if let Some(timestamp) = parse_absolute_date(value) {
+        return Some(timestamp);
+    }
+
+    // `<n>.<unit>` pairs, optionally followed by `ago`
+    let mut words = value.split(['.', ' ']).filter(|word| !word.is_empty());
+    let mut offset: u64 = 0;
+    let mut any = false;
+    while let Some(word) = words.next() {
+        if word == "ago" && any {
+            return words.next().is_none().then(|| now.saturating_sub(offset));
+        }
+        let count: u64 = word.parse().ok()?;
+        let unit = words.next()?;
+        let seconds = match unit.strip_suffix('s').unwrap_or(unit) {
+            "second" | "sec" => 1,
+            "minute" | "min" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            "year" => 365 * 24 * 60 * 60,
+            _ => return None,
+        };
+        offset = offset.checked_add(count.checked_mul(seconds)?)?;
+        any = true;
+    }
+    any.then(|| now.saturating_sub(offset))
+}
+
+/// Parses `YYYY-MM-DD` with an optional ` HH:MM[:SS]`, in UTC.
+fn parse_absolute_date(value: &str) -> Option<u64> {
+    let (date, time) = match value.split_once([' ', 'T']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (value, None),
+    };
+    let fields = |s: &str, sep| -> Option<Vec<u32>> {
+        s.split(sep).map(|field| field.parse().ok()).collect()
+    };
+
+    let [year, month, day] = fields(date, '-')?[..] else {
+        return None;
+    };
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let time = match time {
+        Some(time) => Some(fields(time, ':')?),
+        None => None,
+    };
+    let seconds = match time.as_deref() {
+        None => 0,
+        Some(&[hour, minute]) if hour < 24 && minute < 60 => hour * 3600 + minute * 60,
+        Some(&[hour, minute, second]) if hour < 24 && minute < 60 && second < 61 => {
+            hour * 3600 + minute * 60 + second
+        }
+        Some(_) => return None,
+    };
+
+    let days = days_from_civil(i64::from(year), month, day);
+    u64::try_from(days * 24 * 60 * 60 + i64::from(seconds)).ok()
+}
+
+/// Days since 1970-01-01 in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((i64::from(month) + 9) % 12) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Turns a color such as `bold red` or `#ff0000 ul` into the ANSI escape sequence git uses.
+pub(crate) fn parse_color(value: &str) -> Option<String> {
+    const ATTRIBUTES: [(&str, u32, u32); 7] = [
+        ("bold", 1, 22),
+        ("dim", 2, 22),
+        ("italic", 3, 23),
+        ("ul", 4, 24),
+        ("blink", 5, 25),
+        ("reverse", 7, 27),
+        ("strike", 9, 29),
+    ];
+
+    let mut reset = false;
+    // a bit per SGR code, so that codes come out in ascending order
+    let mut attributes: u64 = 0;
+    let mut colors = Vec::new();
+    for word in value.split_ascii_whitespace() {
+        if let Some(color) = Color::parse(word) {
+            if colors.len() == 2 {
+                return None;
+            }
+            colors.push(color);
+        } else if word == "reset" {
+            reset = true;
+        } else {
+            let (name, negate) = match word.strip_prefix("no") {
+                Some(name) => (name.strip_prefix('-').unwrap_or(name), true),
+                None => (word, false),
+            };
+            let &(_, on, off) = ATTRIBUTES.iter().find(|(attr, _, _)| *attr == name)?;
+            attributes |= 1 << if negate { off } else { on };
+        }
+    }
+
+    let fg = colors.first().and_then(|color| color.sgr(false));
+    let bg = colors.get(1).and_then(|color| color.sgr(true));
+    if !reset && attributes == 0 && fg.is_none() && bg.is_none() {
+        return Some(String::new());
+    }
+
+    // git emits the reset as an empty code
+    let codes: Vec<String> = reset
+        .then(String::new)
+        .into_iter()
+        .chain(
+            (0..64)
+                .filter(|bit| attributes & (1 << bit) != 0)
+                .map(|bit: u32| bit.to_string()),
+        )
+        .chain(fg)
+        .chain(bg)
+        .collect();
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// One color word of a color value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Color {
+    Normal,
+    Default,
+    Ansi(u8),
+    Bright(u8),
+    Ansi256(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    fn parse(word: &str) -> Option<Self> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+
+        let lower = word.to_ascii_lowercase();
+        match lower.as_str() {
+            "normal" => return Some(Color::Normal),
+            "default" => return Some(Color::Default),
+            _ => {}
+        }
+        let (name, bright) = match lower.strip_prefix("bright") {
+            Some(name) => (name, true),
+            None => (lower.as_str(), false),
+        };
+        if let Some(i) = NAMES.iter().position(|color| *color == name) {
+            let i = i as u8;
+            return Some(if bright {
+                Color::Bright(i)
+            } else {
+                Color::Ansi(i)
+            });
+        }
+        if let Some(hex) = word.strip_prefix('#') {
+            if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked hex");
+            return Some(Color::Rgb(channel(0), channel(2), channel(4)));
+        }
+        match word.parse::<i32>().ok()? {
+            -1 => Some(Color::Normal),
+            n @ 0..=7 => Some(Color::Ansi(n as u8)),
+            n @ 8..=15 => Some(Color::Bright(n as u8 - 8)),
+            n @ 16..=255 => Some(Color::Ansi256(n as u8)),
+            _ => None,
+        }
+    }
+
+    /// The SGR code selecting this color, or `None` to leave the color alone.
+    fn sgr(self, background: bool) -> Option<String> {
+        let base = if background { 40 } else { 30 };
+        Some(match self {
+            Color::Normal => return None,
+            Color::Default => (base + 9).to_string(),
+            Color::Ansi(i) => (base + u32::from(i)).to_string(),
+            Color::Bright(i) => (base + 60 + u32::from(i)).to_string(),
+            Color::Ansi256(n) => format!("{};5;{n}", base + 8),
+            Color::Rgb(r, g, b) => format!("{};2;{r};{g};{b}", base + 8),
+        })
+    }
+}
+
+/// One piece of a config file, together with the exact text it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+enum Event {
+    /// Blank lines and comments.
+    Other(String),
+    Section {
+        raw: String,
+        section: String,
+        subsection: Option<String>,
+    },
+    Entry {
+        raw: String,
+        name: String,
+        value: Option<String>,
+        line: usize,
+    },
+}
+
+impl Event {
+    fn raw(&self) -> &str {
+        match self {
+            Event::Other(raw) | Event::Section { raw, .. } | Event::Entry { raw, .. } => raw,
+        }
+    }
+
+    fn raw_mut(&mut self) -> &mut String {
+        match self {
+            Event::Other(raw) | Event::Section { raw, .. } | Event::Entry { raw, .. } => raw,
+        }
+    }
+}
+
+/// A single config file, kept verbatim so that edits leave the rest of it untouched.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct ConfigFile {
+    path: Option<PathBuf>,
+    events: Vec<Event>,
+}
+
+impl ConfigFile {
+    /// Reads the file at `path`, or `None` if it does not exist.
+    pub(crate) fn read(path: &Path) -> Result<Option<Self>, Error> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text, Some(path)).map(Some),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::Io(err.to_string())),
+        }
+    }
+
+    /// Parses `text`; `path` is only used to report errors and origins.
+    pub(crate) fn parse(text: &str, path: Option<&Path>) -> Result<Self, Error> {
+        let mut parser = Parser {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+        };
+        let syntax = |line| Error::Syntax {
+            file: path
+                .map(|path| path.display().to_string())
+                .unwrap_or_default(),
+            line,
+        };
+
+        let mut events = Vec::new();
+        let mut in_section = false;
+        loop {
+            let start = parser.pos;
+            parser.skip_whitespace();
+            let event = match parser.peek() {
+                None if start == parser.pos => break,
+                None => Event::Other(String::new()),
+                Some('\n') => {
+                    parser.bump();
+                    Event::Other(String::new())
+                }
+                Some('#' | ';') => {
+                    parser.skip_line();
+                    Event::Other(String::new())
+                }
+                Some('[') => {
+                    parser.bump();
+                    let (section, subsection) =
+                        parser.section_header().ok_or_else(|| syntax(parser.line))?;
+                    // keep the rest of the line with the header, unless an entry follows on it
+                    parser.skip_whitespace();
+                    if matches!(parser.peek(), None | Some('\n' | '#' | ';')) {
+                        parser.skip_line();
+                    }
+                    in_section = true;
+                    Event::Section {
+                        raw: String::new(),
+                        section,
+                        subsection,
+                    }
+                }
+                Some(c) if c.is_ascii_alphabetic() && in_section => {
+                    let line = parser.line;
+                    let name = parser.name();
+                    parser.skip_whitespace();
+                    let value = match parser.peek() {
+                        Some('=') => {
+                            parser.bump();
+                            Some(parser.value().ok_or_else(|| syntax(parser.line))?)
+                        }
+                        None | Some('\n' | '#' | ';') => {
+                            parser.skip_line();
+                            None
+                        }
+                        Some(_) => return Err(syntax(line)),
+                    };
+                    Event::Entry {
+                        raw: String::new(),
+                        name: name.to_ascii_lowercase(),
+                        value,
+                        line,
+                    }
+                }
+                Some(_) => return Err(syntax(parser.line)),
+            };
+            events.push(event);
+            let raw = parser.chars[start..parser.pos].iter().collect();
+            *events.last_mut().expect("just pushed").raw_mut() = raw;
+        }
+
+        Ok(Self {
+            path: path.map(Path::to_owned),
+            events,
+        })
+    }
+
+    /// The file's text, identical to what was parsed apart from edits.
+    pub(crate) fn serialize(&self) -> String {
+        self.events.iter().map(Event::raw).collect()
+    }
+
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Every entry in the file, in order, as belonging to `scope`.
+    pub(crate) fn entries(&self, scope: Scope) -> Vec<ConfigEntry> {
+        let mut section = None;
+        let mut entries = Vec::new();
+        for event in &self.events {
+            match event {
+                Event::Section {
+                    section: name,
+                    subsection,
+                    ..
+                } => section = Some((name, subsection)),
+                Event::Entry {
+                    name, value, line, ..
+                } => {
+                    let (section, subsection) = section.expect("entries follow a section");
+                    entries.push(ConfigEntry {
+                        section: section.clone(),
+                        subsection: subsection.clone(),
+                        name: name.clone(),
+                        value: value.clone(),
+                        origin: Origin {
+                            scope,
+                            file: self.path.clone(),
+                            line: Some(*line),
+                        },
+                    });
+                }
+                Event::Other(_) => {}
+            }
+        }
+        entries
+    }
+
+    /// The last value for `key`, the one that wins.
+    pub(crate) fn get(&self, key: &str) -> Option<Option<&str>> {
+        let key = Key::parse(key).ok()?;
+        let index = *self.positions(&key).entries.last()?;
+        match &self.events[index] {
+            Event::Entry { value, .. } => Some(value.as_deref()),
+            _ => unreachable!("positions only returns entries"),
+        }
+    }
+
+    /// Every value for `key`, in order.
+    pub(crate) fn get_all(&self, key: &str) -> Vec<Option<&str>> {
+        let Ok(key) = Key::parse(key) else {
+            return Vec::new();
+        };
+        self.positions(&key)
+            .entries
+            .into_iter()
+            .map(|index| match &self.events[index] {
+                Event::Entry { value, .. } => value.as_deref(),
+                _ => unreachable!("positions only returns entries"),
+            })
+            .collect()
+    }
+
+    /// Sets the single value of `key`, replacing it in place if it exists.
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let parsed = Key::parse(key)?;
+        match self.positions(&parsed).entries[..] {
+            [] => self.add(key, value),
+            [index] => {
+                let indent: String = self.events[index]
+                    .raw()
+                    .chars()
+                    .take_while(|c| c.is_whitespace())
+                    .collect();
+                self.events[index] = entry_event(&indent, key, value);
+                Ok(())
+            }
+            _ => Err(Error::MultipleValues(key.to_owned())),
+        }
+    }
+
+    /// Adds another value for `key` at the end of its section, creating the section if needed.
+    pub(crate) fn add(&mut self, key: &str, value: &str) -> Result<(), Error> {
+        let parsed = Key::parse(key)?;
+        let event = entry_event("\t", key, value);
+        match self.positions(&parsed).section_end {
+            Some(index) => {
+                terminate_line(&mut self.events[index]);
+                self.events.insert(index + 1, event);
+            }
+            None => {
+                if let Some(last) = self.events.last_mut() {
+                    terminate_line(last);
+                }
+                self.events.push(Event::Section {
+                    raw: format_section(&parsed.section, parsed.subsection.as_deref()),
+                    section: parsed.section,
+                    subsection: parsed.subsection,
+                });
+                self.events.push(event);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every value for `key`, returning how many there were.
+    pub(crate) fn unset_all(&mut self, key: &str) -> Result<usize, Error> {
+        let key = Key::parse(key)?;
+        let positions = self.positions(&key).entries;
+        for &index in positions.iter().rev() {
+            self.events.remove(index);
+        }
+        Ok(positions.len())
+    }
+
+    /// Renames every `[old]` section to `new`, returning how many there were.
+    pub(crate) fn rename_section(&mut self, old: &str, new: &str) -> Result<usize, Error> {
+        let old = parse_section(old)?;
+        let (new_section, new_subsection) = parse_section(new)?;
+        let mut renamed = 0;
+        for event in &mut self.events {
+            if let Event::Section {
+                raw,
+                section,
+                subsection,
+            } = event
+            {
+                if *section == old.0 && *subsection == old.1 {
+                    *raw = format_section(&new_section, new_subsection.as_deref());
+                    *section = new_section.clone();
+                    *subsection = new_subsection.clone();
+                    renamed += 1;
+                }
+            }
+        }
+        Ok(renamed)
+    }
+
+    /// Removes every `[name]` section with everything in it, returning how many there were.
+    pub(crate) fn remove_section(&mut self, name: &str) -> Result<usize, Error> {
+        let name = parse_section(name)?;
+        let mut removed = 0;
+        let mut in_section = false;
+        self.events.retain(|event| {
+            if let Event::Section {
+                section,
+                subsection,
+                ..
+            } = event
+            {
+                in_section = (section, subsection) == (&name.0, &name.1);
+                removed += usize::from(in_section);
+            }
+            !in_section
+        });
+        Ok(removed)
+    }
+
+    /// Finds the entries for `key` and the last event in the last section it could go in.
+    fn positions(&self, key: &Key) -> Positions {
+        let mut positions = Positions::default();
+        let mut in_section = false;
+        for (index, event) in self.events.iter().enumerate() {
+            match event {
+                Event::Section {
+                    section,
+                    subsection,
+                    ..
+                } => {
+                    in_section = *section == key.section && *subsection == key.subsection;
+                    if in_section {
+                        positions.section_end = Some(index);
+                    }
+                }
+                Event::Entry { name, .. } if in_section => {
+                    positions.section_end = Some(index);
+                    if *name == key.name {
+                        positions.entries.push(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        positions
+    }
+}
+
+#[derive(Default)]
+struct Positions {
+    entries: Vec<usize>,
+    section_end: Option<usize>,
+}
+
+/// Writes an entry the way git does, keeping the spelling of the key's variable name.
+fn entry_event(indent: &str, key: &str, value: &str) -> Event {
+    let name = key.rsplit_once('.').map_or(key, |(_, name)| name);
+    let needs_quotes = value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains([';', '#']);
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    if needs_quotes {
+        escaped = format!("\"{escaped}\"");
+    }
+    Event::Entry {
+        raw: format!("{indent}{name} = {escaped}\n"),
+        name: name.to_ascii_lowercase(),
+        value: Some(value.to_owned()),
+        // edited entries only get a line number when the file is read again
+        line: 0,
+    }
+}
+
+fn format_section(section: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(subsection) => {
+            let escaped = subsection.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("[{section} \"{escaped}\"]\n")
+        }
+        None => format!("[{section}]\n"),
+    }
+}
+
+/// Splits `section[.subsection]` as used by `--rename-section` and `--remove-section`.
+fn parse_section(name: &str) -> Result<(String, Option<String>), Error> {
+    let (section, subsection) = match name.split_once('.') {
+        Some((section, subsection)) => (section, Some(subsection.to_owned())),
+        None => (name, None),
+    };
+    if section.is_empty()
+        || !section
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(Error::InvalidSection(name.to_owned()));
+    }
+    Ok((section.to_ascii_lowercase(), subsection))
+}
+
+/// Makes sure text inserted after `event` starts on a new line.
+fn terminate_line(event: &mut Event) {
+    let raw = event.raw_mut();
+    if !raw.is_empty() && !raw.ends_with('\n') {
+        raw.push('\n');
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c != '\n' && c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    /// Skips to the start of the next line.
+    fn skip_line(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        {
+            name.push(c);
+            self.bump();
+        }
+        name
+    }
+
+    /// Parses `section]`, `section "subsection"]` or the legacy `section.subsection]`.
+    fn section_header(&mut self) -> Option<(String, Option<String>)> {
+        let mut name = String::new();
+        while let Some(c) = self
+            .peek()
+            .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '.')
+        {
+            name.push(c);
+            self.bump();
+        }
+        if name.is_empty() {
+            return None;
+        }
+
+        match self.bump()? {
+            ']' => match name.split_once('.') {
+                Some((section, subsection)) => Some((
+                    section.to_ascii_lowercase(),
+                    Some(subsection.to_ascii_lowercase()),
+                )),
+                None => Some((name.to_ascii_lowercase(), None)),
+            },
+            c if c.is_whitespace() && c != '\n' && !name.contains('.') => {
+                self.skip_whitespace();
+                if self.bump()? != '"' {
+                    return None;
+                }
+                let mut subsection = String::new();
+                loop {
+                    match self.bump()? {
+                        '\n' => return None,
+                        '"' => break,
+                        '\\' => subsection.push(self.bump().filter(|c| *c != '\n')?),
+                        c => subsection.push(c),
+                    }
+                }
+                (self.bump()? == ']').then(|| (name.to_ascii_lowercase(), Some(subsection)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parses a value up to the end of its (possibly continued) line.
+    fn value(&mut self) -> Option<String> {
+        self.skip_whitespace();
+        let mut value = String::new();
+        let mut quoted = false;
+        // whitespace outside quotes is only kept when more value follows it
+        let mut pending_space = 0;
+        loop {
+            let c = match self.bump() {
+                None | Some('\n') if !quoted => return Some(value),
+                None | Some('\n') => return None,
+                Some(c) => c,
+            };
+            match c {
+                c if c.is_whitespace() && !quoted => {
+                    pending_space += 1;
+                    continue;
+                }
+                ';' | '#' if !quoted => {
+                    self.skip_line();
+                    return Some(value);
+                }
+                _ => {}
+            }
+            value.extend(std::iter::repeat_n(' ', pending_space));
+            pending_space = 0;
+            match c {
+                '"' => quoted = !quoted,
+                '\\' => match self.bump()? {
+                    '\n' => {}
+                    'n' => value.push('\n'),
+                    't' => value.push('\t'),
+                    'b' => value.push('\u{8}'),
+                    c @ ('\\' | '"') => value.push(c),
+                    _ => return None,
+                },
+                c => value.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn parse(text: &str) -> Result<ConfigFile, Error> {
+        ConfigFile::parse(text, Some(Path::new("config")))
+    }
+
+    #[test]
+    fn parse_git_syntax() {
+        let text = indoc! {r#"
+            # comment
+            [core]
+                bare = false ; inline comment
+                editor = "vim -u \"NONE\"" # quoted
+            [remote "Origin"]
+                url = https://example.com/repo.git
+                fetch = +refs/heads/*:refs/remotes/origin/*
+                fetch = +refs/tags/*:refs/tags/*
+            [branch.Main]
+                rebase
+            [alias]
+                lg = log \
+                    --oneline
+        "#};
+        let file = parse(text).unwrap();
+        assert_eq!(file.serialize(), text);
+        let entries = file.entries(Scope::Local);
+        let pairs: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.key(), entry.value.clone()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("core.bare".to_owned(), Some("false".to_owned())),
+                (
+                    "core.editor".to_owned(),
+                    Some(r#"vim -u "NONE""#.to_owned())
+                ),
+                (
+                    "remote.Origin.url".to_owned(),
+                    Some("https://example.com/repo.git".to_owned())
+                ),
+                (
+                    "remote.Origin.fetch".to_owned(),
+                    Some("+refs/heads/*:refs/remotes/origin/*".to_owned())
+                ),
+                (
+                    "remote.Origin.fetch".to_owned(),
+                    Some("+refs/tags/*:refs/tags/*".to_owned())
+                ),
+                ("branch.main.rebase".to_owned(), None),
+                // like git, indentation after a continuation is part of the value
+                (
+                    "alias.lg".to_owned(),
+                    Some(format!("log{}--oneline", " ".repeat(9)))
+                ),
+            ]
+        );
+        assert_eq!(entries[0].origin.line, Some(3));
+        assert_eq!(entries[6].origin.line, Some(12));
+    }
+
+    #[test]
+    fn syntax_error_names_line() {
+        assert_eq!(
+            parse("[core]\nbare = \"unterminated\n"),
+            Err(Error::Syntax {
+                file: "config".to_owned(),
+                line: 3
+            })
+        );
+        assert_eq!(
+            parse("[core]\n= value\n"),
+            Err(Error::Syntax {
+                file: "config".to_owned(),
+                line: 2
+            })
+        );
+    }
+
+    #[test]
+    fn edits_keep_formatting() {
+        let text = indoc! {"
+            # user settings
+            [user]
+              name = Old ; was here
+              email = old@example.com
+            [remote \"origin\"]
+            \turl = https://example.com
+            [core] bare = false"};
+        let mut file = parse(text).unwrap();
+
+        file.set("user.name", "New Name").unwrap();
+        file.add("remote.origin.fetch", "+refs/*:refs/*").unwrap();
+        file.set("core.editor", "vim # really").unwrap();
+        file.add("branch.Main.remote", "origin").unwrap();
+        assert_eq!(file.unset_all("user.email"), Ok(1));
+
+        assert_eq!(
+            file.serialize(),
+            indoc! {"
+                # user settings
+                [user]
+                  name = New Name
+                [remote \"origin\"]
+                \turl = https://example.com
+                \tfetch = +refs/*:refs/*
+                [core] bare = false
+                \teditor = \"vim # really\"
+                [branch \"Main\"]
+                \tremote = origin
+            "}
+        );
+        let reparsed = parse(&file.serialize()).unwrap();
+        assert_eq!(reparsed.get("core.editor"), Some(Some("vim # really")));
+        assert_eq!(reparsed.get("branch.Main.remote"), Some(Some("origin")));
+        assert_eq!(reparsed.get("branch.main.remote"), None);
+
+        file.add("remote.origin.url", "https://mirror.example.com")
+            .unwrap();
+        assert_eq!(
+            file.set("remote.origin.url", "x"),
+            Err(Error::MultipleValues("remote.origin.url".to_owned()))
+        );
+    }
+
+    #[test]
+    fn sections() {
+        let mut file = parse(indoc! {"
+            [remote \"origin\"]
+            \turl = a
+            # about b
+            [remote \"b\"]
+            \turl = b
+            [core]
+            \tbare = false
+        "})
+        .unwrap();
+
+        assert_eq!(
+            file.rename_section("remote.origin", "remote.up\"stream"),
+            Ok(1)
+        );
+        assert_eq!(file.remove_section("remote.b"), Ok(1));
+        assert_eq!(file.remove_section("remote.b"), Ok(0));
+        assert_eq!(
+            file.serialize(),
+            indoc! {"
+                [remote \"up\\\"stream\"]
+                \turl = a
+                # about b
+                [core]
+                \tbare = false
+            "}
+        );
+        assert_eq!(
+            parse(&file.serialize())
+                .unwrap()
+                .get_all("remote.up\"stream.url"),
+            [Some("a")]
+        );
+        assert!(file.remove_section("bad section").is_err());
+    }
+
+    #[test]
+    fn layers_last_one_wins() {
+        let tempdir = TempDir::new().unwrap();
+        let global = tempdir.path().join("gitconfig");
+        let local = tempdir.path().join("config");
+        fs::write(&global, "[user]\n\tname = Global\n[core]\n\tpager = less\n").unwrap();
+        fs::write(&local, "[user]\n\tname = Local\n[core]\n\tpager = more\n").unwrap();
+
+        let set = ConfigSet::load_sources(
+            &ConfigSources {
+                system: Some(tempdir.path().join("missing")),
+                global: vec![global.clone()],
+                local: Some(local.clone()),
+                worktree: None,
+                command_line: vec![("User.Name".to_owned(), Some("Override".to_owned()))],
+                includes: IncludeContext::default(),
+            },
+            true,
+        )
+        .unwrap();
+
+        let name = set.get("user.name").unwrap();
+        assert_eq!(name.value.as_deref(), Some("Override"));
+        assert_eq!(name.origin.scope, Scope::Command);
+        assert_eq!(name.origin.to_string(), "command line:");
+
+        let pager = set.get("core.pager").unwrap();
+        assert_eq!(pager.value.as_deref(), Some("more"));
+        assert_eq!(pager.origin.file.as_deref(), Some(local.as_path()));
+        assert_eq!(pager.origin.line, Some(4));
+
+        let names: Vec<_> = set
+            .get_all("user.name")
+            .iter()
+            .map(|entry| entry.value.as_deref().unwrap())
+            .collect();
+        assert_eq!(names, ["Global", "Local", "Override"]);
+    }
+
+    #[test]
+    fn includes() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let gitdir = dir.join("work/repo/.git");
+        fs::create_dir_all(&gitdir).unwrap();
+        fs::create_dir(dir.join("conf")).unwrap();
+
+        let local = dir.join("config");
+        fs::write(
+            &local,
+            format!(
+                indoc! {r#"
+                    [user]
+                        name = Before
+                    [include]
+                        path = conf/base
+                    [includeIf "gitdir:{}/work/"]
+                        path = conf/work
+                    [includeIf "gitdir/i:REPO/.GIT"]
+                        path = conf/nocase
+                    [includeIf "gitdir:elsewhere/"]
+                        path = conf/never
+                    [includeIf "onbranch:feature/"]
+                        path = conf/feature
+                    [includeIf "hasconfig:remote.*.url:https://example.com/**"]
+                        path = conf/remote
+                "#},
+                dir.display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("conf/base"),
+            "[user]\n\tname = Base\n[include]\n\tpath = nested\n",
+        )
+        .unwrap();
+        fs::write(dir.join("conf/nested"), "[user]\n\tnested = yes\n").unwrap();
+        fs::write(dir.join("conf/work"), "[user]\n\temail = me@work\n").unwrap();
+        fs::write(dir.join("conf/nocase"), "[user]\n\tnocase = yes\n").unwrap();
+        fs::write(dir.join("conf/never"), "[user]\n\tnever = yes\n").unwrap();
+        fs::write(dir.join("conf/feature"), "[user]\n\tfeature = yes\n").unwrap();
+        fs::write(dir.join("conf/remote"), "[user]\n\tremote = yes\n").unwrap();
+
+        let mut sources = ConfigSources {
+            local: Some(local),
+            command_line: vec![(
+                "remote.origin.url".to_owned(),
+                Some("https://example.com/org/repo".to_owned()),
+            )],
+            includes: IncludeContext {
+                gitdir: Some(gitdir),
+                branch: Some("feature/x".to_owned()),
+                remote_urls: Vec::new(),
+            },
+            ..ConfigSources::default()
+        };
+        let set = ConfigSet::load_sources(&sources, true).unwrap();
+
+        let value = |key| set.get(key).and_then(|entry| entry.value.clone());
+        // the include takes effect where it appears, after `name = Before`
+        assert_eq!(value("user.name").as_deref(), Some("Base"));
+        assert_eq!(
+            set.get("user.nested").unwrap().origin.file.as_deref(),
+            Some(dir.join("conf/nested").as_path())
+        );
+        assert_eq!(value("user.email").as_deref(), Some("me@work"));
+        assert_eq!(value("user.nocase").as_deref(), Some("yes"));
+        assert_eq!(value("user.never"), None);
+        assert_eq!(value("user.feature").as_deref(), Some("yes"));
+        assert_eq!(value("user.remote").as_deref(), Some("yes"));
+
+        let set = ConfigSet::load_sources(&sources, false).unwrap();
+        assert_eq!(
+            set.get("user.name").unwrap().value.as_deref(),
+            Some("Before")
+        );
+
+        // without a context, includes are left alone
+        sources.includes.branch = None;
+        let set = ConfigSet::load_sources(&sources, true).unwrap();
+        assert_eq!(set.get("user.feature"), None);
+        let mut plain = ConfigSet::default();
+        plain
+            .add_file(&dir.join("config"), Scope::Local, true, false)
+            .unwrap();
+        assert_eq!(
+            plain.get("user.name").unwrap().value.as_deref(),
+            Some("Before")
+        );
+        // outside a repository, only unconditional includes apply
+        let mut plain = ConfigSet::default();
+        plain
+            .add_file(&dir.join("config"), Scope::Local, true, true)
+            .unwrap();
+        assert_eq!(
+            plain.get("user.name").unwrap().value.as_deref(),
+            Some("Base")
+        );
+        assert_eq!(plain.get("user.email"), None);
+    }
+
+    #[test]
+    fn include_cycles_and_depth() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        fs::write(dir.join("a"), "[include]\n\tpath = b\n").unwrap();
+        fs::write(dir.join("b"), "[include]\n\tpath = a\n").unwrap();
+        let sources = ConfigSources {
+            local: Some(dir.join("a")),
+            ..ConfigSources::default()
+        };
+        assert_eq!(
+            ConfigSet::load_sources(&sources, true),
+            Err(Error::IncludeCycle(dir.join("a")))
+        );
+
+        for i in 0..=MAX_INCLUDE_DEPTH + 1 {
+            let next = i + 1;
+            fs::write(
+                dir.join(format!("{i}")),
+                format!("[include]\n\tpath = {next}\n"),
+            )
+            .unwrap();
+        }
+        let sources = ConfigSources {
+            local: Some(dir.join("0")),
+            ..ConfigSources::default()
+        };
+        assert!(matches!(
+            ConfigSet::load_sources(&sources, true),
+            Err(Error::IncludeDepth(_))
+        ));
+    }
+
+    #[test]
+    fn env_protocol() {
+        let vars = |name: &str| match name {
+            "GIT_CONFIG_COUNT" => Some("2".to_owned()),
+            "GIT_CONFIG_KEY_0" => Some("user.name".to_owned()),
+            "GIT_CONFIG_VALUE_0" => Some("Env".to_owned()),
+            "GIT_CONFIG_KEY_1" => Some("core.bare".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            env_pairs(vars),
+            Err(Error::MissingEnvValue("GIT_CONFIG_VALUE_1".to_owned()))
+        );
+
+        let vars = |name: &str| match name {
+            "GIT_CONFIG_COUNT" => Some("1".to_owned()),
+            "GIT_CONFIG_KEY_0" => Some("user.name".to_owned()),
+            "GIT_CONFIG_VALUE_0" => Some("Env".to_owned()),
+            _ => None,
+        };
+        assert_eq!(
+            env_pairs(vars),
+            Ok(vec![("user.name".to_owned(), Some("Env".to_owned()))])
+        );
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let parameters = [
+            format_parameter("user.name", Some("O'Brien")),
+            format_parameter("core.bare", None),
+            "'color.ui=auto'".to_owned(),
+        ]
+        .join(" ");
+        assert_eq!(
+            parse_parameters(&parameters),
+            Ok(vec![
+                ("user.name".to_owned(), Some("O'Brien".to_owned())),
+                ("core.bare".to_owned(), None),
+                ("color.ui".to_owned(), Some("auto".to_owned())),
+            ])
+        );
+        assert_eq!(
+            parse_parameters("'unterminated"),
+            Err(Error::BogusParameters)
+        );
+    }
+
+    #[test]
+    fn typed_getters() {
+        let file = parse(indoc! {"
+            [core]
+                bare
+                compression = 0
+                bigFileThreshold = 512k
+                excludesFile = ~/.gitignore
+                editor
+            [color \"diff\"]
+                old = bold red
+                new = \"#00ff00 ul\"
+                plain = normal
+            [gc]
+                pruneExpire = 2.weeks.ago
+                reflogExpire = 2024-01-31 12:00
+                reflogExpireUnreachable = never
+        "})
+        .unwrap();
+
+        assert_eq!(file.get_bool("core", "bare"), Ok(Some(true)));
+        assert_eq!(file.get_bool("core", "compression"), Ok(Some(false)));
+        assert_eq!(file.get_bool("core", "missing"), Ok(None));
+        assert_eq!(
+            file.get_int("core", "bigFileThreshold"),
+            Ok(Some(512 * 1024))
+        );
+        assert_eq!(
+            file.get_int("core", "editor"),
+            Err(Error::Missing {
+                section: "core".to_owned(),
+                key: "editor".to_owned()
+            })
+        );
+        assert_eq!(
+            file.get_bool("color.diff", "old"),
+            Err(Error::InvalidBool {
+                section: "color.diff".to_owned(),
+                key: "old".to_owned(),
+                value: "bold red".to_owned()
+            })
+        );
+
+        let home = PathBuf::from(env::var_os("HOME").unwrap());
+        assert_eq!(
+            file.get_path("core", "excludesFile"),
+            Ok(Some(home.join(".gitignore")))
+        );
+
+        assert_eq!(
+            file.get_color("color.diff", "old"),
+            Ok(Some("\x1b[1;31m".to_owned()))
+        );
+        assert_eq!(
+            file.get_color("color.diff", "new"),
+            Ok(Some("\x1b[4;38;2;0;255;0m".to_owned()))
+        );
+        assert_eq!(
+            file.get_color("color.diff", "plain"),
+            Ok(Some(String::new()))
+        );
+
+        assert_eq!(
+            file.get_expiry_date("gc", "reflogExpire"),
+            Ok(Some(1_706_702_400))
+        );
+        assert_eq!(
+            file.get_expiry_date("gc", "reflogExpireUnreachable"),
+            Ok(Some(0))
+        );
+        assert!(file.get_expiry_date("gc", "pruneExpire").unwrap().unwrap() > 0);
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(parse_int("-3g"), Some(-3 << 30));
+        assert_eq!(parse_int("12x"), None);
+        assert_eq!(parse_int("9999999999g"), None);
+        assert_eq!(parse_bool("Yes"), Some(true));
+        assert_eq!(parse_bool("2"), Some(true));
+        assert_eq!(parse_bool("maybe"), None);
+
+        // expected values are from `git config --get-color`
+        for (value, expected) in [
+            ("reset red", "\x1b[;31m"),
+            ("reset", "\x1b[m"),
+            ("normal red", "\x1b[41m"),
+            ("blue bold red", "\x1b[1;34;41m"),
+            ("brightred", "\x1b[91m"),
+            ("196", "\x1b[38;5;196m"),
+            ("dim no-dim", "\x1b[2;22m"),
+            ("RED", "\x1b[31m"),
+        ] {
+            assert_eq!(parse_color(value).as_deref(), Some(expected), "{value}");
+        }
+        assert_eq!(parse_color("Bold"), None);
+        assert_eq!(parse_color("red green blue"), None);
+
+        let now = 1_700_000_000;
+        assert_eq!(parse_expiry_date("now", now), Some(u64::MAX));
+        assert_eq!(parse_expiry_date("90.days", now), Some(now - 90 * 86400));
+        assert_eq!(
+            parse_expiry_date("1 hour 30 minutes ago", now),
+            Some(now - 5400)
+        );
+        assert_eq!(parse_expiry_date("1970-01-02", now), Some(86400));
+        assert_eq!(parse_expiry_date("2.fortnights.ago", now), None);
+        assert_eq!(parse_expiry_date("ago", now), None);
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            Key::parse("Remote.my.Origin.URL"),
+            Ok(Key {
+                section: "remote".to_owned(),
+                subsection: Some("my.Origin".to_owned()),
+                name: "url".to_owned(),
+            })
+        );
+        assert!(Key::parse("core").is_err());
+        assert!(Key::parse("core.1bare").is_err());
+    }
+}
@@ -0,0 +1,680 @@
+use std::{
+    env,
+    error::Error,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use application::clap;
+use regex::Regex;
+
+use crate::{
+    config::{ConfigEntry, ConfigFile, ConfigSet, ConfigSources, Key, Scope},
+    lockfile::Lockfile,
+    repo::{self, RealRepo},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum ConfigError {
+    #[error("wrong number of arguments")]
+    Usage,
+    #[error("No such section: {0}")]
+    NoSuchSection(String),
+    #[error("{0} has multiple values")]
+    MultipleValues(String),
+    #[error("Not in a git directory")]
+    NotInRepository,
+    #[error("$HOME not set")]
+    NoHome,
+    #[error("System config is disabled by GIT_CONFIG_NOSYSTEM")]
+    NoSystem,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Use the repository config file
+    #[arg(long, group = "scope")]
+    local: bool,
+    /// Use the per-user config file
+    #[arg(long, group = "scope")]
+    global: bool,
+    /// Use the system-wide config file
+    #[arg(long, group = "scope")]
+    system: bool,
+    /// Use the given config file
+    #[arg(short = 'f', long, value_name = "file", group = "scope")]
+    file: Option<PathBuf>,
+
+    /// Get the value for a key
+    #[arg(long, group = "action")]
+    get: bool,
+    /// Get all values for a multi-valued key
+    #[arg(long, group = "action")]
+    get_all: bool,
+    /// Get all keys matching a regex, with their values
+    #[arg(long, group = "action")]
+    get_regexp: bool,
+    /// Set the value for a key, which must have at most one value
+    #[arg(long, group = "action")]
+    set: bool,
+    /// Add a new value for a key without touching existing ones
+    #[arg(long, group = "action")]
+    add: bool,
+    /// Remove the value for a key, which must have exactly one value
+    #[arg(long, group = "action")]
+    unset: bool,
+    /// Remove all values for a key
+    #[arg(long, group = "action")]
+    unset_all: bool,
+    /// List all variables with their values
+    #[arg(short = 'l', long, group = "action")]
+    list: bool,
+    /// Rename a section
+    #[arg(long, group = "action")]
+    rename_section: bool,
+    /// Remove a section and everything in it
+    #[arg(long, group = "action")]
+    remove_section: bool,
+
+    /// Show the file (or `command line:`) each value came from
+    #[arg(long)]
+    show_origin: bool,
+    /// Follow `include` and `includeIf`, which by default only happens when
+    /// reading every layer
+    #[arg(long, overrides_with = "no_includes")]
+    includes: bool,
+    /// Do not follow `include` and `includeIf`
+    #[arg(long, overrides_with = "includes")]
+    no_includes: bool,
+    /// `<name> [<value>]`, or the arguments of the chosen action
+    #[arg(value_name = "args")]
+    args: Vec<String>,
+}
+
+/// What to do, with the arguments it needs.
+#[derive(Debug)]
+enum Action<'a> {
+    Get(&'a str),
+    GetAll(&'a str),
+    GetRegexp(&'a str),
+    Set(&'a str, &'a str),
+    Add(&'a str, &'a str),
+    Unset(&'a str),
+    UnsetAll(&'a str),
+    List,
+    RenameSection(&'a str, &'a str),
+    RemoveSection(&'a str),
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let action = self.action()?;
+        let status = self.perform(action, &mut io::stdout().lock())?;
+        if status != 0 {
+            std::process::exit(status);
+        }
+        Ok(())
+    }
+
+    /// The action the flags choose, which must be given the right number of
+    /// arguments.
+    fn action(&self) -> Result<Action<'_>, ConfigError> {
+        let args: Vec<&str> = self.args.iter().map(String::as_str).collect();
+        Ok(match (self.action_flag(), args.as_slice()) {
+            (None | Some("get"), [name]) => Action::Get(name),
+            (None | Some("set"), [name, value]) => Action::Set(name, value),
+            (Some("get-all"), [name]) => Action::GetAll(name),
+            (Some("get-regexp"), [regex]) => Action::GetRegexp(regex),
+            (Some("add"), [name, value]) => Action::Add(name, value),
+            (Some("unset"), [name]) => Action::Unset(name),
+            (Some("unset-all"), [name]) => Action::UnsetAll(name),
+            (Some("list"), []) => Action::List,
+            (Some("rename-section"), [old, new]) => Action::RenameSection(old, new),
+            (Some("remove-section"), [name]) => Action::RemoveSection(name),
+            _ => return Err(ConfigError::Usage),
+        })
+    }
+
+    /// Carries out `action`, printing to `out`, and returns the exit status:
+    /// like git, 1 when there is nothing to get and 5 when there is nothing
+    /// to unset.
+    fn perform(&self, action: Action<'_>, out: &mut impl Write) -> Result<i32, Box<dyn Error>> {
+        match action {
+            Action::Get(name) => {
+                Key::parse(name)?;
+                let set = self.read()?;
+                let Some(entry) = set.get(name) else {
+                    return Ok(1);
+                };
+                self.print_value(out, entry, false)?;
+            }
+            Action::GetAll(name) => {
+                Key::parse(name)?;
+                let set = self.read()?;
+                let entries = set.get_all(name);
+                if entries.is_empty() {
+                    return Ok(1);
+                }
+                for entry in entries {
+                    self.print_value(out, entry, false)?;
+                }
+            }
+            Action::GetRegexp(regex) => {
+                let regex = Regex::new(regex)?;
+                let set = self.read()?;
+                let mut found = false;
+                for entry in set.entries().iter().filter(|e| regex.is_match(&e.key())) {
+                    self.print_value(out, entry, true)?;
+                    found = true;
+                }
+                if !found {
+                    return Ok(1);
+                }
+            }
+            Action::List => {
+                for entry in self.read()?.entries() {
+                    let origin = self.origin_prefix(entry);
+                    match &entry.value {
+                        Some(value) => writeln!(out, "{origin}{}={value}", entry.key())?,
+                        None => writeln!(out, "{origin}{}", entry.key())?,
+                    }
+                }
+            }
+            Action::Set(name, value) => {
+                self.edit(|file| Ok(file.set(name, value).map(|()| true)?))?;
+            }
+            Action::Add(name, value) => {
+                self.edit(|file| Ok(file.add(name, value).map(|()| true)?))?;
+            }
+            Action::Unset(name) => {
+                let found = self.edit(|file| match file.get_all(name).len() {
+                    0 => Ok(false),
+                    1 => Ok(file.unset_all(name)? > 0),
+                    _ => Err(ConfigError::MultipleValues(name.to_owned()).into()),
+                })?;
+                if !found {
+                    // like git, a missing key is reported through the exit status only
+                    return Ok(5);
+                }
+            }
+            Action::UnsetAll(name) => {
+                if !self.edit(|file| Ok(file.unset_all(name)? > 0))? {
+                    return Ok(5);
+                }
+            }
+            Action::RenameSection(old, new) => {
+                if !self.edit(|file| Ok(file.rename_section(old, new)? > 0))? {
+                    return Err(ConfigError::NoSuchSection(old.to_owned()).into());
+                }
+            }
+            Action::RemoveSection(name) => {
+                if !self.edit(|file| Ok(file.remove_section(name)? > 0))? {
+                    return Err(ConfigError::NoSuchSection(name.to_owned()).into());
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    fn action_flag(&self) -> Option<&'static str> {
+        [
+            (self.get, "get"),
+            (self.get_all, "get-all"),
+            (self.get_regexp, "get-regexp"),
+            (self.set, "set"),
+            (self.add, "add"),
+            (self.unset, "unset"),
+            (self.unset_all, "unset-all"),
+            (self.list, "list"),
+            (self.rename_section, "rename-section"),
+            (self.remove_section, "remove-section"),
+        ]
+        .into_iter()
+        .find_map(|(set, name)| set.then_some(name))
+    }
+
+    /// The configuration to read: the chosen file, or every layer that applies here.
+    fn read(&self) -> Result<ConfigSet, Box<dyn Error>> {
+        // like git, only follow includes out of one chosen file when asked to
+        let single = self.file.is_some() || self.local || self.global || self.system;
+        let includes = self.includes || !single && !self.no_includes;
+        let mut set = ConfigSet::default();
+        if let Some(file) = &self.file {
+            set.add_file(file, Scope::Local, true, includes)?;
+        } else if self.local {
+            set.add_file(&local_path()?, Scope::Local, false, includes)?;
+        } else if self.global {
+            for file in ConfigSources::from_env()?.global {
+                set.add_file(&file, Scope::Global, false, includes)?;
+            }
+        } else if self.system {
+            if let Some(file) = ConfigSources::from_env()?.system {
+                set.add_file(&file, Scope::System, false, includes)?;
+            }
+        } else {
+            // outside a repository, git still reads the system and global files
+            let sources = match RealRepo::discover(&env::current_dir()?) {
+                Ok(repo) => repo.config_sources()?,
+                Err(repo::Error::NotGitRepository(_) | repo::Error::FilesystemBoundary(_)) => {
+                    ConfigSources::from_env()?
+                }
+                Err(err) => return Err(err.into()),
+            };
+            set = ConfigSet::load_sources(&sources, includes)?;
+        }
+        Ok(set)
+    }
+
+    /// Locks the file to write and applies `edit` to its current contents,
+    /// replacing the file if `edit` reports a change.
+    fn edit<F>(&self, edit: F) -> Result<bool, Box<dyn Error>>
+    where
+        F: FnOnce(&mut ConfigFile) -> Result<bool, Box<dyn Error>>,
+    {
+        let path = self.write_path()?;
+        // read under the lock so that concurrent edits are not lost
+        let mut lock = Lockfile::acquire(&path)?;
+        let mut file = ConfigFile::read(lock.path())?.unwrap_or_default();
+        if !edit(&mut file)? {
+            return Ok(false);
+        }
+        lock.write_all(file.serialize().as_bytes())?;
+        lock.commit()?;
+        Ok(true)
+    }
+
+    /// The single file that writes go to; the repository's own config by default.
+    fn write_path(&self) -> Result<PathBuf, Box<dyn Error>> {
+        if let Some(file) = &self.file {
+            return Ok(file.clone());
+        }
+        if self.system {
+            return Ok(ConfigSources::from_env()?
+                .system
+                .ok_or(ConfigError::NoSystem)?);
+        }
+        if self.global {
+            return global_write_path();
+        }
+        local_path()
+    }
+
+    fn print_value(
+        &self,
+        out: &mut impl Write,
+        entry: &ConfigEntry,
+        with_key: bool,
+    ) -> io::Result<()> {
+        let origin = self.origin_prefix(entry);
+        match (with_key, &entry.value) {
+            (true, Some(value)) => writeln!(out, "{origin}{} {value}", entry.key()),
+            (true, None) => writeln!(out, "{origin}{}", entry.key()),
+            (false, value) => writeln!(out, "{origin}{}", value.as_deref().unwrap_or_default()),
+        }
+    }
+
+    fn origin_prefix(&self, entry: &ConfigEntry) -> String {
+        if !self.show_origin {
+            return String::new();
+        }
+        // like git, show files below the current directory relative to it
+        let mut origin = entry.origin.clone();
+        if let (Some(file), Ok(cwd)) = (&mut origin.file, env::current_dir()) {
+            if let Ok(relative) = file.strip_prefix(&cwd) {
+                *file = relative.to_owned();
+            }
+        }
+        format!("{origin}\t")
+    }
+}
+
+fn local_path() -> Result<PathBuf, Box<dyn Error>> {
+    match RealRepo::discover(&env::current_dir()?) {
+        Ok(repo) => Ok(repo.commondir().join("config")),
+        Err(repo::Error::NotGitRepository(_) | repo::Error::FilesystemBoundary(_)) => {
+            Err(ConfigError::NotInRepository.into())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// `GIT_CONFIG_GLOBAL`, or else the global file [`choose_global`] picks.
+fn global_write_path() -> Result<PathBuf, Box<dyn Error>> {
+    if let Some(file) = env::var_os("GIT_CONFIG_GLOBAL") {
+        return Ok(PathBuf::from(file));
+    }
+    Ok(choose_global(&ConfigSources::from_env()?.global)?)
+}
+
+/// Of the XDG file and `~/.gitconfig` in `global`, `~/.gitconfig`, unless
+/// only the XDG file exists, as git decides.
+fn choose_global(global: &[PathBuf]) -> Result<PathBuf, ConfigError> {
+    let home = global
+        .iter()
+        .find(|file| file.ends_with(".gitconfig"))
+        .ok_or(ConfigError::NoHome)?;
+    let xdg = global.iter().find(|file| *file != home);
+    match xdg {
+        Some(xdg) if !home.exists() && xdg.exists() => Ok(xdg.clone()),
+        _ => Ok(home.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// `git config --file <file> [--<flag>] <args>...`
+    fn args(file: &Path, flag: Option<&str>, args: &[&str]) -> Args {
+        let mut parsed = Args {
+            local: false,
+            global: false,
+            system: false,
+            file: Some(file.to_owned()),
+            get: false,
+            get_all: false,
+            get_regexp: false,
+            set: false,
+            add: false,
+            unset: false,
+            unset_all: false,
+            list: false,
+            rename_section: false,
+            remove_section: false,
+            show_origin: false,
+            includes: false,
+            no_includes: false,
+            args: args.iter().map(|&arg| arg.to_owned()).collect(),
+        };
+        match flag {
+            None => {}
+            Some("get") => parsed.get = true,
+            Some("get-all") => parsed.get_all = true,
+            Some("get-regexp") => parsed.get_regexp = true,
+            Some("set") => parsed.set = true,
+            Some("add") => parsed.add = true,
+            Some("unset") => parsed.unset = true,
+            Some("unset-all") => parsed.unset_all = true,
+            Some("list") => parsed.list = true,
+            Some("rename-section") => parsed.rename_section = true,
+            Some("remove-section") => parsed.remove_section = true,
+            Some("show-origin") => parsed.show_origin = true,
+            Some("includes") => parsed.includes = true,
+            Some(flag) => panic!("unknown flag {flag}"),
+        }
+        parsed
+    }
+
+    /// Runs the command, returning its exit status and output.
+    fn config(
+        file: &Path,
+        flag: Option<&str>,
+        rest: &[&str],
+    ) -> Result<(i32, String), Box<dyn Error>> {
+        let args = args(file, flag, rest);
+        let mut out = Vec::new();
+        let status = args.perform(args.action()?, &mut out)?;
+        Ok((status, String::from_utf8(out)?))
+    }
+
+    #[test]
+    fn dispatch() {
+        let file = Path::new("config");
+        let action = |flag, rest: &[&str]| {
+            let args = args(file, flag, rest);
+            args.action().map(|action| format!("{action:?}"))
+        };
+
+        assert_eq!(action(None, &["a.b"]).unwrap(), r#"Get("a.b")"#);
+        assert_eq!(action(None, &["a.b", "v"]).unwrap(), r#"Set("a.b", "v")"#);
+        assert_eq!(action(Some("get"), &["a.b"]).unwrap(), r#"Get("a.b")"#);
+        assert_eq!(
+            action(Some("get-all"), &["a.b"]).unwrap(),
+            r#"GetAll("a.b")"#
+        );
+        assert_eq!(
+            action(Some("get-regexp"), &["^a"]).unwrap(),
+            r#"GetRegexp("^a")"#
+        );
+        assert_eq!(
+            action(Some("set"), &["a.b", "v"]).unwrap(),
+            r#"Set("a.b", "v")"#
+        );
+        assert_eq!(
+            action(Some("add"), &["a.b", "v"]).unwrap(),
+            r#"Add("a.b", "v")"#
+        );
+        assert_eq!(action(Some("unset"), &["a.b"]).unwrap(), r#"Unset("a.b")"#);
+        assert_eq!(
+            action(Some("unset-all"), &["a.b"]).unwrap(),
+            r#"UnsetAll("a.b")"#
+        );
+        assert_eq!(action(Some("list"), &[]).unwrap(), "List");
+        assert_eq!(
+            action(Some("rename-section"), &["a", "b"]).unwrap(),
+            r#"RenameSection("a", "b")"#
+        );
+        assert_eq!(
+            action(Some("remove-section"), &["a"]).unwrap(),
+            r#"RemoveSection("a")"#
+        );
+
+        for (flag, rest) in [
+            (None, &[][..]),
+            (None, &["a.b", "v", "w"]),
+            (Some("get"), &["a.b", "v"]),
+            (Some("set"), &["a.b"]),
+            (Some("get-all"), &[]),
+            (Some("unset"), &["a.b", "v"]),
+            (Some("list"), &["a.b"]),
+            (Some("rename-section"), &["a"]),
+            (Some("remove-section"), &[]),
+        ] {
+            assert!(
+                matches!(action(flag, rest), Err(ConfigError::Usage)),
+                "{flag:?} {rest:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn get_and_set() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config");
+
+        // reading a missing file with --file is an error, as in git
+        assert!(config(&file, None, &["a.b"]).is_err());
+
+        assert_eq!(config(&file, None, &["core.bare", "false"]).unwrap().0, 0);
+        assert_eq!(
+            config(&file, Some("add"), &["remote.o.fetch", "one"])
+                .unwrap()
+                .0,
+            0
+        );
+        assert_eq!(
+            config(&file, Some("add"), &["remote.o.fetch", "two"])
+                .unwrap()
+                .0,
+            0
+        );
+
+        assert_eq!(
+            config(&file, None, &["core.bare"]).unwrap(),
+            (0, "false\n".into())
+        );
+        assert_eq!(
+            config(&file, None, &["core.missing"]).unwrap(),
+            (1, String::new())
+        );
+        assert_eq!(
+            config(&file, Some("get"), &["remote.o.fetch"]).unwrap(),
+            (0, "two\n".into())
+        );
+        assert_eq!(
+            config(&file, Some("get-all"), &["remote.o.fetch"]).unwrap(),
+            (0, "one\ntwo\n".into())
+        );
+        assert_eq!(
+            config(&file, Some("get-all"), &["remote.o.missing"]).unwrap(),
+            (1, String::new())
+        );
+        assert_eq!(
+            config(&file, Some("get-regexp"), &["^remote\\."]).unwrap(),
+            (0, "remote.o.fetch one\nremote.o.fetch two\n".into())
+        );
+        assert_eq!(
+            config(&file, Some("get-regexp"), &["^user\\."]).unwrap(),
+            (1, String::new())
+        );
+        assert_eq!(
+            config(&file, Some("list"), &[]).unwrap(),
+            (
+                0,
+                "core.bare=false\nremote.o.fetch=one\nremote.o.fetch=two\n".into()
+            )
+        );
+
+        // invalid keys are rejected before anything is read
+        assert!(config(&file, None, &["nodot"]).is_err());
+    }
+
+    #[test]
+    fn unset() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config");
+        fs::write(&file, "[a]\n\tone = 1\n\tmany = x\n\tmany = y\n").unwrap();
+
+        assert_eq!(
+            config(&file, Some("unset"), &["a.missing"]).unwrap(),
+            (5, String::new())
+        );
+        assert_eq!(
+            config(&file, Some("unset-all"), &["a.missing"]).unwrap(),
+            (5, String::new())
+        );
+
+        let err = config(&file, Some("unset"), &["a.many"]).unwrap_err();
+        assert!(matches!(
+            err.downcast::<ConfigError>().as_deref(),
+            Ok(ConfigError::MultipleValues(name)) if name == "a.many"
+        ));
+        assert_eq!(
+            config(&file, Some("get-all"), &["a.many"]).unwrap(),
+            (0, "x\ny\n".into())
+        );
+
+        assert_eq!(config(&file, Some("unset"), &["a.one"]).unwrap().0, 0);
+        assert_eq!(config(&file, None, &["a.one"]).unwrap().0, 1);
+        assert_eq!(config(&file, Some("unset-all"), &["a.many"]).unwrap().0, 0);
+        assert_eq!(config(&file, Some("get-all"), &["a.many"]).unwrap().0, 1);
+
+        // the lock is released whichever way an edit ends
+        assert!(!dir.path().join("config.lock").exists());
+    }
+
+    #[test]
+    fn sections() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config");
+        fs::write(&file, "[a]\n\tkey = 1\n[b]\n\tkey = 2\n").unwrap();
+
+        for (flag, rest) in [
+            ("rename-section", &["missing", "c"][..]),
+            ("remove-section", &["missing"]),
+        ] {
+            let err = config(&file, Some(flag), rest).unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast::<ConfigError>().as_deref(),
+                    Ok(ConfigError::NoSuchSection(name)) if name == "missing"
+                ),
+                "{flag}"
+            );
+        }
+
+        assert_eq!(
+            config(&file, Some("rename-section"), &["a", "c"])
+                .unwrap()
+                .0,
+            0
+        );
+        assert_eq!(config(&file, Some("remove-section"), &["b"]).unwrap().0, 0);
+        assert_eq!(
+            config(&file, Some("list"), &[]).unwrap(),
+            (0, "c.key=1\n".into())
+        );
+    }
+
+    #[test]
+    fn show_origin() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config");
+        fs::write(&file, "[a]\n\tb = 1\n\tflag\n").unwrap();
+        let origin = format!("file:{}", file.display());
+
+        assert_eq!(
+            config(&file, Some("show-origin"), &["a.b"]).unwrap(),
+            (0, format!("{origin}\t1\n"))
+        );
+
+        let mut list = args(&file, Some("list"), &[]);
+        list.show_origin = true;
+        let mut out = Vec::new();
+        assert_eq!(list.perform(Action::List, &mut out).unwrap(), 0);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!("{origin}\ta.b=1\n{origin}\ta.flag\n")
+        );
+    }
+
+    #[test]
+    fn includes() {
+        let dir = TempDir::new().unwrap();
+        let file = dir.path().join("config");
+        fs::write(&file, "[include]\n\tpath = other\n").unwrap();
+        fs::write(dir.path().join("other"), "[a]\n\tb = included\n").unwrap();
+
+        // a single file only follows includes with --includes
+        assert_eq!(config(&file, None, &["a.b"]).unwrap().0, 1);
+        assert_eq!(
+            config(&file, Some("includes"), &["a.b"]).unwrap(),
+            (0, "included\n".into())
+        );
+    }
+
+    #[test]
+    fn global_file() {
+        let dir = TempDir::new().unwrap();
+        let xdg = dir.path().join("xdg/git/config");
+        let home = dir.path().join(".gitconfig");
+        let global = [xdg.clone(), home.clone()];
+
+        // neither exists: ~/.gitconfig is created
+        assert_eq!(choose_global(&global).unwrap(), home);
+
+        // only the XDG file exists: it is written instead
+        fs::create_dir_all(xdg.parent().unwrap()).unwrap();
+        fs::write(&xdg, "").unwrap();
+        assert_eq!(choose_global(&global).unwrap(), xdg);
+
+        // both exist: ~/.gitconfig wins
+        fs::write(&home, "").unwrap();
+        assert_eq!(choose_global(&global).unwrap(), home);
+
+        // without HOME there is no ~/.gitconfig to fall back to
+        assert!(matches!(choose_global(&[xdg]), Err(ConfigError::NoHome)));
+        assert!(matches!(choose_global(&[]), Err(ConfigError::NoHome)));
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    env,
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use application::clap;
+
+use crate::{odb::ObjectStore as _, repo::RealRepo, Execute};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Also report packed objects, prunable loose objects and garbage
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let objects = repo.objects();
+        let packs = objects.packs()?;
+        let Scan {
+            count,
+            size,
+            garbage,
+        } = scan(
+            &repo.commondir().join("objects"),
+            objects.algorithm().hex_len(),
+        )?;
+
+        if !self.verbose {
+            println!("{count} objects, {} kilobytes", size / 1024);
+            return Ok(());
+        }
+
+        let mut prune_packable = 0;
+        for id in objects.loose().ids()? {
+            if packs.iter().any(|pack| pack.index().find(&id).is_some()) {
+                prune_packable += 1;
+            }
+        }
+        let mut in_pack = 0;
+        let mut size_pack = 0;
+        for pack in packs {
+            in_pack += pack.index().len();
+            size_pack += fs::metadata(pack.path())?.len();
+            size_pack += fs::metadata(pack.path().with_extension("idx"))?.len();
+        }
+        for (path, _) in &garbage {
+            eprintln!("warning: garbage found: {}", path.display());
+        }
+
+        println!("count: {count}");
+        println!("size: {}", size / 1024);
+        println!("in-pack: {in_pack}");
+        println!("packs: {}", packs.len());
+        println!("size-pack: {}", size_pack / 1024);
+        println!("prune-packable: {prune_packable}");
+        println!("garbage: {}", garbage.len());
+        println!(
+            "size-garbage: {}",
+            garbage.iter().map(|(_, size)| size).sum::<u64>() / 1024
+        );
+        for alternate in objects.alternates()? {
+            println!("alternate: {}", alternate.dir().display());
+        }
+        Ok(())
+    }
+}
+
+/// What [`scan`] finds in an object directory.
+#[derive(Debug, PartialEq)]
+struct Scan {
+    /// How many loose objects there are, and the space they take up.
+    count: usize,
+    size: u64,
+    /// The files that are neither loose objects nor parts of a pack, with
+    /// their sizes.
+    garbage: Vec<(PathBuf, u64)>,
+}
+
+/// Looks through the object directory `dir` for ids `hex_len` digits long.
+fn scan(dir: &Path, hex_len: usize) -> Result<Scan, io::Error> {
+    let (mut count, mut size) = (0, 0);
+    let mut garbage = Vec::new();
+    for byte in 0..=u8::MAX {
+        let fanout = dir.join(format!("{byte:02x}"));
+        for (name, metadata) in files(&fanout)? {
+            if name.len() == hex_len - 2 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+                count += 1;
+                size += disk_usage(&metadata);
+            } else {
+                garbage.push((fanout.join(name), disk_usage(&metadata)));
+            }
+        }
+    }
+
+    let pack_dir = dir.join("pack");
+    for (name, metadata) in files(&pack_dir)? {
+        let path = pack_dir.join(&name);
+        let has = |ext| path.with_extension(ext).is_file();
+        // an index and its pack need each other; the rest only accompany a pack
+        let known = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("pack") => has("idx"),
+            Some("idx") => has("pack"),
+            Some("keep" | "bitmap" | "rev" | "promisor" | "mtimes") => has("pack"),
+            _ => false,
+        };
+        if !known {
+            garbage.push((path, disk_usage(&metadata)));
+        }
+    }
+    Ok(Scan {
+        count,
+        size,
+        garbage,
+    })
+}
+
+/// The regular files directly inside `dir`, which need not exist.
+fn files(dir: &Path) -> Result<Vec<(String, fs::Metadata)>, io::Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            files.push((entry.file_name().to_string_lossy().into_owned(), metadata));
+        }
+    }
+    Ok(files)
+}
+
+/// The space a file takes up on disk, which is what git reports for loose objects.
+#[cfg(unix)]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt as _;
+
+    metadata.blocks() * 512
+}
+
+#[cfg(not(unix))]
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    metadata.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn garbage() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let loose = "ab/cdef0123456789abcdef0123456789abcdef01";
+        let files = [
+            loose,
+            // too short, and not hex
+            "ab/cdef",
+            "ab/xyzw0123456789abcdef0123456789abcdef01",
+            "pack/pack-1.pack",
+            "pack/pack-1.idx",
+            "pack/pack-1.keep",
+            // an index without its pack, and what only goes with a pack
+            "pack/pack-2.idx",
+            "pack/pack-2.keep",
+            "pack/tmp_pack_1234",
+        ];
+        for file in files {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::write(dir.join(file), "x").unwrap();
+        }
+        // directories are not counted at all
+        fs::create_dir(dir.join("cd")).unwrap();
+        fs::create_dir(dir.join("ab/cd")).unwrap();
+
+        let scan = scan(dir, 40).unwrap();
+        assert_eq!(scan.count, 1);
+        assert_eq!(
+            scan.size,
+            disk_usage(&fs::metadata(dir.join(loose)).unwrap())
+        );
+        let mut garbage: Vec<_> = scan
+            .garbage
+            .into_iter()
+            .map(|(path, _)| path.strip_prefix(dir).unwrap().to_str().unwrap().to_owned())
+            .collect();
+        garbage.sort();
+        assert_eq!(
+            garbage,
+            [
+                "ab/cdef",
+                "ab/xyzw0123456789abcdef0123456789abcdef01",
+                "pack/pack-2.idx",
+                "pack/pack-2.keep",
+                "pack/tmp_pack_1234",
+            ]
+        );
+    }
+}
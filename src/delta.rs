@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+/// Rebuilds an object from its delta base, or `None` if the delta is malformed
+/// or does not apply to `base`.
+///
+/// A delta starts with the sizes of its base and result as little-endian
+/// base-128 varints, followed by instructions that either copy a range of the
+/// base or insert literal bytes.
+pub(crate) fn apply(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 0;
+    let base_len = varint(delta, &mut pos)?;
+    let result_len = varint(delta, &mut pos)?;
+    if base_len != base.len() {
+        return None;
+    }
+
+    let mut out = Vec::with_capacity(result_len);
+    while pos < delta.len() {
+        let op = delta[pos];
+        pos += 1;
+        if op & 0x80 != 0 {
+            // bits 0-3 select offset bytes and bits 4-6 size bytes, least significant first
+            let mut offset = 0;
+            let mut size = 0;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(pos)? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (0x10 << i) != 0 {
+                    size |= (*delta.get(pos)? as usize) << (i * 8);
+                    pos += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            out.extend_from_slice(base.get(offset..offset.checked_add(size)?)?);
+        } else if op != 0 {
+            let len = op as usize;
+            out.extend_from_slice(delta.get(pos..pos + len)?);
+            pos += len;
+        } else {
+            // reserved for future use
+            return None;
+        }
+    }
+
+    (out.len() == result_len).then_some(out)
+}
+
+/// The size of the object a delta produces, without applying it.
+pub(crate) fn result_size(delta: &[u8]) -> Option<usize> {
+    let mut pos = 0;
+    varint(delta, &mut pos)?;
+    varint(delta, &mut pos)
+}
+
+/// Encodes `target` as a delta against `base`.
+///
+/// Every aligned block of the base is indexed, and matches found while sliding
+/// over the target are grown in both directions before they become copies.
+pub(crate) fn create(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, base.len());
+    write_varint(&mut out, target.len());
+
+    // copy offsets are limited to 32 bits
+    let addressable = base.len().min(u32::MAX as usize);
+    let mut blocks = HashMap::new();
+    for start in (0..addressable.saturating_sub(BLOCK - 1)).step_by(BLOCK) {
+        blocks.entry(&base[start..start + BLOCK]).or_insert(start);
+    }
+
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while pos + BLOCK <= target.len() {
+        let Some(&start) = blocks.get(&target[pos..pos + BLOCK]) else {
+            pos += 1;
+            continue;
+        };
+        let back = (1..=(pos - literal_start).min(start))
+            .take_while(|&back| base[start - back] == target[pos - back])
+            .count();
+        let (base_start, target_start) = (start - back, pos - back);
+        let len = base[base_start..addressable]
+            .iter()
+            .zip(&target[target_start..])
+            .take_while(|(a, b)| a == b)
+            .count();
+        insert(&mut out, &target[literal_start..target_start]);
+        copy(&mut out, base_start, len);
+        pos = target_start + len;
+        literal_start = pos;
+    }
+    insert(&mut out, &target[literal_start..]);
+    out
+}
+
+/// The granularity at which matches are found.
+const BLOCK: usize = 16;
+/// The longest copy a single instruction describes portably.
+const MAX_COPY: usize = 0x10000;
+/// The longest literal a single instruction can carry.
+const MAX_INSERT: usize = 0x7f;
+
+fn copy(out: &mut Vec<u8>, mut offset: usize, mut len: usize) {
+    while len > 0 {
+        let size = len.min(MAX_COPY);
+        let mut op = 0x80;
+        let mut args = Vec::new();
+        for i in 0..4 {
+            let byte = (offset >> (i * 8)) as u8;
+            if byte != 0 {
+                op |= 1 << i;
+                args.push(byte);
+            }
+        }
+        // a size of 0x10000 is written as no size bytes at all
+        for i in 0..3 {
+            let byte = (size >> (i * 8)) as u8;
+            if byte != 0 {
+                op |= 0x10 << i;
+                args.push(byte);
+            }
+        }
+        out.push(op);
+        out.extend_from_slice(&args);
+        offset += size;
+        len -= size;
+    }
+}
+
+fn insert(out: &mut Vec<u8>, data: &[u8]) {
+    for chunk in data.chunks(MAX_INSERT) {
+        out.push(chunk.len() as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(0x80 | (value & 0x7f) as u8);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn varint(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize).checked_shl(shift)?;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_and_insert() {
+        let base = b"hello world\n";
+        // sizes 12 and 18, copy 6 bytes from 0, insert "there", copy 7 bytes from 5
+        let delta = [12, 18, 0x90, 6, 5, b't', b'h', b'e', b'r', b'e', 0x91, 5, 7];
+        assert_eq!(apply(base, &delta).unwrap(), b"hello there world\n");
+        assert_eq!(result_size(&delta), Some(18));
+
+        // the base has the wrong size
+        assert_eq!(apply(b"hello", &delta), None);
+        // a copy past the end of the base
+        assert_eq!(apply(base, &[12, 20, 0x91, 5, 20]), None);
+        // the reserved instruction
+        assert_eq!(apply(base, &[12, 0, 0]), None);
+    }
+
+    #[test]
+    fn round_trip() {
+        let base: Vec<u8> = (0..200_000u32).flat_map(|n| n.to_le_bytes()).collect();
+        let mut target = base.clone();
+        target.splice(1000..1000, b"inserted".iter().copied());
+        target.drain(500_000..500_100);
+        target.extend_from_slice(&[7; 300]);
+
+        let delta = create(&base, &target);
+        assert!(delta.len() < 1000, "{}", delta.len());
+        assert_eq!(apply(&base, &delta).unwrap(), target);
+        assert_eq!(apply(b"", &create(b"", b"abc")).unwrap(), b"abc");
+        assert_eq!(apply(b"abc", &create(b"abc", b"")).unwrap(), b"");
+    }
+}
@@ -0,0 +1,721 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    error::Error,
+    fmt, iter,
+};
+
+use application::clap;
+
+use crate::{
+    object::{Commit, GitObject, HashAlgorithm, ObjectId, ObjectType, Tag},
+    odb::{self, ObjectDatabase, ObjectStore as _, RawObject},
+    reachable::{self, Reached},
+    repo::RealRepo,
+    tree::{FileMode, Tree},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum FsckError {
+    #[error("errors found: {0}")]
+    Failed(usize),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Report every object nothing reaches, not just the dangling ones
+    #[arg(long)]
+    unreachable: bool,
+    /// Do not report dangling objects
+    #[arg(long)]
+    no_dangling: bool,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let roots = reachable::roots(&repo)?;
+        let findings = fsck(&repo.objects(), &roots, self.unreachable)?;
+
+        let mut errors = 0;
+        for finding in findings {
+            if self.no_dangling && matches!(finding, Finding::Dangling(..)) {
+                continue;
+            }
+            errors += usize::from(finding.is_error());
+            println!("{finding}");
+        }
+        if errors > 0 {
+            return Err(FsckError::Failed(errors).into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// One thing wrong with an object, named by git's fsck message id where git has one.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Problem {
+    pub(crate) severity: Severity,
+    pub(crate) check: &'static str,
+    pub(crate) message: String,
+}
+
+impl Problem {
+    fn error(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            check,
+            message: message.into(),
+        }
+    }
+
+    fn warning(check: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            check,
+            message: message.into(),
+        }
+    }
+}
+
+/// A line of `fsck` output.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Finding {
+    /// A problem with an object or pack, such as `tree <id>` or `pack <path>`.
+    Bad {
+        subject: String,
+        problem: Problem,
+    },
+    /// Reachable but absent; the type is what the referring object expects.
+    Missing(Option<ObjectType>, ObjectId),
+    /// Unreachable, and not referred to by any other object.
+    Dangling(ObjectType, ObjectId),
+    Unreachable(ObjectType, ObjectId),
+}
+
+impl Finding {
+    pub(crate) fn is_error(&self) -> bool {
+        match self {
+            Finding::Bad { problem, .. } => problem.severity == Severity::Error,
+            Finding::Missing(..) => true,
+            Finding::Dangling(..) | Finding::Unreachable(..) => false,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Finding::Bad { subject, problem } => write!(
+                f,
+                "{} in {subject}: {}: {}",
+                problem.severity, problem.check, problem.message
+            ),
+            Finding::Missing(Some(kind), id) => write!(f, "missing {kind} {id}"),
+            Finding::Missing(None, id) => write!(f, "missing object {id}"),
+            Finding::Dangling(kind, id) => write!(f, "dangling {kind} {id}"),
+            Finding::Unreachable(kind, id) => write!(f, "unreachable {kind} {id}"),
+        }
+    }
+}
+
+/// Checks every copy of every object, then which of them `roots` reach.
+///
+/// Problems with packs and then individual objects come first, in the order the
+/// objects are stored, followed by missing and then dangling or unreachable objects by id.
+pub(crate) fn fsck(
+    objects: &ObjectDatabase,
+    roots: &[Reached],
+    unreachable: bool,
+) -> Result<Vec<Finding>, odb::Error> {
+    let algorithm = objects.algorithm();
+    // objects in alternates are checked too, though they are only borrowed
+    let databases: Vec<_> = iter::once(objects).chain(objects.alternates()?).collect();
+    let mut findings = Vec::new();
+    for database in &databases {
+        for pack in database.packs()? {
+            if let Err(err) = pack.verify_checksum() {
+                findings.push(Finding::Bad {
+                    subject: format!("pack {}", pack.path().display()),
+                    problem: Problem::error("badPack", err.to_string()),
+                });
+            }
+        }
+    }
+    // objects that could be read, with the objects each refers to
+    let mut kinds = BTreeMap::new();
+    let mut links = HashMap::new();
+    // objects that exist but could not be read; they are not also reported missing
+    let mut broken = HashSet::new();
+
+    let mut inspect = |id: ObjectId, raw: Result<RawObject, String>| {
+        let raw = match raw {
+            Ok(raw) => raw,
+            Err(message) => {
+                broken.insert(id);
+                findings.push(Finding::Bad {
+                    subject: format!("object {id}"),
+                    problem: Problem::error("badObject", message),
+                });
+                return;
+            }
+        };
+        let subject = format!("{} {id}", raw.kind);
+        let actual = ObjectId::hash(algorithm, raw.kind, &raw.data);
+        if actual != id {
+            broken.insert(id);
+            findings.push(Finding::Bad {
+                subject,
+                problem: Problem::error("hashMismatch", format!("contents hash to {actual}")),
+            });
+            return;
+        }
+        kinds.insert(id, raw.kind);
+        match GitObject::parse(raw.kind, &raw.data, algorithm) {
+            Ok(object) => {
+                findings.extend(check(&object, algorithm).into_iter().map(|problem| {
+                    Finding::Bad {
+                        subject: subject.clone(),
+                        problem,
+                    }
+                }));
+                links.insert(id, references(&object, algorithm));
+            }
+            Err(err) => {
+                let check = match raw.kind {
+                    ObjectType::Tree => "badTree",
+                    _ => "badFormat",
+                };
+                findings.push(Finding::Bad {
+                    subject,
+                    problem: Problem::error(check, err.to_string()),
+                });
+            }
+        }
+    };
+
+    let mut local = HashSet::new();
+    for (n, database) in databases.iter().enumerate() {
+        let mut ids = Vec::new();
+        for id in database.loose().ids()? {
+            inspect(
+                id,
+                database.loose().read(&id).map_err(|err| err.to_string()),
+            );
+            ids.push(id);
+        }
+        for pack in database.packs()? {
+            // a REF_DELTA base may live in another pack or be loose
+            let external = |base: &ObjectId| objects.read(base).ok();
+            for id in pack.index().ids() {
+                let raw = match pack.read(&id, &external) {
+                    Ok(Some(raw)) => Ok(raw),
+                    Ok(None) => Err("listed in the index but not found in the pack".to_owned()),
+                    Err(err) => Err(err.to_string()),
+                };
+                inspect(id, raw);
+                ids.push(id);
+            }
+        }
+        if n == 0 {
+            local.extend(ids);
+        }
+    }
+
+    let mut reached = HashSet::new();
+    let mut missing = BTreeMap::new();
+    let mut pending: Vec<_> = roots.iter().map(|root| (root.id, None)).collect();
+    while let Some((id, expected)) = pending.pop() {
+        if !reached.insert(id) {
+            continue;
+        }
+        let Some(&kind) = kinds.get(&id) else {
+            if !broken.contains(&id) {
+                missing.insert(id, expected);
+            }
+            continue;
+        };
+        if expected.is_some_and(|expected| expected != kind) {
+            findings.push(Finding::Bad {
+                subject: format!("{kind} {id}"),
+                problem: Problem::error(
+                    "badType",
+                    format!("referred to as a {}", expected.unwrap()),
+                ),
+            });
+        }
+        if let Some(links) = links.get(&id) {
+            pending.extend(links.iter().map(|&(id, kind)| (id, Some(kind))));
+        }
+    }
+    findings.extend(
+        missing
+            .into_iter()
+            .map(|(id, kind)| Finding::Missing(kind, id)),
+    );
+
+    let referenced: HashSet<_> = links.values().flatten().map(|&(id, _)| id).collect();
+    for (id, kind) in kinds {
+        // what an alternate holds is for its own repository to account for
+        if reached.contains(&id) || !local.contains(&id) {
+            continue;
+        }
+        if unreachable {
+            findings.push(Finding::Unreachable(kind, id));
+        } else if !referenced.contains(&id) {
+            findings.push(Finding::Dangling(kind, id));
+        }
+    }
+    Ok(findings)
+}
+
+/// The objects `object` refers to, with the type each should have.
+fn references(object: &GitObject, algorithm: HashAlgorithm) -> Vec<(ObjectId, ObjectType)> {
+    let ours = |id: &ObjectId| id.algorithm() == algorithm;
+    match object {
+        GitObject::Blob(_) => Vec::new(),
+        GitObject::Tree(tree) => tree
+            .entries
+            .iter()
+            // submodule commits live in another repository
+            .filter(|entry| entry.mode != FileMode::GITLINK)
+            .map(|entry| (entry.id, entry.mode.object_type()))
+            .collect(),
+        GitObject::Commit(commit) => {
+            let tree = commit.tree().ok().map(|id| (id, ObjectType::Tree));
+            let parents = commit.parents().unwrap_or_default();
+            tree.into_iter()
+                .chain(parents.into_iter().map(|id| (id, ObjectType::Commit)))
+                .filter(|(id, _)| ours(id))
+                .collect()
+        }
+        GitObject::Tag(tag) => match (tag.object(), tag.target_type()) {
+            (Ok(id), Ok(kind)) if ours(&id) => vec![(id, kind)],
+            _ => Vec::new(),
+        },
+    }
+}
+
+/// Checks the contents of an object that parsed, the way `git fsck` does.
+pub(crate) fn check(object: &GitObject, algorithm: HashAlgorithm) -> Vec<Problem> {
+    match object {
+        GitObject::Blob(_) => Vec::new(),
+        GitObject::Tree(tree) => check_tree(tree),
+        GitObject::Commit(commit) => check_commit(commit, algorithm),
+        GitObject::Tag(tag) => check_tag(tag, algorithm),
+    }
+}
+
+fn check_tree(tree: &Tree) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let mut report = |problem: Problem| {
+        // like git, each kind of problem is reported once per tree
+        if !problems.contains(&problem) {
+            problems.push(problem);
+        }
+    };
+
+    for entry in &tree.entries {
+        match entry.name.as_slice() {
+            b"" => report(Problem::warning("emptyName", "contains empty pathname")),
+            b"." => report(Problem::warning("hasDot", "contains '.'")),
+            b".." => report(Problem::warning("hasDotdot", "contains '..'")),
+            name if name.eq_ignore_ascii_case(b".git") => {
+                report(Problem::warning("hasDotgit", "contains '.git'"))
+            }
+            name if name.contains(&b'/') => {
+                report(Problem::warning("fullPathname", "contains full pathnames"))
+            }
+            _ => {}
+        }
+        if !entry.mode.is_canonical() {
+            if FileMode::new(entry.mode.bits()).is_canonical() {
+                report(Problem::warning(
+                    "zeroPaddedFilemode",
+                    "contains zero-padded file modes",
+                ));
+            } else {
+                report(Problem::warning("badFilemode", "contains bad file modes"));
+            }
+        }
+        if entry.id.is_null() {
+            report(Problem::warning(
+                "nullSha1",
+                "contains entries pointing to null sha1",
+            ));
+        }
+    }
+
+    // a file and a directory of the same name need not be adjacent, as in `a`, `a.c`, `a/`
+    let mut names = HashSet::new();
+    if !tree.entries.iter().all(|entry| names.insert(&entry.name)) {
+        report(Problem::error(
+            "duplicateEntries",
+            "contains duplicate file entries",
+        ));
+    }
+    // adjacent duplicates are not also reported as unsorted
+    let sorted = tree
+        .entries
+        .windows(2)
+        .all(|pair| pair[0].name == pair[1].name || pair[0].cmp_git(&pair[1]).is_lt());
+    if !sorted {
+        report(Problem::error("treeNotSorted", "not properly sorted"));
+    }
+    problems
+}
+
+fn check_commit(commit: &Commit, algorithm: HashAlgorithm) -> Vec<Problem> {
+    // the headers git requires come first, in this order
+    let mut headers = commit.0.headers().peekable();
+    match headers.next() {
+        Some(("tree", value)) if is_id(value, algorithm) => {}
+        Some(("tree", _)) => {
+            return vec![Problem::error(
+                "badTreeSha1",
+                "invalid 'tree' line format - bad sha1",
+            )]
+        }
+        _ => {
+            return vec![Problem::error(
+                "missingTree",
+                "invalid format - expected 'tree' line",
+            )]
+        }
+    }
+    while let Some((_, value)) = headers.next_if(|(key, _)| *key == "parent") {
+        if !is_id(value, algorithm) {
+            return vec![Problem::error(
+                "badParentSha1",
+                "invalid 'parent' line format - bad sha1",
+            )];
+        }
+    }
+
+    match headers.next_if(|(key, _)| *key == "author") {
+        Some((_, value)) => {
+            if let Some(problem) = check_ident(value) {
+                return vec![problem];
+            }
+        }
+        None => {
+            return vec![Problem::error(
+                "missingAuthor",
+                "invalid format - expected 'author' line",
+            )]
+        }
+    }
+    if headers.next_if(|(key, _)| *key == "author").is_some() {
+        return vec![Problem::error(
+            "multipleAuthors",
+            "invalid format - multiple 'author' lines",
+        )];
+    }
+    match headers.next_if(|(key, _)| *key == "committer") {
+        Some((_, value)) => check_ident(value).into_iter().collect(),
+        None => vec![Problem::error(
+            "missingCommitter",
+            "invalid format - expected 'committer' line",
+        )],
+    }
+}
+
+fn check_tag(tag: &Tag, algorithm: HashAlgorithm) -> Vec<Problem> {
+    let mut headers = tag.0.headers();
+    let problem = match headers.next() {
+        Some(("object", value)) if is_id(value, algorithm) => None,
+        Some(("object", _)) => Some(Problem::error(
+            "badObjectSha1",
+            "invalid 'object' line format - bad sha1",
+        )),
+        _ => Some(Problem::error(
+            "missingObject",
+            "invalid format - expected 'object' line",
+        )),
+    }
+    .or_else(|| match headers.next() {
+        Some(("type", _)) if tag.target_type().is_ok() => None,
+        Some(("type", _)) => Some(Problem::error("badType", "invalid 'type' value")),
+        _ => Some(Problem::error(
+            "missingTypeEntry",
+            "invalid format - expected 'type' line",
+        )),
+    })
+    .or_else(|| match headers.next() {
+        Some(("tag", _)) => None,
+        _ => Some(Problem::error(
+            "missingTagEntry",
+            "invalid format - expected 'tag' line",
+        )),
+    })
+    // old tags have no tagger, which is fine
+    .or_else(|| match headers.next() {
+        Some(("tagger", value)) => check_ident(value),
+        _ => None,
+    });
+    problem.into_iter().collect()
+}
+
+fn is_id(value: &[u8], algorithm: HashAlgorithm) -> bool {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse::<ObjectId>().ok())
+        .is_some_and(|id| id.algorithm() == algorithm)
+}
+
+/// Checks an `author`, `committer` or `tagger` value: `Name <email> <seconds> <+hhmm>`.
+fn check_ident(value: &[u8]) -> Option<Problem> {
+    let bad = |check, problem: &str| {
+        Some(Problem::error(
+            check,
+            format!("invalid author/committer line - {problem}"),
+        ))
+    };
+
+    let Some(lt) = value.iter().position(|&b| b == b'<') else {
+        return bad("missingEmail", "missing email");
+    };
+    if lt == 0 {
+        return bad("missingNameBeforeEmail", "missing name before email");
+    }
+    if value[lt - 1] != b' ' {
+        return bad("missingSpaceBeforeEmail", "missing space before email");
+    }
+    if value[..lt].iter().any(|&b| b == b'>' || b == b'\n') {
+        return bad("badName", "bad name");
+    }
+    let rest = &value[lt + 1..];
+    let Some(gt) = rest.iter().position(|&b| b == b'>') else {
+        return bad("badEmail", "bad email");
+    };
+    if rest[..gt].iter().any(|&b| b == b'<' || b == b'\n') {
+        return bad("badEmail", "bad email");
+    }
+
+    let Some(rest) = rest[gt + 1..].strip_prefix(b" ") else {
+        return bad("missingSpaceBeforeDate", "missing space before date");
+    };
+    let digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+    let (date, zone) = rest.split_at(digits);
+    if date.len() > 1 && date[0] == b'0' {
+        return bad("zeroPaddedDate", "zero-padded date");
+    }
+    let date = std::str::from_utf8(date).unwrap_or_default();
+    if date.parse::<u64>().is_err() {
+        return bad("badDate", "bad date");
+    }
+    let valid_zone = zone.len() == 6
+        && zone[0] == b' '
+        && matches!(zone[1], b'+' | b'-')
+        && zone[2..].iter().all(u8::is_ascii_digit);
+    if !valid_zone {
+        return bad("badTimezone", "bad time zone");
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{kvlm::Kvlm, object::Object as _};
+
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha1;
+
+    fn commit(data: &str) -> GitObject {
+        GitObject::Commit(Commit(Kvlm::parse(data.as_bytes()).unwrap()))
+    }
+
+    fn checks(object: &GitObject) -> Vec<&'static str> {
+        check(object, ALGORITHM)
+            .into_iter()
+            .map(|problem| problem.check)
+            .collect()
+    }
+
+    #[test]
+    fn trees() {
+        let id = ObjectId::hash(ALGORITHM, ObjectType::Blob, b"");
+        let tree = |entries: &[(&str, &[u8])]| {
+            let mut data = Vec::new();
+            for (mode, name) in entries {
+                data.extend_from_slice(format!("{mode} ").as_bytes());
+                data.extend_from_slice(name);
+                data.push(0);
+                data.extend_from_slice(id.as_bytes());
+            }
+            GitObject::Tree(Tree::parse(&data, ALGORITHM).unwrap())
+        };
+
+        assert!(checks(&tree(&[("100644", b"a"), ("40000", b"b")])).is_empty());
+        assert_eq!(
+            checks(&tree(&[("100644", b"b"), ("100644", b"a")])),
+            ["treeNotSorted"]
+        );
+        // `a.c` sorts between the file `a` and the directory `a`
+        assert_eq!(
+            checks(&tree(&[
+                ("100644", b"a"),
+                ("100644", b"a.c"),
+                ("40000", b"a")
+            ])),
+            ["duplicateEntries"]
+        );
+        assert_eq!(
+            checks(&tree(&[("100644", b"a"), ("100644", b"a")])),
+            ["duplicateEntries"]
+        );
+        assert_eq!(
+            checks(&tree(&[
+                ("040000", b"a"),
+                ("100664", b"b"),
+                ("100644", b".GIT"),
+                ("100644", b"c/d"),
+            ])),
+            [
+                "zeroPaddedFilemode",
+                "badFilemode",
+                "hasDotgit",
+                "fullPathname",
+                "treeNotSorted"
+            ]
+        );
+    }
+
+    #[test]
+    fn commits_and_tags() {
+        let tree = ObjectId::hash(ALGORITHM, ObjectType::Tree, b"");
+        let ident = "A U Thor <author@example.com> 1234567890 +0200";
+        let valid = format!("tree {tree}\nparent {tree}\nauthor {ident}\ncommitter {ident}\n\nm\n");
+        assert!(checks(&commit(&valid)).is_empty());
+
+        assert_eq!(
+            checks(&commit(&format!("author {ident}\ncommitter {ident}\n"))),
+            ["missingTree"]
+        );
+        assert_eq!(
+            checks(&commit(&format!("tree {tree}\ncommitter {ident}\n"))),
+            ["missingAuthor"]
+        );
+        assert_eq!(
+            checks(&commit(&format!(
+                "tree {tree}\nauthor {ident}\nauthor {ident}\ncommitter {ident}\n"
+            ))),
+            ["multipleAuthors"]
+        );
+        assert_eq!(
+            checks(&commit(&format!("tree {tree}\nauthor {ident}\n"))),
+            ["missingCommitter"]
+        );
+        for (ident, check) in [
+            ("A <a@b> 1 +0000", None),
+            ("<a@b> 1 +0000", Some("missingNameBeforeEmail")),
+            ("A<a@b> 1 +0000", Some("missingSpaceBeforeEmail")),
+            ("A a@b 1 +0000", Some("missingEmail")),
+            ("A <a@b 1 +0000", Some("badEmail")),
+            ("A <a@b>1 +0000", Some("missingSpaceBeforeDate")),
+            ("A <a@b> 01 +0000", Some("zeroPaddedDate")),
+            ("A <a@b> x +0000", Some("badDate")),
+            ("A <a@b> 1 0000", Some("badTimezone")),
+            ("A <a@b> 1 +000", Some("badTimezone")),
+        ] {
+            let problems = checks(&commit(&format!(
+                "tree {tree}\nauthor {ident}\ncommitter A <a@b> 1 +0000\n"
+            )));
+            assert_eq!(problems, Vec::from_iter(check), "{ident}");
+        }
+
+        let tag = |data: &str| GitObject::Tag(Tag(Kvlm::parse(data.as_bytes()).unwrap()));
+        assert!(checks(&tag(&format!("object {tree}\ntype tree\ntag v1\n\nm\n"))).is_empty());
+        assert_eq!(
+            checks(&tag(&format!("object {tree}\ntype bogus\ntag v1\n"))),
+            ["badType"]
+        );
+        assert_eq!(
+            checks(&tag(&format!("object {tree}\ntype tree\n"))),
+            ["missingTagEntry"]
+        );
+        assert_eq!(checks(&tag("object 1234\n")), ["badObjectSha1"]);
+    }
+
+    #[test]
+    fn connectivity() {
+        let tempdir = TempDir::new().unwrap();
+        let objects = ObjectDatabase::new(tempdir.path(), ALGORITHM);
+        let blob = objects.write(ObjectType::Blob, b"hello\n").unwrap();
+        let absent = ObjectId::hash(ALGORITHM, ObjectType::Blob, b"absent\n");
+        let mut data = Vec::new();
+        for (name, id) in [("a", blob), ("b", absent)] {
+            data.extend_from_slice(format!("100644 {name}\0").as_bytes());
+            data.extend_from_slice(id.as_bytes());
+        }
+        let tree = objects.write(ObjectType::Tree, &data).unwrap();
+        let ident = "A <a@b> 0 +0000";
+        let commit = format!("tree {tree}\nauthor {ident}\ncommitter {ident}\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        // a commit nothing reaches, and the tree only it refers to
+        let orphan_tree = objects.write(ObjectType::Tree, b"").unwrap();
+        let orphan = format!("tree {orphan_tree}\nauthor {ident}\ncommitter {ident}\n\nm\n");
+        let orphan = objects
+            .write(ObjectType::Commit, orphan.as_bytes())
+            .unwrap();
+
+        let roots = [Reached {
+            id: commit,
+            path: Vec::new(),
+        }];
+        assert_eq!(
+            fsck(&objects, &roots, false).unwrap(),
+            [
+                Finding::Missing(Some(ObjectType::Blob), absent),
+                Finding::Dangling(ObjectType::Commit, orphan),
+            ]
+        );
+        assert_eq!(
+            fsck(&objects, &roots, true).unwrap(),
+            [
+                Finding::Missing(Some(ObjectType::Blob), absent),
+                Finding::Unreachable(ObjectType::Commit, orphan),
+                Finding::Unreachable(ObjectType::Tree, orphan_tree),
+            ]
+        );
+
+        // an object whose contents no longer match its name
+        let path = objects.loose().path(&blob);
+        fs::remove_file(&path).unwrap();
+        fs::copy(objects.loose().path(&orphan_tree), &path).unwrap();
+        let findings = fsck(&objects, &roots, false).unwrap();
+        assert_eq!(
+            findings[0].to_string(),
+            format!("error in tree {blob}: hashMismatch: contents hash to {orphan_tree}")
+        );
+        assert!(findings[0].is_error());
+    }
+}
@@ -0,0 +1,194 @@
+use std::{collections::BTreeMap, env, error::Error, fs};
+
+use application::clap;
+
+use crate::{
+    lockfile::{self, Lockfile},
+    object::GitObject,
+    odb::ObjectStore as _,
+    pack::PackOptions,
+    prune,
+    refs::{self, PackedRef, RefValue},
+    repack::{self, RepackOptions},
+    repo::{Config as _, RealRepo},
+    Execute,
+};
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Spend much longer looking for deltas
+    #[arg(long)]
+    aggressive: bool,
+    /// Prune unreachable loose objects older than <date> (default `gc.pruneExpire`)
+    #[arg(long, value_name = "date", conflicts_with = "no_prune")]
+    prune: Option<String>,
+    /// Do not prune any loose objects
+    #[arg(long)]
+    no_prune: bool,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let config = repo.config()?;
+
+        pack_refs(&repo)?;
+
+        let mut pack = repack::pack_options(&repo)?;
+        if self.aggressive {
+            let window = config.get_int("gc", "aggressiveWindow")?.unwrap_or(250);
+            let depth = config.get_int("gc", "aggressiveDepth")?.unwrap_or(50);
+            pack = PackOptions {
+                window: usize::try_from(window)?,
+                depth: usize::try_from(depth)?,
+            };
+        }
+        // objects that must be kept are still packed, but nothing is deleted
+        let precious = repo.has_precious_objects();
+        let options = RepackOptions {
+            all: true,
+            loosen_unreachable: !precious,
+            delete: !precious,
+            local: true,
+            pack,
+        };
+        repack::repack(&repo, &options)?;
+
+        if precious || self.no_prune {
+            return Ok(());
+        }
+        let expire = match self.prune {
+            Some(date) => prune::expiry_date(&date)?,
+            None => match config.get_value("gc", "pruneExpire") {
+                Some(Some(date)) => prune::expiry_date(date)?,
+                _ => prune::expiry_date("2.weeks.ago")?,
+            },
+        };
+        prune::prune(&repo, expire, false, false)
+    }
+}
+
+/// Moves every loose ref into `packed-refs`, like `git pack-refs --all --prune`.
+fn pack_refs(repo: &RealRepo) -> Result<(), Box<dyn Error>> {
+    let store = repo.refs();
+    let mut lock = Lockfile::acquire(store.packed_path())?;
+    // symbolic refs, and those belonging to one worktree, stay loose
+    let loose: BTreeMap<_, _> = store
+        .loose()?
+        .into_iter()
+        .filter_map(|(name, value)| match value {
+            RefValue::Direct(id) if !refs::is_per_worktree(&name) => Some((name, id)),
+            _ => None,
+        })
+        .collect();
+    let mut refs: BTreeMap<_, _> = store
+        .packed()?
+        .into_iter()
+        .map(|(name, packed)| (name, packed.id))
+        .collect();
+    refs.extend(loose.clone());
+
+    // annotated tags also record what they point to, so readers need not open them
+    let objects = repo.objects();
+    let mut packed = BTreeMap::new();
+    for (name, id) in refs {
+        let mut peeled = id;
+        while let Ok(GitObject::Tag(tag)) = objects.read_object(&peeled) {
+            peeled = tag.object()?;
+        }
+        let peeled = (peeled != id).then_some(peeled);
+        packed.insert(name, PackedRef { id, peeled });
+    }
+    let packed = refs::format_packed(&packed);
+    lock.write_all(packed.as_bytes())?;
+    lock.commit()?;
+
+    for (name, id) in loose {
+        // the ref is locked while it is checked and removed, so that an update
+        // cannot land in between; one that is being updated is left alone
+        let lock = match Lockfile::acquire(store.path(&name)) {
+            Ok(lock) => lock,
+            Err(lockfile::Error::Locked(_)) => continue,
+            Err(err) => return Err(err.into()),
+        };
+        // a ref that moved since it was packed stays loose, where it takes precedence
+        let unchanged = fs::read_to_string(lock.path())
+            .is_ok_and(|contents| contents.trim_end() == id.to_string());
+        if unchanged {
+            fs::remove_file(lock.path())?;
+            drop(lock);
+            store.remove_empty_parents(&name);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::{ObjectId, ObjectType},
+        repo::{InitOptions, RealRepoCreator, RefTransaction, RefUpdate, RepoCreator as _},
+    };
+
+    #[test]
+    fn pack_loose_refs() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = RealRepoCreator::create(tempdir.path(), &InitOptions::default()).unwrap();
+        let objects = repo.objects();
+        let tree = objects.write(ObjectType::Tree, b"").unwrap();
+        let commit =
+            format!("tree {tree}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        let tag = format!("object {commit}\ntype commit\ntag v1\ntagger A <a@b> 0 +0000\n\nt\n");
+        let tag = objects.write(ObjectType::Tag, tag.as_bytes()).unwrap();
+        let mut transaction = RefTransaction::new(repo.refs());
+        for (name, id) in [
+            ("HEAD", commit),
+            ("refs/heads/topic/a", commit),
+            ("refs/heads/busy", commit),
+            ("refs/tags/v1", tag),
+        ] {
+            transaction.add(RefUpdate::create(name, id));
+        }
+        transaction.commit().unwrap();
+
+        let store = repo.refs();
+        // another process is updating `busy`
+        let busy = Lockfile::acquire(store.path("refs/heads/busy")).unwrap();
+        pack_refs(&repo).unwrap();
+        drop(busy);
+
+        let line = |id: ObjectId, name| format!("{id} {name}\n");
+        let expected = [
+            "# pack-refs with: peeled fully-peeled sorted \n".to_owned(),
+            line(commit, "refs/heads/busy"),
+            line(commit, "refs/heads/master"),
+            line(commit, "refs/heads/topic/a"),
+            line(tag, "refs/tags/v1"),
+            format!("^{commit}\n"),
+        ];
+        assert_eq!(
+            fs::read_to_string(store.packed_path()).unwrap(),
+            expected.concat()
+        );
+        let loose: Vec<_> = store.loose().unwrap().into_keys().collect();
+        assert_eq!(loose, ["refs/heads/busy"]);
+        assert!(!store.path("refs/heads/topic").exists());
+        assert_eq!(
+            store.read("HEAD").unwrap(),
+            Some(RefValue::Symbolic("refs/heads/master".to_owned()))
+        );
+        assert_eq!(store.resolve("refs/tags/v1").unwrap(), Some(tag));
+    }
+}
@@ -1,8 +1,75 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, Read as _},
+    path::PathBuf,
+};
+
 use application::clap;
 
-use crate::Execute;
+use crate::{
+    object::{GitObject, HashAlgorithm, ObjectId, ObjectType},
+    odb::ObjectStore as _,
+    repo::{self, RealRepo},
+    Execute,
+};
 
 #[derive(Debug, clap::Args)]
-pub(crate) struct Args;
+pub(crate) struct Args {
+    /// Write the object into the object database
+    #[arg(short)]
+    write: bool,
+    /// The type of object to create
+    #[arg(short = 't', value_name = "type", default_value = "blob")]
+    kind: ObjectType,
+    /// Read the object from standard input
+    #[arg(long)]
+    stdin: bool,
+    files: Vec<PathBuf>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let mut contents = Vec::new();
+        if self.stdin {
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf)?;
+            contents.push(buf);
+        }
+        for file in &self.files {
+            contents.push(fs::read(file)?);
+        }
+
+        // outside a repository, objects can still be hashed with the default algorithm
+        let repo = match RealRepo::discover(&env::current_dir()?) {
+            Ok(repo) => Some(repo),
+            Err(repo::Error::NotGitRepository(_) | repo::Error::FilesystemBoundary(_))
+                if !self.write =>
+            {
+                None
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let algorithm = repo
+            .as_ref()
+            .map_or(HashAlgorithm::default(), RealRepo::object_format);
+        for data in contents {
+            // refuse to create objects that other git tooling could not read
+            GitObject::parse(self.kind, &data, algorithm)?;
+            let id = match &repo {
+                Some(repo) if self.write => repo.objects().write(self.kind, &data)?,
+                _ => ObjectId::hash(algorithm, self.kind, &data),
+            };
+            println!("{id}");
+        }
 
-impl Execute for Args {}
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    lockfile::{self, Lockfile},
+    object::{HashAlgorithm, ObjectId},
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Index file is corrupt: {0}")]
+    Corrupt(String),
+    #[error("Unsupported index version: {0}")]
+    UnsupportedVersion(u32),
+    #[error(transparent)]
+    Lock(#[from] lockfile::Error),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+const SIGNATURE: &[u8] = b"DIRC";
+const EXTENDED_FLAG: u16 = 0x4000;
+const NAME_MASK: u16 = 0x0fff;
+
+/// One path in the staging area, with the stat data used to detect changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct IndexEntry {
+    pub(crate) ctime: (u32, u32),
+    pub(crate) mtime: (u32, u32),
+    pub(crate) dev: u32,
+    pub(crate) ino: u32,
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u32,
+    pub(crate) id: ObjectId,
+    pub(crate) flags: u16,
+    pub(crate) extended_flags: Option<u16>,
+    pub(crate) path: Vec<u8>,
+}
+
+impl IndexEntry {
+    /// An entry for a file just written to the worktree.
+    #[cfg(unix)]
+    pub(crate) fn from_metadata(
+        path: Vec<u8>,
+        id: ObjectId,
+        mode: u32,
+        metadata: &fs::Metadata,
+    ) -> Self {
+        use std::os::unix::fs::MetadataExt as _;
+
+        // the on-disk format truncates stat data to 32 bits
+        Self {
+            ctime: (metadata.ctime() as u32, metadata.ctime_nsec() as u32),
+            mtime: (metadata.mtime() as u32, metadata.mtime_nsec() as u32),
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            mode,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+            id,
+            flags: path.len().min(NAME_MASK as usize) as u16,
+            extended_flags: None,
+            path,
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn from_metadata(
+        path: Vec<u8>,
+        id: ObjectId,
+        mode: u32,
+        metadata: &fs::Metadata,
+    ) -> Self {
+        Self {
+            mode,
+            size: metadata.len() as u32,
+            id,
+            flags: path.len().min(NAME_MASK as usize) as u16,
+            path,
+            ..Self::default()
+        }
+    }
+
+    /// The merge stage; zero for normal entries.
+    pub(crate) fn stage(&self) -> u16 {
+        (self.flags >> 12) & 0x3
+    }
+}
+
+/// The `index` file (versions 2 and 3), without extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Index {
+    pub(crate) version: u32,
+    /// The repository's hash, which sizes both the entry ids and the checksum.
+    pub(crate) algorithm: HashAlgorithm,
+    pub(crate) entries: Vec<IndexEntry>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self::new(HashAlgorithm::default())
+    }
+}
+
+impl Index {
+    pub(crate) fn new(algorithm: HashAlgorithm) -> Self {
+        Self {
+            version: 2,
+            algorithm,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Reads the index at `path`; a missing file is an empty index.
+    pub(crate) fn read<P>(path: P, algorithm: HashAlgorithm) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        match fs::read(path) {
+            Ok(data) => Self::parse(&data, algorithm),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::new(algorithm)),
+            Err(err) => Err(Error::Io(err.to_string())),
+        }
+    }
+
+    pub(crate) fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self, Error> {
+        let corrupt = |msg: &str| Error::Corrupt(msg.to_owned());
+        let id_len = algorithm.len();
+        if data.len() < 12 + id_len || &data[..4] != SIGNATURE {
+            return Err(corrupt("bad signature"));
+        }
+        let (body, checksum) = data.split_at(data.len() - id_len);
+        if algorithm.digest(&[body]).as_bytes() != checksum {
+            return Err(corrupt("bad checksum"));
+        }
+
+        let version = u32::from_be_bytes(body[4..8].try_into().unwrap());
+        if !(2..=3).contains(&version) {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let count = u32::from_be_bytes(body[8..12].try_into().unwrap());
+
+        let mut entries = Vec::new();
+        let mut pos = 12;
+        for _ in 0..count {
+            // ten stat words, the id, then two bytes of flags
+            let fixed_len = 40 + id_len + 2;
+            let fixed = body
+                .get(pos..pos + fixed_len)
+                .ok_or_else(|| corrupt("truncated entry"))?;
+            let word = |i: usize| u32::from_be_bytes(fixed[i * 4..i * 4 + 4].try_into().unwrap());
+            let flags = u16::from_be_bytes(fixed[fixed_len - 2..].try_into().unwrap());
+            let mut name_start = pos + fixed_len;
+            let extended_flags = if flags & EXTENDED_FLAG != 0 {
+                let extended = body
+                    .get(name_start..name_start + 2)
+                    .ok_or_else(|| corrupt("truncated entry"))?;
+                name_start += 2;
+                Some(u16::from_be_bytes(extended.try_into().unwrap()))
+            } else {
+                None
+            };
+            let name_len = body[name_start..]
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| corrupt("unterminated path"))?;
+
+            entries.push(IndexEntry {
+                ctime: (word(0), word(1)),
+                mtime: (word(2), word(3)),
+                dev: word(4),
+                ino: word(5),
+                mode: word(6),
+                uid: word(7),
+                gid: word(8),
+                size: word(9),
+                id: ObjectId::from_bytes(&fixed[40..40 + id_len]).expect("slice has the id length"),
+                flags,
+                extended_flags,
+                path: body[name_start..name_start + name_len].to_vec(),
+            });
+
+            // entries are NUL-padded to a multiple of eight bytes
+            let len = name_start - pos + name_len;
+            pos += (len + 8) & !7;
+        }
+
+        Ok(Self {
+            version,
+            algorithm,
+            entries,
+        })
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let version = if self.entries.iter().any(|e| e.extended_flags.is_some()) {
+            3
+        } else {
+            self.version
+        };
+
+        let mut out = SIGNATURE.to_vec();
+        out.extend_from_slice(&version.to_be_bytes());
+        out.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            let start = out.len();
+            for word in [
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                out.extend_from_slice(&word.to_be_bytes());
+            }
+            out.extend_from_slice(entry.id.as_bytes());
+            let mut flags = entry.flags & !EXTENDED_FLAG;
+            if entry.extended_flags.is_some() {
+                flags |= EXTENDED_FLAG;
+            }
+            out.extend_from_slice(&flags.to_be_bytes());
+            if let Some(extended) = entry.extended_flags {
+                out.extend_from_slice(&extended.to_be_bytes());
+            }
+            out.extend_from_slice(&entry.path);
+            let len = out.len() - start;
+            out.resize(start + ((len + 8) & !7), 0);
+        }
+
+        let checksum = self.algorithm.digest(&[&out]);
+        out.extend_from_slice(checksum.as_bytes());
+        out
+    }
+
+    /// Writes through `<path>.lock` so that readers never see a partial index.
+    pub(crate) fn write<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut lock = Lockfile::acquire(path)?;
+        lock.write_all(&self.serialize())?;
+        Ok(lock.commit()?)
+    }
+
+    /// Puts the entries in the order git requires: by path, then by stage.
+    pub(crate) fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| a.path.cmp(&b.path).then(a.stage().cmp(&b.stage())));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::object::ObjectType;
+
+    fn entry(path: &str, algorithm: HashAlgorithm) -> IndexEntry {
+        IndexEntry {
+            mode: 0o100644,
+            size: 6,
+            id: ObjectId::hash(algorithm, ObjectType::Blob, b"hello\n"),
+            flags: path.len() as u16,
+            path: path.as_bytes().to_vec(),
+            ..IndexEntry::default()
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let tempdir = TempDir::new().unwrap();
+        for algorithm in [HashAlgorithm::Sha1, HashAlgorithm::Sha256] {
+            let mut index = Index {
+                entries: ["src/main.rs", "a", "abcdefgh"]
+                    .map(|path| entry(path, algorithm))
+                    .to_vec(),
+                ..Index::new(algorithm)
+            };
+            index.sort();
+            assert_eq!(index.entries[0].path, b"a");
+
+            let path = tempdir.path().join(format!("index-{algorithm}"));
+            index.write(&path).unwrap();
+            assert!(!tempdir
+                .path()
+                .join(format!("index-{algorithm}.lock"))
+                .exists());
+            assert_eq!(Index::read(&path, algorithm).unwrap(), index);
+        }
+    }
+
+    #[test]
+    fn missing_is_empty() {
+        let tempdir = TempDir::new().unwrap();
+        assert_eq!(
+            Index::read(tempdir.path().join("index"), HashAlgorithm::Sha256),
+            Ok(Index::new(HashAlgorithm::Sha256))
+        );
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let mut data = Index {
+            entries: vec![entry("a", HashAlgorithm::Sha1)],
+            ..Index::default()
+        }
+        .serialize();
+        assert!(matches!(
+            Index::parse(&data, HashAlgorithm::Sha256),
+            Err(Error::Corrupt(_))
+        ));
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+        assert!(matches!(
+            Index::parse(&data, HashAlgorithm::Sha1),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
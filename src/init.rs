@@ -3,19 +3,31 @@ use std::{error::Error, path::PathBuf};
 use application::clap;
 
 use crate::{
-    repo::{RealRepoCreator, RepoCreator as _},
+    object::HashAlgorithm,
+    repo::{InitOptions, RealRepoCreator, RepoCreator as _},
     Execute,
 };
 
 #[derive(Debug, clap::Args)]
 pub(crate) struct Args {
+    /// Create a bare repository
+    #[arg(long)]
+    bare: bool,
+    /// The hash algorithm that names objects: `sha1` or `sha256`
+    #[arg(long, value_name = "format", default_value = "sha1")]
+    object_format: HashAlgorithm,
     #[clap(default_value = ".")]
     path: PathBuf,
 }
 
 impl Execute for Args {
     fn execute(self) -> Result<(), crate::GitError> {
-        RealRepoCreator::create(self.path).map_err(|err| Box::new(err) as Box<dyn Error>)?;
+        let options = InitOptions {
+            bare: self.bare,
+            object_format: self.object_format,
+        };
+        RealRepoCreator::create(self.path, &options)
+            .map_err(|err| Box::new(err) as Box<dyn Error>)?;
         Ok(())
     }
 }
@@ -0,0 +1,180 @@
+use crate::object::Error;
+
+/// An ordered key-value list with message, the format shared by commits and tags.
+///
+/// Keys may repeat (e.g. `parent`), and values that span several lines are
+/// stored with their continuation prefix removed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Kvlm {
+    headers: Vec<(String, Vec<u8>)>,
+    /// `None` when the object has no blank line separating headers from a message.
+    message: Option<Vec<u8>>,
+}
+
+impl Kvlm {
+    pub(crate) fn parse(mut data: &[u8]) -> Result<Self, Error> {
+        let mut headers = Vec::new();
+        loop {
+            match data.first() {
+                None => {
+                    return Ok(Self {
+                        headers,
+                        message: None,
+                    })
+                }
+                Some(b'\n') => {
+                    return Ok(Self {
+                        headers,
+                        message: Some(data[1..].to_vec()),
+                    })
+                }
+                Some(_) => {}
+            }
+
+            let space = data
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| Error::MalformedKvlm("header without value".to_owned()))?;
+            let key = std::str::from_utf8(&data[..space])
+                .ok()
+                .filter(|key| !key.is_empty() && !key.contains('\n'))
+                .ok_or_else(|| Error::MalformedKvlm("invalid header name".to_owned()))?;
+
+            // the value runs until a newline that is not followed by a space
+            let mut end = space + 1;
+            loop {
+                match data[end..].iter().position(|&b| b == b'\n') {
+                    None => return Err(Error::MalformedKvlm(format!("unterminated {key}"))),
+                    Some(pos) => {
+                        end += pos;
+                        if data.get(end + 1) != Some(&b' ') {
+                            break;
+                        }
+                        end += 1;
+                    }
+                }
+            }
+
+            let value = data[space + 1..end]
+                .split(|&b| b == b'\n')
+                .enumerate()
+                .fold(Vec::new(), |mut value, (i, line)| {
+                    if i > 0 {
+                        value.push(b'\n');
+                        value.extend_from_slice(&line[1..]);
+                    } else {
+                        value.extend_from_slice(line);
+                    }
+                    value
+                });
+            headers.push((key.to_owned(), value));
+            data = &data[end + 1..];
+        }
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (key, value) in &self.headers {
+            out.extend_from_slice(key.as_bytes());
+            out.push(b' ');
+            for (i, line) in value.split(|&b| b == b'\n').enumerate() {
+                if i > 0 {
+                    out.extend_from_slice(b"\n ");
+                }
+                out.extend_from_slice(line);
+            }
+            out.push(b'\n');
+        }
+        if let Some(message) = &self.message {
+            out.push(b'\n');
+            out.extend_from_slice(message);
+        }
+        out
+    }
+
+    /// The first value for `key`.
+    pub(crate) fn get(&self, key: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub(crate) fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(k, _)| k == key)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    pub(crate) fn headers(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.headers
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+    }
+
+    pub(crate) fn push(&mut self, key: &str, value: impl Into<Vec<u8>>) {
+        self.headers.push((key.to_owned(), value.into()));
+    }
+
+    pub(crate) fn message(&self) -> &[u8] {
+        self.message.as_deref().unwrap_or_default()
+    }
+
+    pub(crate) fn set_message(&mut self, message: impl Into<Vec<u8>>) {
+        self.message = Some(message.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNED: &str = concat!(
+        "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147\n",
+        "parent 206941306e8a8af65b66eaaaea388a7ae24d49a0\n",
+        "author Thibault Polge <thibault@thb.lt> 1527025023 +0200\n",
+        "committer Thibault Polge <thibault@thb.lt> 1527025044 +0200\n",
+        "gpgsig -----BEGIN PGP SIGNATURE-----\n",
+        " \n",
+        " iQIzBAABCAAdFiEExwXquOM8bWb4Q2zVGxM2FxoLkGQFAlsEjZQACgkQGxM2FxoL\n",
+        " kGQdcBAAqPP+ln4nGDd2gETXjvOpOxLzIMEw4A9gU6CzWzm+oB8mEIKyaH0UFIPh\n",
+        " =lgTX\n",
+        " -----END PGP SIGNATURE-----\n",
+        "\n",
+        "Create first draft\n",
+    );
+
+    #[test]
+    fn round_trip() {
+        let kvlm = Kvlm::parse(SIGNED.as_bytes()).unwrap();
+        assert_eq!(kvlm.serialize(), SIGNED.as_bytes());
+    }
+
+    #[test]
+    fn continuation_lines() {
+        let kvlm = Kvlm::parse(SIGNED.as_bytes()).unwrap();
+        let gpgsig = kvlm.get("gpgsig").unwrap();
+        assert!(gpgsig.starts_with(b"-----BEGIN PGP SIGNATURE-----\n\niQIz"));
+        assert!(gpgsig.ends_with(b"=lgTX\n-----END PGP SIGNATURE-----"));
+        assert_eq!(kvlm.message(), b"Create first draft\n");
+        assert_eq!(
+            kvlm.headers().map(|(key, _)| key).collect::<Vec<_>>(),
+            ["tree", "parent", "author", "committer", "gpgsig"]
+        );
+    }
+
+    #[test]
+    fn without_message() {
+        let data = b"object 4b825dc642cb6eb9a060e54bf8d69288fbee4904\ntype tree\n";
+        let kvlm = Kvlm::parse(data).unwrap();
+        assert_eq!(kvlm.message(), b"");
+        assert_eq!(kvlm.serialize(), data);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Kvlm::parse(b"tree\n\n").is_err());
+        assert!(Kvlm::parse(b"tree abc").is_err());
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Unable to create '{0}': File exists. Another git process seems to be running")]
+    Locked(PathBuf),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// Exclusive ownership of a file while it is rewritten.
+///
+/// `<path>.lock` is created exclusively and receives the new contents, which
+/// only replace `path` on [`Lockfile::commit`]. Dropping the lock without
+/// committing leaves `path` untouched.
+#[derive(Debug)]
+pub(crate) struct Lockfile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: File,
+    done: bool,
+}
+
+impl Lockfile {
+    pub(crate) fn acquire<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_owned();
+        let mut lock_path = path.clone().into_os_string();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::AlreadyExists => Error::Locked(lock_path.clone()),
+                _ => Error::Io(err.to_string()),
+            })?;
+        Ok(Self {
+            path,
+            lock_path,
+            file,
+            done: false,
+        })
+    }
+
+    /// The file being replaced.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.file
+            .write_all(data)
+            .map_err(|err| Error::Io(err.to_string()))
+    }
+
+    /// Atomically replaces the file with what was written.
+    pub(crate) fn commit(mut self) -> Result<(), Error> {
+        let result = self
+            .file
+            .sync_all()
+            .and_then(|()| fs::rename(&self.lock_path, &self.path));
+        // on failure, the lock is removed on drop
+        self.done = result.is_ok();
+        result.map_err(|err| Error::Io(err.to_string()))
+    }
+}
+
+impl Drop for Lockfile {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn commit_and_rollback() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("config");
+        fs::write(&path, "old").unwrap();
+
+        let mut lock = Lockfile::acquire(&path).unwrap();
+        assert_eq!(
+            Lockfile::acquire(&path).unwrap_err(),
+            Error::Locked(tempdir.path().join("config.lock"))
+        );
+        lock.write_all(b"new").unwrap();
+        lock.commit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+
+        let mut lock = Lockfile::acquire(&path).unwrap();
+        lock.write_all(b"abandoned").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert!(!tempdir.path().join("config.lock").exists());
+    }
+}
@@ -1,7 +1,7 @@
 // TODO: remove
 #![allow(dead_code)]
 
-use std::error::Error;
+use std::{env, error::Error, path::PathBuf};
 
 use application::{clap, Application};
 
@@ -18,36 +18,130 @@ mod cat_file;
 mod check_ignore;
 mod checkout;
 mod commit;
+mod config;
+mod config_command;
+mod count_objects;
+mod delta;
+mod fsck;
+mod gc;
 mod hash_object;
+mod index;
 mod init;
+mod kvlm;
+mod lockfile;
 mod log;
 mod ls_files;
 mod ls_tree;
+mod object;
+mod odb;
+mod pack;
+mod pack_objects;
+mod prune;
+mod reachable;
+mod reflog;
+mod reflog_command;
+mod refs;
+mod repack;
 mod repo;
 mod rev_parse;
 mod rm;
 mod show_ref;
 mod status;
+mod symbolic_ref;
 mod tag;
+mod tree;
+mod update_ref;
+mod verify_pack;
+mod wildmatch;
+mod worktree;
 
 #[derive(clap::Parser, Debug)]
 #[command(name = "wyag", about = "the stupidest content tracker")]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Options that apply to every command and must come before it.
+#[derive(clap::Args, Debug)]
+struct GlobalArgs {
+    /// Run as if started in <path>; may be repeated, each relative to the last
+    #[arg(short = 'C', value_name = "path")]
+    chdir: Vec<PathBuf>,
+    /// Path to the repository (sets `GIT_DIR`)
+    #[arg(long, value_name = "path")]
+    git_dir: Option<PathBuf>,
+    /// Path to the working tree (sets `GIT_WORK_TREE`)
+    #[arg(long, value_name = "path")]
+    work_tree: Option<PathBuf>,
+    /// Override a configuration value for this invocation; may be repeated
+    #[arg(short = 'c', value_name = "name=value")]
+    config: Vec<String>,
+}
+
+impl GlobalArgs {
+    /// Applies the options to the process the same way git does, so that
+    /// repository lookup only has to consult the environment.
+    fn apply(self) -> Result<(), GitError> {
+        for dir in self.chdir.iter().filter(|dir| !dir.as_os_str().is_empty()) {
+            env::set_current_dir(dir).map_err(|err| Box::new(err) as Box<dyn Error>)?;
+        }
+        if let Some(git_dir) = self.git_dir {
+            env::set_var("GIT_DIR", git_dir);
+        }
+        if let Some(work_tree) = self.work_tree {
+            env::set_var("GIT_WORK_TREE", work_tree);
+        }
+        if !self.config.is_empty() {
+            // appended to any parameters inherited from a parent git process
+            let mut parameters = env::var("GIT_CONFIG_PARAMETERS").unwrap_or_default();
+            for option in &self.config {
+                let (key, value) = match option.split_once('=') {
+                    Some((key, value)) => (key, Some(value)),
+                    None => (option.as_str(), None),
+                };
+                if !parameters.is_empty() {
+                    parameters.push(' ');
+                }
+                parameters.push_str(&config::format_parameter(key, value));
+            }
+            env::set_var("GIT_CONFIG_PARAMETERS", parameters);
+        }
+        Ok(())
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
 enum Command {
     Add(add::Args),
     CatFile(cat_file::Args),
     CheckIgnore(check_ignore::Args),
     Checkout(checkout::Args),
     Commit(commit::Args),
+    Config(config_command::Args),
+    CountObjects(count_objects::Args),
+    Fsck(fsck::Args),
+    Gc(gc::Args),
     HashObject(hash_object::Args),
     Init(init::Args),
     Log(log::Args),
     LsFiles(ls_files::Args),
     LsTree(ls_tree::Args),
+    PackObjects(pack_objects::Args),
+    Prune(prune::Args),
+    Reflog(reflog_command::Args),
+    Repack(repack::Args),
     RevParse(rev_parse::Args),
     Rm(rm::Args),
     ShowRef(show_ref::Args),
     Status(status::Args),
+    SymbolicRef(symbolic_ref::Args),
     Tag(tag::Args),
+    UpdateRef(update_ref::Args),
+    VerifyPack(verify_pack::Args),
+    Worktree(worktree::Args),
 }
 
 trait Execute: Sized {
@@ -64,25 +158,38 @@ impl Execute for Command {
             Command::CheckIgnore(args) => args.execute(),
             Command::Checkout(args) => args.execute(),
             Command::Commit(args) => args.execute(),
+            Command::Config(args) => args.execute(),
+            Command::CountObjects(args) => args.execute(),
+            Command::Fsck(args) => args.execute(),
+            Command::Gc(args) => args.execute(),
             Command::HashObject(args) => args.execute(),
             Command::Init(args) => args.execute(),
             Command::Log(args) => args.execute(),
             Command::LsFiles(args) => args.execute(),
             Command::LsTree(args) => args.execute(),
+            Command::PackObjects(args) => args.execute(),
+            Command::Prune(args) => args.execute(),
+            Command::Reflog(args) => args.execute(),
+            Command::Repack(args) => args.execute(),
             Command::RevParse(args) => args.execute(),
             Command::Rm(args) => args.execute(),
             Command::ShowRef(args) => args.execute(),
             Command::Status(args) => args.execute(),
+            Command::SymbolicRef(args) => args.execute(),
             Command::Tag(args) => args.execute(),
+            Command::UpdateRef(args) => args.execute(),
+            Command::VerifyPack(args) => args.execute(),
+            Command::Worktree(args) => args.execute(),
         }
     }
 }
 
 impl Application for Git {
     type Error = GitError;
-    type Args = Command;
+    type Args = Cli;
 
     fn main(&self, args: Self::Args) -> Result<(), Self::Error> {
-        args.execute()
+        args.global.apply()?;
+        args.command.execute()
     }
 }
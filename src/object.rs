@@ -0,0 +1,464 @@
+use std::{fmt, str::FromStr};
+
+use sha1::{digest, Digest, Sha1};
+use sha2::Sha256;
+
+use crate::{kvlm::Kvlm, tree::Tree};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Invalid object type: {0}")]
+    InvalidType(String),
+    #[error("Invalid object id: {0}")]
+    InvalidId(String),
+    #[error("Unknown hash algorithm: {0}")]
+    UnknownHashAlgorithm(String),
+    #[error("Malformed commit or tag: {0}")]
+    MalformedKvlm(String),
+    #[error("Malformed tree: {0}")]
+    MalformedTree(String),
+    #[error("Missing or invalid {1} header in {0}")]
+    InvalidHeader(ObjectType, &'static str),
+}
+
+/// The kind of an object, as written in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ObjectType {
+    Blob,
+    Tree,
+    Commit,
+    Tag,
+}
+
+impl ObjectType {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            ObjectType::Blob => "blob",
+            ObjectType::Tree => "tree",
+            ObjectType::Commit => "commit",
+            ObjectType::Tag => "tag",
+        }
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ObjectType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blob" => Ok(ObjectType::Blob),
+            "tree" => Ok(ObjectType::Tree),
+            "commit" => Ok(ObjectType::Commit),
+            "tag" => Ok(ObjectType::Tag),
+            _ => Err(Error::InvalidType(s.to_owned())),
+        }
+    }
+}
+
+/// The hash function a repository names its objects with (`extensions.objectFormat`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    /// The length of a binary id.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha1 => 20,
+            HashAlgorithm::Sha256 => 32,
+        }
+    }
+
+    pub(crate) fn hex_len(&self) -> usize {
+        self.len() * 2
+    }
+
+    /// Hashes the concatenation of `parts`; also used for file checksums.
+    pub(crate) fn digest(&self, parts: &[&[u8]]) -> ObjectId {
+        fn run<D: Digest>(parts: &[&[u8]]) -> digest::Output<D> {
+            let mut hasher = D::new();
+            parts.iter().for_each(|part| hasher.update(part));
+            hasher.finalize()
+        }
+        match self {
+            HashAlgorithm::Sha1 => ObjectId::Sha1(run::<Sha1>(parts).into()),
+            HashAlgorithm::Sha256 => ObjectId::Sha256(run::<Sha256>(parts).into()),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Sha256 => "sha256",
+        })
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sha1" => Ok(HashAlgorithm::Sha1),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(Error::UnknownHashAlgorithm(s.to_owned())),
+        }
+    }
+}
+
+/// The name of an object, under either hash algorithm.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum ObjectId {
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+impl ObjectId {
+    /// Hashes `data` the way git does: `<type> <len>\0<data>`.
+    pub(crate) fn hash(algorithm: HashAlgorithm, kind: ObjectType, data: &[u8]) -> Self {
+        algorithm.digest(&[&header(kind, data.len()), data])
+    }
+
+    /// The all-zero id, which git uses for "no object".
+    pub(crate) fn null(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha1 => ObjectId::Sha1([0; 20]),
+            HashAlgorithm::Sha256 => ObjectId::Sha256([0; 32]),
+        }
+    }
+
+    /// A binary id; the algorithm follows from the length.
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes.len() {
+            20 => bytes.try_into().ok().map(ObjectId::Sha1),
+            32 => bytes.try_into().ok().map(ObjectId::Sha256),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            ObjectId::Sha1(_) => HashAlgorithm::Sha1,
+            ObjectId::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        match self {
+            ObjectId::Sha1(bytes) => bytes,
+            ObjectId::Sha256(bytes) => bytes,
+        }
+    }
+
+    pub(crate) fn is_null(&self) -> bool {
+        self.as_bytes().iter().all(|&byte| byte == 0)
+    }
+
+    /// The hex name split into the loose object directory and file name.
+    pub(crate) fn loose_path(&self) -> (String, String) {
+        let hex = self.to_string();
+        (hex[..2].to_owned(), hex[2..].to_owned())
+    }
+}
+
+impl Default for ObjectId {
+    fn default() -> Self {
+        Self::null(HashAlgorithm::default())
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_bytes()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ObjectId({self})")
+    }
+}
+
+impl FromStr for ObjectId {
+    type Err = Error;
+
+    /// A full hex name of either length.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidId(s.to_owned());
+        if !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let bytes: Vec<u8> = s
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect();
+        Self::from_bytes(&bytes).ok_or_else(invalid)
+    }
+}
+
+/// Conversion between an object's payload and its typed form.
+pub(crate) trait Object: Sized {
+    const TYPE: ObjectType;
+
+    /// Parses a payload from a repository that names objects with `algorithm`.
+    fn parse(data: &[u8], algorithm: HashAlgorithm) -> Result<Self, Error>;
+
+    fn serialize(&self) -> Vec<u8>;
+
+    fn id(&self, algorithm: HashAlgorithm) -> ObjectId {
+        ObjectId::hash(algorithm, Self::TYPE, &self.serialize())
+    }
+}
+
+/// A parsed object of any type.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum GitObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl GitObject {
+    pub(crate) fn parse(
+        kind: ObjectType,
+        data: &[u8],
+        algorithm: HashAlgorithm,
+    ) -> Result<Self, Error> {
+        Ok(match kind {
+            ObjectType::Blob => GitObject::Blob(Blob::parse(data, algorithm)?),
+            ObjectType::Tree => GitObject::Tree(Tree::parse(data, algorithm)?),
+            ObjectType::Commit => GitObject::Commit(Commit::parse(data, algorithm)?),
+            ObjectType::Tag => GitObject::Tag(Tag::parse(data, algorithm)?),
+        })
+    }
+
+    pub(crate) fn kind(&self) -> ObjectType {
+        match self {
+            GitObject::Blob(_) => ObjectType::Blob,
+            GitObject::Tree(_) => ObjectType::Tree,
+            GitObject::Commit(_) => ObjectType::Commit,
+            GitObject::Tag(_) => ObjectType::Tag,
+        }
+    }
+
+    pub(crate) fn serialize(&self) -> Vec<u8> {
+        match self {
+            GitObject::Blob(blob) => blob.serialize(),
+            GitObject::Tree(tree) => tree.serialize(),
+            GitObject::Commit(commit) => commit.serialize(),
+            GitObject::Tag(tag) => tag.serialize(),
+        }
+    }
+
+    pub(crate) fn id(&self, algorithm: HashAlgorithm) -> ObjectId {
+        ObjectId::hash(algorithm, self.kind(), &self.serialize())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Blob(pub(crate) Vec<u8>);
+
+impl Object for Blob {
+    const TYPE: ObjectType = ObjectType::Blob;
+
+    fn parse(data: &[u8], _algorithm: HashAlgorithm) -> Result<Self, Error> {
+        Ok(Self(data.to_vec()))
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Commit(pub(crate) Kvlm);
+
+impl Commit {
+    pub(crate) fn tree(&self) -> Result<ObjectId, Error> {
+        header_id(&self.0, ObjectType::Commit, "tree")
+    }
+
+    pub(crate) fn parents(&self) -> Result<Vec<ObjectId>, Error> {
+        self.0
+            .get_all("parent")
+            .map(|value| parse_id(value, ObjectType::Commit, "parent"))
+            .collect()
+    }
+
+    pub(crate) fn message(&self) -> &[u8] {
+        self.0.message()
+    }
+}
+
+impl Object for Commit {
+    const TYPE: ObjectType = ObjectType::Commit;
+
+    fn parse(data: &[u8], _algorithm: HashAlgorithm) -> Result<Self, Error> {
+        Kvlm::parse(data).map(Self)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Tag(pub(crate) Kvlm);
+
+impl Tag {
+    /// The tagged object.
+    pub(crate) fn object(&self) -> Result<ObjectId, Error> {
+        header_id(&self.0, ObjectType::Tag, "object")
+    }
+
+    pub(crate) fn target_type(&self) -> Result<ObjectType, Error> {
+        std::str::from_utf8(self.0.get("type").unwrap_or_default())
+            .ok()
+            .and_then(|kind| kind.parse().ok())
+            .ok_or(Error::InvalidHeader(ObjectType::Tag, "type"))
+    }
+
+    pub(crate) fn name(&self) -> Option<&[u8]> {
+        self.0.get("tag")
+    }
+}
+
+impl Object for Tag {
+    const TYPE: ObjectType = ObjectType::Tag;
+
+    fn parse(data: &[u8], _algorithm: HashAlgorithm) -> Result<Self, Error> {
+        Kvlm::parse(data).map(Self)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.0.serialize()
+    }
+}
+
+fn header_id(kvlm: &Kvlm, kind: ObjectType, key: &'static str) -> Result<ObjectId, Error> {
+    parse_id(
+        kvlm.get(key).ok_or(Error::InvalidHeader(kind, key))?,
+        kind,
+        key,
+    )
+}
+
+fn parse_id(value: &[u8], kind: ObjectType, key: &'static str) -> Result<ObjectId, Error> {
+    std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .ok_or(Error::InvalidHeader(kind, key))
+}
+
+/// The header that precedes an object's payload, both when hashing and on disk.
+pub(crate) fn header(kind: ObjectType, len: usize) -> Vec<u8> {
+    format!("{kind} {len}\0").into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_matches_git() {
+        // `printf 'hello\n' | git hash-object --stdin`
+        assert_eq!(
+            ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Blob, b"hello\n").to_string(),
+            "ce013625030ba8dba906f756967f9e9ca394464a"
+        );
+        // the empty tree
+        assert_eq!(
+            ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Tree, b"").to_string(),
+            "4b825dc642cb6eb9a060e54bf8d69288fbee4904"
+        );
+        // the same in a repository created with `git init --object-format=sha256`
+        assert_eq!(
+            ObjectId::hash(HashAlgorithm::Sha256, ObjectType::Blob, b"hello\n").to_string(),
+            "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4"
+        );
+        assert_eq!(
+            ObjectId::hash(HashAlgorithm::Sha256, ObjectType::Tree, b"").to_string(),
+            "6ef19b41225c5369f1c104d45d8d85efa9b057b53b14b4b9b939dd74decc5321"
+        );
+    }
+
+    #[test]
+    fn commit_round_trip() {
+        let data = b"tree 4b825dc642cb6eb9a060e54bf8d69288fbee4904\n\
+            parent ce013625030ba8dba906f756967f9e9ca394464a\n\
+            author A U Thor <author@example.com> 1112911993 -0700\n\
+            committer C O Mitter <committer@example.com> 1112911993 -0700\n\
+            \n\
+            initial\n";
+        let object = GitObject::parse(ObjectType::Commit, data, HashAlgorithm::Sha1).unwrap();
+        assert_eq!(object.serialize(), data);
+        let GitObject::Commit(commit) = object else {
+            panic!("expected a commit");
+        };
+        assert_eq!(
+            commit.tree(),
+            Ok(ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Tree, b""))
+        );
+        assert_eq!(
+            commit.parents(),
+            Ok(vec![ObjectId::hash(
+                HashAlgorithm::Sha1,
+                ObjectType::Blob,
+                b"hello\n"
+            )])
+        );
+        assert_eq!(commit.message(), b"initial\n");
+    }
+
+    #[test]
+    fn tag_headers() {
+        let tag = Tag::parse(
+            b"object ce013625030ba8dba906f756967f9e9ca394464a\ntype blob\ntag v1\n\nmsg\n",
+            HashAlgorithm::Sha1,
+        )
+        .unwrap();
+        assert_eq!(tag.target_type(), Ok(ObjectType::Blob));
+        assert_eq!(tag.name(), Some(&b"v1"[..]));
+        assert_eq!(
+            Tag::parse(b"type blob\n\n", HashAlgorithm::Sha1)
+                .unwrap()
+                .object(),
+            Err(Error::InvalidHeader(ObjectType::Tag, "object"))
+        );
+    }
+
+    #[test]
+    fn parse_id() {
+        let id: ObjectId = "ce013625030ba8dba906f756967f9e9ca394464a".parse().unwrap();
+        assert_eq!(id.loose_path().0, "ce");
+        assert_eq!(id.algorithm(), HashAlgorithm::Sha1);
+        let id: ObjectId = "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4"
+            .parse()
+            .unwrap();
+        assert_eq!(id.algorithm(), HashAlgorithm::Sha256);
+        assert!(!id.is_null());
+        assert!(ObjectId::null(HashAlgorithm::Sha256).is_null());
+        assert!("+e013625030ba8dba906f756967f9e9ca394464a"
+            .parse::<ObjectId>()
+            .is_err());
+        assert!("ce01".parse::<ObjectId>().is_err());
+        assert!("zz013625030ba8dba906f756967f9e9ca394464a"
+            .parse::<ObjectId>()
+            .is_err());
+    }
+}
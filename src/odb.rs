@@ -0,0 +1,490 @@
+use std::{
+    cell::OnceCell,
+    collections::{HashSet, VecDeque},
+    fs,
+    io::{Read as _, Write as _},
+    path::{Path, PathBuf},
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{
+    object::{self, header, GitObject, HashAlgorithm, ObjectId, ObjectType},
+    pack::{self, Pack},
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Object not found: {0}")]
+    NotFound(ObjectId),
+    #[error("Not a valid object name: {0}")]
+    InvalidName(String),
+    #[error("Short object ID {0} is ambiguous")]
+    Ambiguous(String),
+    #[error("Object {0} is corrupt: {1}")]
+    Corrupt(ObjectId, String),
+    #[error(transparent)]
+    Object(#[from] object::Error),
+    #[error(transparent)]
+    Pack(#[from] pack::Error),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// An object's type and payload, without the header.
+#[derive(Debug, PartialEq)]
+pub(crate) struct RawObject {
+    pub(crate) kind: ObjectType,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Reading and writing objects by id.
+pub(crate) trait ObjectStore {
+    /// The hash function that names the objects in this store.
+    fn algorithm(&self) -> HashAlgorithm;
+
+    fn read(&self, id: &ObjectId) -> Result<RawObject, Error>;
+
+    fn write(&self, kind: ObjectType, data: &[u8]) -> Result<ObjectId, Error>;
+
+    fn contains(&self, id: &ObjectId) -> bool;
+
+    fn read_object(&self, id: &ObjectId) -> Result<GitObject, Error> {
+        let raw = self.read(id)?;
+        Ok(GitObject::parse(raw.kind, &raw.data, self.algorithm())?)
+    }
+
+    fn write_object(&self, object: &GitObject) -> Result<ObjectId, Error> {
+        self.write(object.kind(), &object.serialize())
+    }
+
+    /// All objects whose hex name starts with `prefix`.
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>, Error>;
+
+    /// Resolves a full or abbreviated (at least 4 characters) hex name.
+    fn resolve(&self, name: &str) -> Result<ObjectId, Error> {
+        let full = name.parse::<ObjectId>().ok();
+        if let Some(id) = full.filter(|id| id.algorithm() == self.algorithm()) {
+            return Ok(id);
+        }
+        if name.len() < 4
+            || name.len() > self.algorithm().hex_len()
+            || !name.bytes().all(|b| b.is_ascii_hexdigit())
+        {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+
+        let mut found = self.find_prefix(&name.to_ascii_lowercase())?;
+        found.dedup();
+        match found.as_slice() {
+            [] => Err(Error::InvalidName(name.to_owned())),
+            [id] => Ok(*id),
+            _ => Err(Error::Ambiguous(name.to_owned())),
+        }
+    }
+
+    /// The shortest prefix of `id`'s hex name, at least `min_len` (and 4)
+    /// characters long, that names no other object.
+    fn abbreviate(&self, id: &ObjectId, min_len: usize) -> Result<String, Error> {
+        let hex = id.to_string();
+        let mut len = min_len.clamp(4, hex.len());
+        while len < hex.len()
+            && self
+                .find_prefix(&hex[..len])?
+                .iter()
+                .any(|other| other != id)
+        {
+            len += 1;
+        }
+        Ok(hex[..len].to_owned())
+    }
+}
+
+/// Zlib-compressed objects stored one per file under `objects/xx/yyyy...`.
+#[derive(Debug, PartialEq)]
+pub(crate) struct LooseObjects {
+    dir: PathBuf,
+    algorithm: HashAlgorithm,
+}
+
+impl LooseObjects {
+    pub(crate) fn new<P>(dir: P, algorithm: HashAlgorithm) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            algorithm,
+        }
+    }
+
+    pub(crate) fn path(&self, id: &ObjectId) -> PathBuf {
+        let (dir, file) = id.loose_path();
+        self.dir.join(dir).join(file)
+    }
+
+    /// Every loose object, sorted; files that are not named like objects are skipped.
+    pub(crate) fn ids(&self) -> Result<Vec<ObjectId>, Error> {
+        let mut ids = Vec::new();
+        for byte in 0..=u8::MAX {
+            ids.extend(self.find_prefix(&format!("{byte:02x}"))?);
+        }
+        Ok(ids)
+    }
+}
+
+impl ObjectStore for LooseObjects {
+    fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    fn read(&self, id: &ObjectId) -> Result<RawObject, Error> {
+        if id.algorithm() != self.algorithm {
+            return Err(Error::NotFound(*id));
+        }
+        let compressed = match fs::read(self.path(id)) {
+            Ok(compressed) => compressed,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(Error::NotFound(*id))
+            }
+            Err(err) => return Err(Error::Io(err.to_string())),
+        };
+        let mut raw = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut raw)
+            .map_err(|err| Error::Corrupt(*id, err.to_string()))?;
+
+        let corrupt = |msg: &str| Error::Corrupt(*id, msg.to_owned());
+        let nul = raw
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| corrupt("missing header terminator"))?;
+        let header = std::str::from_utf8(&raw[..nul]).map_err(|_| corrupt("invalid header"))?;
+        let (kind, len) = header
+            .split_once(' ')
+            .ok_or_else(|| corrupt("invalid header"))?;
+        let kind = kind.parse().map_err(|_| corrupt("unknown object type"))?;
+        let len: usize = len.parse().map_err(|_| corrupt("invalid object size"))?;
+        if len != raw.len() - nul - 1 {
+            return Err(corrupt("object size does not match header"));
+        }
+
+        raw.drain(..=nul);
+        Ok(RawObject { kind, data: raw })
+    }
+
+    fn write(&self, kind: ObjectType, data: &[u8]) -> Result<ObjectId, Error> {
+        let id = ObjectId::hash(self.algorithm, kind, data);
+        let path = self.path(&id);
+        if path.exists() {
+            return Ok(id);
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&header(kind, data.len()))
+            .and_then(|()| encoder.write_all(data))
+            .map_err(|err| Error::Io(err.to_string()))?;
+        let compressed = encoder.finish().map_err(|err| Error::Io(err.to_string()))?;
+
+        // write to a temporary file first so readers never see a partial object
+        let dir = path.parent().expect("loose object path has a parent");
+        fs::create_dir_all(dir).map_err(|err| Error::Io(err.to_string()))?;
+        let tmp = dir.join(format!("tmp_obj_{}", std::process::id()));
+        fs::write(&tmp, compressed).map_err(|err| Error::Io(err.to_string()))?;
+        fs::rename(&tmp, &path).map_err(|err| Error::Io(err.to_string()))?;
+
+        Ok(id)
+    }
+
+    fn contains(&self, id: &ObjectId) -> bool {
+        id.algorithm() == self.algorithm && self.path(id).is_file()
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>, Error> {
+        let (dir, rest) = prefix.split_at(2);
+        let entries = match fs::read_dir(self.dir.join(dir)) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(Error::Io(err.to_string())),
+        };
+
+        let mut found = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::Io(err.to_string()))?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else { continue };
+            if name.starts_with(rest) {
+                let id = format!("{dir}{name}").parse::<ObjectId>().ok();
+                found.extend(id.filter(|id| id.algorithm() == self.algorithm));
+            }
+        }
+        found.sort();
+        Ok(found)
+    }
+}
+
+/// Everything under an `objects/` directory: packs first, then loose objects,
+/// then the same in each alternate object directory.
+///
+/// New objects are always written loose; packs and alternates are found when first needed.
+#[derive(Debug)]
+pub(crate) struct ObjectDatabase {
+    dir: PathBuf,
+    loose: LooseObjects,
+    packs: OnceCell<Vec<Pack>>,
+    /// Alternates given from outside, like `GIT_ALTERNATE_OBJECT_DIRECTORIES`.
+    extra_alternates: Vec<PathBuf>,
+    alternates: OnceCell<Vec<ObjectDatabase>>,
+}
+
+impl ObjectDatabase {
+    pub(crate) fn new<P>(dir: P, algorithm: HashAlgorithm) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        Self {
+            dir: dir.as_ref().to_owned(),
+            loose: LooseObjects::new(&dir, algorithm),
+            packs: OnceCell::new(),
+            extra_alternates: Vec::new(),
+            alternates: OnceCell::new(),
+        }
+    }
+
+    /// Also searches `dirs`, ahead of those listed in `info/alternates`.
+    pub(crate) fn with_alternates(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.extra_alternates = dirs;
+        self.alternates = OnceCell::new();
+        self
+    }
+
+    pub(crate) fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub(crate) fn loose(&self) -> &LooseObjects {
+        &self.loose
+    }
+
+    pub(crate) fn packs(&self) -> Result<&[Pack], Error> {
+        if let Some(packs) = self.packs.get() {
+            return Ok(packs);
+        }
+        let packs = pack::find_indexes(&self.dir)?
+            .into_iter()
+            .map(|idx| Pack::open(idx, self.algorithm()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(self.packs.get_or_init(|| packs))
+    }
+
+    /// Every alternate object directory, including the alternates of alternates.
+    ///
+    /// Each directory appears once, in the order found, and never this one;
+    /// directories that do not exist are skipped.
+    pub(crate) fn alternates(&self) -> Result<&[ObjectDatabase], Error> {
+        if let Some(alternates) = self.alternates.get() {
+            return Ok(alternates);
+        }
+        let mut seen = HashSet::new();
+        seen.extend(fs::canonicalize(&self.dir).ok());
+        let mut pending: VecDeque<_> = self.extra_alternates.iter().cloned().collect();
+        pending.extend(read_alternates(&self.dir)?);
+
+        let mut alternates = Vec::new();
+        while let Some(dir) = pending.pop_front() {
+            let Ok(dir) = fs::canonicalize(&dir) else {
+                continue;
+            };
+            // alternates that list each other would otherwise be searched forever
+            if !seen.insert(dir.clone()) {
+                continue;
+            }
+            pending.extend(read_alternates(&dir)?);
+            alternates.push(ObjectDatabase::new(dir, self.algorithm()));
+        }
+        Ok(self.alternates.get_or_init(|| alternates))
+    }
+
+    /// Reads `id` from this directory alone.
+    fn read_local(&self, id: &ObjectId) -> Result<RawObject, Error> {
+        // a REF_DELTA base may live in another pack or be loose
+        let external = |base: &ObjectId| self.read(base).ok();
+        for pack in self.packs()? {
+            if let Some(raw) = pack.read(id, &external)? {
+                return Ok(raw);
+            }
+        }
+        self.loose.read(id)
+    }
+
+    fn contains_local(&self, id: &ObjectId) -> bool {
+        let packed = self
+            .packs()
+            .is_ok_and(|packs| packs.iter().any(|pack| pack.index().find(id).is_some()));
+        packed || self.loose.contains(id)
+    }
+
+    fn find_prefix_local(&self, prefix: &str) -> Result<Vec<ObjectId>, Error> {
+        let mut found = self.loose.find_prefix(prefix)?;
+        for pack in self.packs()? {
+            found.extend(pack.index().find_prefix(prefix));
+        }
+        Ok(found)
+    }
+}
+
+/// The directories listed in `<dir>/info/alternates`, relative ones resolved against `dir`.
+fn read_alternates(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let contents = match fs::read_to_string(dir.join("info/alternates")) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::Io(err.to_string())),
+    };
+    Ok(contents
+        .lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+impl ObjectStore for ObjectDatabase {
+    fn algorithm(&self) -> HashAlgorithm {
+        self.loose.algorithm()
+    }
+
+    fn read(&self, id: &ObjectId) -> Result<RawObject, Error> {
+        match self.read_local(id) {
+            Err(Error::NotFound(_)) => {}
+            result => return result,
+        }
+        for alternate in self.alternates()? {
+            match alternate.read_local(id) {
+                Err(Error::NotFound(_)) => {}
+                result => return result,
+            }
+        }
+        Err(Error::NotFound(*id))
+    }
+
+    fn write(&self, kind: ObjectType, data: &[u8]) -> Result<ObjectId, Error> {
+        self.loose.write(kind, data)
+    }
+
+    fn contains(&self, id: &ObjectId) -> bool {
+        self.contains_local(id)
+            || self.alternates().is_ok_and(|alternates| {
+                alternates
+                    .iter()
+                    .any(|alternate| alternate.contains_local(id))
+            })
+    }
+
+    fn find_prefix(&self, prefix: &str) -> Result<Vec<ObjectId>, Error> {
+        let mut found = self.find_prefix_local(prefix)?;
+        for alternate in self.alternates()? {
+            found.extend(alternate.find_prefix_local(prefix)?);
+        }
+        found.sort();
+        found.dedup();
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let tempdir = TempDir::new().unwrap();
+        let store = LooseObjects::new(tempdir.path(), HashAlgorithm::Sha1);
+        let id = store.write(ObjectType::Blob, b"hello\n").unwrap();
+        assert_eq!(id.to_string(), "ce013625030ba8dba906f756967f9e9ca394464a");
+        assert!(tempdir
+            .path()
+            .join("ce/013625030ba8dba906f756967f9e9ca394464a")
+            .is_file());
+        assert_eq!(
+            store.read(&id).unwrap(),
+            RawObject {
+                kind: ObjectType::Blob,
+                data: b"hello\n".to_vec()
+            }
+        );
+        assert_eq!(store.resolve("ce0136"), Ok(id));
+        assert_eq!(
+            store.resolve("ce0137"),
+            Err(Error::InvalidName("ce0137".to_owned()))
+        );
+
+        assert_eq!(store.abbreviate(&id, 4), Ok("ce01".to_owned()));
+        // another object sharing the first six characters
+        let other = format!("ce/0136{}", "f".repeat(34));
+        fs::write(tempdir.path().join(other), b"").unwrap();
+        assert_eq!(store.abbreviate(&id, 4), Ok("ce01362".to_owned()));
+        assert_eq!(store.abbreviate(&id, 40), Ok(id.to_string()));
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let tempdir = TempDir::new().unwrap();
+        let store = LooseObjects::new(tempdir.path(), HashAlgorithm::Sha1);
+        let id = ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Blob, b"hello\n");
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"blob 7\0hello\n").unwrap();
+        fs::create_dir_all(store.path(&id).parent().unwrap()).unwrap();
+        fs::write(store.path(&id), encoder.finish().unwrap()).unwrap();
+        assert!(matches!(store.read(&id), Err(Error::Corrupt(..))));
+    }
+
+    #[test]
+    fn alternates() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = |name: &str| tempdir.path().join(name);
+        for name in ["main", "shared", "reference", "extra"] {
+            fs::create_dir_all(dir(name).join("info")).unwrap();
+        }
+        // relative entries are relative to the objects directory, and the
+        // reference lists the main directory back
+        fs::write(dir("main/info/alternates"), "# comment\n../shared\n").unwrap();
+        let shared = format!("{}\n../missing\n", dir("reference").display());
+        fs::write(dir("shared/info/alternates"), shared).unwrap();
+        fs::write(dir("reference/info/alternates"), "../main\n../shared\n").unwrap();
+
+        let algorithm = HashAlgorithm::Sha1;
+        let id = ObjectDatabase::new(dir("reference"), algorithm)
+            .write(ObjectType::Blob, b"hello\n")
+            .unwrap();
+        let extra = ObjectDatabase::new(dir("extra"), algorithm)
+            .write(ObjectType::Blob, b"extra\n")
+            .unwrap();
+
+        let objects =
+            ObjectDatabase::new(dir("main"), algorithm).with_alternates(vec![dir("extra")]);
+        let alternates: Vec<_> = objects
+            .alternates()
+            .unwrap()
+            .iter()
+            .map(|alternate| alternate.dir().to_owned())
+            .collect();
+        let canonical = |name| fs::canonicalize(dir(name)).unwrap();
+        assert_eq!(
+            alternates,
+            [
+                canonical("extra"),
+                canonical("shared"),
+                canonical("reference")
+            ]
+        );
+
+        assert!(objects.contains(&id) && objects.contains(&extra));
+        assert_eq!(objects.read(&id).unwrap().data, b"hello\n");
+        assert_eq!(objects.resolve(&id.to_string()[..6]), Ok(id));
+        // new objects are never written to an alternate
+        assert_eq!(objects.write(ObjectType::Blob, b"hello\n"), Ok(id));
+        assert!(objects.loose().contains(&id));
+    }
+}
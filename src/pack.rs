@@ -0,0 +1,972 @@
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, Read as _, Seek as _, SeekFrom, Write as _},
+    path::{Path, PathBuf},
+};
+
+use flate2::{bufread::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+
+use crate::{
+    delta,
+    object::{HashAlgorithm, ObjectId, ObjectType},
+    odb::{self, ObjectStore, RawObject},
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("{0} is corrupt: {1}")]
+    Corrupt(PathBuf, String),
+    #[error("Unsupported pack index version {1} in {0}")]
+    UnsupportedIndexVersion(PathBuf, u32),
+    #[error("Delta base {0} is missing")]
+    MissingBase(ObjectId),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+const INDEX_MAGIC: &[u8] = b"\xfftOc";
+const PACK_MAGIC: &[u8] = b"PACK";
+/// Header and fanout table of a version 2 index.
+const INDEX_HEADER_LEN: usize = 8 + 256 * 4;
+/// Set on a 32-bit offset that is really an index into the 64-bit offset table.
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+/// Deeper chains than this are treated as corruption rather than followed.
+const MAX_DELTA_DEPTH: usize = 10_000;
+/// How much inflated delta base data to keep around, like `core.deltaBaseCacheLimit`.
+const DELTA_BASE_CACHE_LIMIT: usize = 96 << 20;
+
+/// The `.idx` file that maps object ids to offsets in a pack (version 2).
+///
+/// Ids are sorted, and the fanout table holds, for each first byte, how many
+/// ids start with a byte at most that large, which narrows every lookup to a
+/// binary search over one bucket.
+#[derive(Debug)]
+pub(crate) struct PackIndex {
+    algorithm: HashAlgorithm,
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl PackIndex {
+    pub(crate) fn read<P>(path: P, algorithm: HashAlgorithm) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|err| Error::Io(err.to_string()))?;
+        Self::parse(data, algorithm).map_err(|err| err.with_path(path))
+    }
+
+    /// Parses an index; errors name no file yet, see [`PackIndex::read`].
+    pub(crate) fn parse(data: Vec<u8>, algorithm: HashAlgorithm) -> Result<Self, Error> {
+        let corrupt = |msg: &str| Error::Corrupt(PathBuf::new(), msg.to_owned());
+        if data.len() < INDEX_HEADER_LEN || &data[..4] != INDEX_MAGIC {
+            return Err(corrupt("bad index signature"));
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        if version != 2 {
+            return Err(Error::UnsupportedIndexVersion(PathBuf::new(), version));
+        }
+
+        let mut index = Self {
+            algorithm,
+            data,
+            len: 0,
+        };
+        if (1..256).any(|byte| index.fanout(byte - 1) > index.fanout(byte)) {
+            return Err(corrupt("fanout table is not sorted"));
+        }
+        index.len = index.fanout(255);
+        let tables = index.len * (algorithm.len() + 4 + 4);
+        let min_len = INDEX_HEADER_LEN + tables + 2 * algorithm.len();
+        if index.data.len() < min_len || !(index.data.len() - min_len).is_multiple_of(8) {
+            return Err(corrupt("index has the wrong size"));
+        }
+        let checksum = algorithm.digest(&[&index.data[..index.data.len() - algorithm.len()]]);
+        if checksum.as_bytes() != &index.data[index.data.len() - algorithm.len()..] {
+            return Err(corrupt("index checksum mismatch"));
+        }
+        Ok(index)
+    }
+
+    /// The number of objects in the pack.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The `n`th id in sorted order.
+    pub(crate) fn id(&self, n: usize) -> ObjectId {
+        let len = self.algorithm.len();
+        let start = INDEX_HEADER_LEN + n * len;
+        ObjectId::from_bytes(&self.data[start..start + len]).expect("slice has the id length")
+    }
+
+    pub(crate) fn ids(&self) -> impl Iterator<Item = ObjectId> + '_ {
+        (0..self.len).map(|n| self.id(n))
+    }
+
+    /// The CRC-32 of the `n`th object's packed data.
+    pub(crate) fn crc32(&self, n: usize) -> u32 {
+        self.word(INDEX_HEADER_LEN + self.len * self.algorithm.len() + n * 4)
+    }
+
+    /// Where the `n`th object starts in the pack.
+    pub(crate) fn offset(&self, n: usize) -> u64 {
+        let offsets = INDEX_HEADER_LEN + self.len * (self.algorithm.len() + 4);
+        let offset = self.word(offsets + n * 4);
+        if offset & LARGE_OFFSET_FLAG == 0 {
+            return offset.into();
+        }
+        let large = offsets + self.len * 4 + (offset & !LARGE_OFFSET_FLAG) as usize * 8;
+        self.data.get(large..large + 8).map_or(u64::MAX, |bytes| {
+            u64::from_be_bytes(bytes.try_into().unwrap())
+        })
+    }
+
+    /// The position of `id` in sorted order, if the pack has it.
+    pub(crate) fn find(&self, id: &ObjectId) -> Option<usize> {
+        if id.algorithm() != self.algorithm {
+            return None;
+        }
+        let first = id.as_bytes()[0] as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout(first - 1)
+        };
+        let end = self.fanout(first);
+        let mut range = start..end;
+        while !range.is_empty() {
+            let mid = range.start + range.len() / 2;
+            match self.id(mid).cmp(id) {
+                std::cmp::Ordering::Less => range.start = mid + 1,
+                std::cmp::Ordering::Greater => range.end = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    /// All ids whose hex name starts with `prefix`, which must be at least two characters.
+    pub(crate) fn find_prefix(&self, prefix: &str) -> Vec<ObjectId> {
+        let Ok(first) = u8::from_str_radix(&prefix[..2], 16) else {
+            return Vec::new();
+        };
+        let first = first as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout(first - 1)
+        };
+        (start..self.fanout(first))
+            .map(|n| self.id(n))
+            .filter(|id| id.to_string().starts_with(prefix))
+            .collect()
+    }
+
+    /// The checksum of the pack this index describes.
+    pub(crate) fn pack_checksum(&self) -> &[u8] {
+        let len = self.algorithm.len();
+        let end = self.data.len() - len;
+        &self.data[end - len..end]
+    }
+
+    fn fanout(&self, byte: usize) -> usize {
+        self.word(8 + byte * 4) as usize
+    }
+
+    fn word(&self, at: usize) -> u32 {
+        u32::from_be_bytes(self.data[at..at + 4].try_into().unwrap())
+    }
+}
+
+/// How one entry of a pack is stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum EntryKind {
+    /// A whole object.
+    Base(ObjectType),
+    /// A delta against the entry at this offset in the same pack.
+    OfsDelta(u64),
+    /// A delta against the object with this id.
+    RefDelta(ObjectId),
+}
+
+/// The header of one entry in a pack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PackEntry {
+    pub(crate) offset: u64,
+    pub(crate) kind: EntryKind,
+    /// The inflated size of the object, or of the delta.
+    pub(crate) size: usize,
+    /// Where the compressed data starts, just after the header.
+    pub(crate) data_offset: u64,
+}
+
+/// A `.pack` file and its index.
+#[derive(Debug)]
+pub(crate) struct Pack {
+    path: PathBuf,
+    index: PackIndex,
+    file: File,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Pack {
+    /// Opens the pack described by the index at `idx`.
+    pub(crate) fn open<P>(idx: P, algorithm: HashAlgorithm) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let index = PackIndex::read(&idx, algorithm)?;
+        let path = idx.as_ref().with_extension("pack");
+        let mut file = File::open(&path).map_err(|err| Error::Io(err.to_string()))?;
+
+        let mut header = [0; 12];
+        file.read_exact(&mut header)
+            .map_err(|err| Error::Io(err.to_string()))?;
+        let corrupt = |msg: &str| Error::Corrupt(path.clone(), msg.to_owned());
+        if &header[..4] != PACK_MAGIC {
+            return Err(corrupt("bad pack signature"));
+        }
+        if !matches!(u32::from_be_bytes(header[4..8].try_into().unwrap()), 2 | 3) {
+            return Err(corrupt("unsupported pack version"));
+        }
+        if u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize != index.len() {
+            return Err(corrupt("object count does not match the index"));
+        }
+
+        Ok(Self {
+            path,
+            index,
+            file,
+            cache: RefCell::default(),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// Checks the pack's trailing checksum against its contents and its index.
+    pub(crate) fn verify_checksum(&self) -> Result<(), Error> {
+        let data = fs::read(&self.path).map_err(|err| Error::Io(err.to_string()))?;
+        let len = self.index.algorithm.len();
+        let corrupt = |msg: &str| Error::Corrupt(self.path.clone(), msg.to_owned());
+        if data.len() < 12 + len {
+            return Err(corrupt("truncated pack"));
+        }
+        let (contents, trailer) = data.split_at(data.len() - len);
+        if self.index.algorithm.digest(&[contents]).as_bytes() != trailer {
+            return Err(corrupt("pack checksum mismatch"));
+        }
+        if trailer != self.index.pack_checksum() {
+            return Err(corrupt("pack checksum does not match its index"));
+        }
+        Ok(())
+    }
+
+    /// Reads `id` if this pack has it; `external` finds delta bases stored elsewhere.
+    pub(crate) fn read(
+        &self,
+        id: &ObjectId,
+        external: &dyn Fn(&ObjectId) -> Option<RawObject>,
+    ) -> Result<Option<RawObject>, Error> {
+        match self.index.find(id) {
+            Some(n) => self.read_at(self.index.offset(n), external).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the object at `offset`, applying any chain of deltas.
+    pub(crate) fn read_at(
+        &self,
+        offset: u64,
+        external: &dyn Fn(&ObjectId) -> Option<RawObject>,
+    ) -> Result<RawObject, Error> {
+        // walk down to a whole object, remembering the deltas on the way
+        let mut chain = Vec::new();
+        let mut base_offset = Some(offset);
+        let (kind, mut data) = loop {
+            let offset = base_offset.expect("only set to None when leaving the loop");
+            if let Some(cached) = self.cache.borrow().get(offset) {
+                break cached;
+            }
+            if chain.len() > MAX_DELTA_DEPTH {
+                return Err(self.corrupt("delta chain is too deep"));
+            }
+            let entry = self.entry(offset)?;
+            match entry.kind {
+                EntryKind::Base(kind) => break (kind, self.inflate(&entry)?),
+                EntryKind::OfsDelta(base) => base_offset = Some(base),
+                EntryKind::RefDelta(base) => match self.index.find(&base) {
+                    Some(n) => base_offset = Some(self.index.offset(n)),
+                    None => {
+                        let raw = external(&base).ok_or(Error::MissingBase(base))?;
+                        chain.push(entry);
+                        base_offset = None;
+                        break (raw.kind, raw.data);
+                    }
+                },
+            }
+            chain.push(entry);
+        };
+
+        // then apply them from the innermost out
+        while let Some(entry) = chain.pop() {
+            if let Some(offset) = base_offset {
+                // bases tend to be shared by many deltas
+                self.cache.borrow_mut().insert(offset, kind, &data);
+            }
+            let delta = self.inflate(&entry)?;
+            data = delta::apply(&data, &delta)
+                .ok_or_else(|| self.corrupt(&format!("bad delta at offset {}", entry.offset)))?;
+            base_offset = Some(entry.offset);
+        }
+        Ok(RawObject { kind, data })
+    }
+
+    /// Reads the header of the entry at `offset`.
+    pub(crate) fn entry(&self, offset: u64) -> Result<PackEntry, Error> {
+        let mut reader = self.reader(offset)?;
+        let mut next = || -> Result<u8, Error> {
+            let mut byte = [0];
+            reader
+                .read_exact(&mut byte)
+                .map_err(|err| Error::Io(err.to_string()))?;
+            Ok(byte[0])
+        };
+
+        // the type, then the size as a varint whose first byte holds four bits
+        let mut byte = next()?;
+        let kind = (byte >> 4) & 0x7;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = next()?;
+            size |= ((byte & 0x7f) as usize)
+                .checked_shl(shift)
+                .ok_or_else(|| self.corrupt("entry size overflows"))?;
+            shift += 7;
+        }
+        let mut header_len = 1 + (shift as u64 - 4) / 7;
+
+        let kind = match kind {
+            1 => EntryKind::Base(ObjectType::Commit),
+            2 => EntryKind::Base(ObjectType::Tree),
+            3 => EntryKind::Base(ObjectType::Blob),
+            4 => EntryKind::Base(ObjectType::Tag),
+            OFS_DELTA => {
+                // a big-endian varint where each continuation also adds one
+                let mut byte = next()?;
+                let mut distance = (byte & 0x7f) as u64;
+                header_len += 1;
+                while byte & 0x80 != 0 {
+                    byte = next()?;
+                    header_len += 1;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|distance| distance.checked_mul(128))
+                        .ok_or_else(|| self.corrupt("delta base offset overflows"))?
+                        | (byte & 0x7f) as u64;
+                }
+                let base = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance != 0)
+                    .ok_or_else(|| self.corrupt("delta base offset is out of range"))?;
+                EntryKind::OfsDelta(base)
+            }
+            REF_DELTA => {
+                let mut id = vec![0; self.index.algorithm.len()];
+                for byte in &mut id {
+                    *byte = next()?;
+                }
+                header_len += id.len() as u64;
+                EntryKind::RefDelta(ObjectId::from_bytes(&id).expect("id has the right length"))
+            }
+            kind => return Err(self.corrupt(&format!("unknown entry type {kind}"))),
+        };
+
+        Ok(PackEntry {
+            offset,
+            kind,
+            size,
+            data_offset: offset + header_len,
+        })
+    }
+
+    /// The inflated data of an entry: the object itself or its delta.
+    pub(crate) fn inflate(&self, entry: &PackEntry) -> Result<Vec<u8>, Error> {
+        let mut data = Vec::with_capacity(entry.size);
+        // never inflate more than the header promised
+        ZlibDecoder::new(self.reader(entry.data_offset)?)
+            .take(entry.size as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|err| self.corrupt(&format!("offset {}: {err}", entry.offset)))?;
+        if data.len() != entry.size {
+            return Err(self.corrupt(&format!("offset {}: size mismatch", entry.offset)));
+        }
+        Ok(data)
+    }
+
+    fn reader(&self, offset: u64) -> Result<BufReader<&File>, Error> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset))
+            .map_err(|err| Error::Io(err.to_string()))?;
+        Ok(BufReader::new(file))
+    }
+
+    fn corrupt(&self, msg: &str) -> Error {
+        Error::Corrupt(self.path.clone(), msg.to_owned())
+    }
+}
+
+impl Error {
+    fn with_path(self, path: &Path) -> Self {
+        match self {
+            Error::Corrupt(_, msg) => Error::Corrupt(path.to_owned(), msg),
+            Error::UnsupportedIndexVersion(_, version) => {
+                Error::UnsupportedIndexVersion(path.to_owned(), version)
+            }
+            err => err,
+        }
+    }
+}
+
+/// Inflated delta bases by pack offset, dropped wholesale when they grow too large.
+#[derive(Debug, Default)]
+struct DeltaBaseCache {
+    entries: HashMap<u64, (ObjectType, Vec<u8>)>,
+    size: usize,
+}
+
+impl DeltaBaseCache {
+    fn get(&self, offset: u64) -> Option<(ObjectType, Vec<u8>)> {
+        self.entries.get(&offset).cloned()
+    }
+
+    fn insert(&mut self, offset: u64, kind: ObjectType, data: &[u8]) {
+        if data.len() > DELTA_BASE_CACHE_LIMIT || self.entries.contains_key(&offset) {
+            return;
+        }
+        if self.size + data.len() > DELTA_BASE_CACHE_LIMIT {
+            self.entries.clear();
+            self.size = 0;
+        }
+        self.size += data.len();
+        self.entries.insert(offset, (kind, data.to_vec()));
+    }
+}
+
+/// The `.idx` files under `objects/pack`, newest first as git searches them.
+pub(crate) fn find_indexes<P>(objects: P) -> Result<Vec<PathBuf>, Error>
+where
+    P: AsRef<Path>,
+{
+    let entries = match fs::read_dir(objects.as_ref().join("pack")) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(Error::Io(err.to_string())),
+    };
+    let mut indexes = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::Io(err.to_string()))?;
+        let path = entry.path();
+        // an index whose pack is gone is left over from an interrupted repack
+        if path.extension().is_some_and(|ext| ext == "idx") && path.with_extension("pack").is_file()
+        {
+            let modified = entry.metadata().and_then(|meta| meta.modified()).ok();
+            indexes.push((modified, path));
+        }
+    }
+    indexes.sort_by(|a, b| b.cmp(a));
+    Ok(indexes.into_iter().map(|(_, path)| path).collect())
+}
+
+/// One object's record in a pack index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct PackIndexEntry {
+    pub(crate) id: ObjectId,
+    pub(crate) offset: u64,
+    pub(crate) crc32: u32,
+}
+
+/// Builds a version 2 index, using the 64-bit table for offsets that need it.
+pub(crate) fn serialize_index(entries: &[PackIndexEntry], pack_checksum: &ObjectId) -> Vec<u8> {
+    let mut entries = entries.to_vec();
+    entries.sort();
+
+    let mut out = INDEX_MAGIC.to_vec();
+    out.extend_from_slice(&2u32.to_be_bytes());
+    let mut count = 0;
+    for byte in 0..=u8::MAX {
+        count += entries
+            .iter()
+            .filter(|e| e.id.as_bytes()[0] == byte)
+            .count();
+        out.extend_from_slice(&(count as u32).to_be_bytes());
+    }
+    for entry in &entries {
+        out.extend_from_slice(entry.id.as_bytes());
+    }
+    for entry in &entries {
+        out.extend_from_slice(&entry.crc32.to_be_bytes());
+    }
+    let mut large = Vec::new();
+    for entry in &entries {
+        let word = match u32::try_from(entry.offset) {
+            Ok(offset) if offset & LARGE_OFFSET_FLAG == 0 => offset,
+            _ => {
+                large.push(entry.offset);
+                (large.len() - 1) as u32 | LARGE_OFFSET_FLAG
+            }
+        };
+        out.extend_from_slice(&word.to_be_bytes());
+    }
+    for offset in large {
+        out.extend_from_slice(&offset.to_be_bytes());
+    }
+    out.extend_from_slice(pack_checksum.as_bytes());
+    let checksum = pack_checksum.algorithm().digest(&[&out]);
+    out.extend_from_slice(checksum.as_bytes());
+    out
+}
+
+/// Tuning for [`build`], like git's `pack.window` and `pack.depth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PackOptions {
+    /// How many of the preceding objects are tried as delta bases for each object.
+    pub(crate) window: usize,
+    /// The longest delta chain allowed.
+    pub(crate) depth: usize,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        Self {
+            window: 10,
+            depth: 50,
+        }
+    }
+}
+
+/// An object to pack, with a hash of the path it was found at, see [`name_hash`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PackInput {
+    pub(crate) id: ObjectId,
+    pub(crate) name_hash: u32,
+}
+
+/// A pack and its index, not yet stored anywhere.
+#[derive(Debug)]
+pub(crate) struct NewPack {
+    pub(crate) data: Vec<u8>,
+    pub(crate) index: Vec<u8>,
+    /// The pack's trailing checksum, which also names it.
+    pub(crate) checksum: ObjectId,
+}
+
+impl NewPack {
+    /// Stores the pack as `<base>-<checksum>.pack`, returning the path of its index.
+    ///
+    /// The index is moved into place last, so readers never find it without its pack.
+    pub(crate) fn save<P>(&self, base: P) -> Result<PathBuf, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let io = |err: io::Error| Error::Io(err.to_string());
+        let mut base = base.as_ref().as_os_str().to_owned();
+        base.push(format!("-{}", self.checksum));
+        let base = PathBuf::from(base);
+        let dir = match base.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::create_dir_all(dir).map_err(io)?;
+        for (data, extension) in [(&self.data, "pack"), (&self.index, "idx")] {
+            let tmp = dir.join(format!("tmp_{extension}_{}", std::process::id()));
+            fs::write(&tmp, data).map_err(io)?;
+            fs::rename(&tmp, base.with_extension(extension)).map_err(io)?;
+        }
+        Ok(base.with_extension("idx"))
+    }
+}
+
+/// Git's hash of the path an object was found at, which groups likely delta
+/// pairs together: its last characters weigh the most, so files with the same
+/// extension sort close to each other.
+pub(crate) fn name_hash(name: &[u8]) -> u32 {
+    name.iter()
+        .filter(|c| !c.is_ascii_whitespace())
+        .fold(0u32, |hash, &c| {
+            (hash >> 2).wrapping_add(u32::from(c) << 24)
+        })
+}
+
+/// An object while its pack is being built.
+struct Packing {
+    id: ObjectId,
+    name_hash: u32,
+    kind: ObjectType,
+    data: Vec<u8>,
+    /// The object this is stored as a delta against, with the delta.
+    base: Option<(usize, Vec<u8>)>,
+    depth: usize,
+}
+
+/// Packs `objects`, in order and without duplicates, into a new pack.
+///
+/// Objects are sorted by type, name hash and descending size, and each is
+/// tried as a delta against the `window` objects before it. Larger objects
+/// therefore tend to be stored whole, with smaller versions as deltas.
+pub(crate) fn build(
+    store: &impl ObjectStore,
+    objects: &[PackInput],
+    options: &PackOptions,
+) -> Result<NewPack, odb::Error> {
+    let mut seen = HashSet::new();
+    let mut packing = Vec::new();
+    for input in objects.iter().filter(|input| seen.insert(input.id)) {
+        let raw = store.read(&input.id)?;
+        packing.push(Packing {
+            id: input.id,
+            name_hash: input.name_hash,
+            kind: raw.kind,
+            data: raw.data,
+            base: None,
+            depth: 0,
+        });
+    }
+    find_deltas(&mut packing, options);
+
+    let mut data = PACK_MAGIC.to_vec();
+    data.extend_from_slice(&2u32.to_be_bytes());
+    data.extend_from_slice(&(packing.len() as u32).to_be_bytes());
+    let mut offsets = vec![None; packing.len()];
+    let mut entries = Vec::with_capacity(packing.len());
+    for n in 0..packing.len() {
+        // bases must come first, since offset deltas only point backwards
+        let mut chain = Vec::new();
+        let mut next = Some(n);
+        while let Some(n) = next.filter(|&n| offsets[n].is_none()) {
+            chain.push(n);
+            next = packing[n].base.as_ref().map(|(base, _)| *base);
+        }
+        for &n in chain.iter().rev() {
+            let offset = data.len() as u64;
+            let object = &packing[n];
+            let base = object
+                .base
+                .as_ref()
+                .map(|(base, delta)| (offsets[*base].expect("bases are written first"), delta));
+            write_entry(&mut data, object.kind, &object.data, offset, base);
+            let mut crc = Crc::new();
+            crc.update(&data[offset as usize..]);
+            offsets[n] = Some(offset);
+            entries.push(PackIndexEntry {
+                id: object.id,
+                offset,
+                crc32: crc.sum(),
+            });
+        }
+    }
+
+    let checksum = store.algorithm().digest(&[&data]);
+    data.extend_from_slice(checksum.as_bytes());
+    Ok(NewPack {
+        index: serialize_index(&entries, &checksum),
+        data,
+        checksum,
+    })
+}
+
+fn find_deltas(packing: &mut [Packing], options: &PackOptions) {
+    let mut order: Vec<usize> = (0..packing.len()).collect();
+    order.sort_by_key(|&n| {
+        let object = &packing[n];
+        (
+            type_number(object.kind),
+            object.name_hash,
+            Reverse(object.data.len()),
+        )
+    });
+
+    for (pos, &target) in order.iter().enumerate() {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        for &base in order[pos.saturating_sub(options.window)..pos].iter().rev() {
+            let (candidate, object) = (&packing[base], &packing[target]);
+            if candidate.kind != object.kind || candidate.depth >= options.depth {
+                continue;
+            }
+            // a delta only pays off when it is well below the object's own size
+            let limit = best
+                .as_ref()
+                .map_or(object.data.len() / 2, |(_, delta)| delta.len());
+            if object.data.len().saturating_sub(candidate.data.len()) >= limit {
+                continue;
+            }
+            let delta = delta::create(&candidate.data, &object.data);
+            if delta.len() < limit {
+                best = Some((base, delta));
+            }
+        }
+        if let Some((base, delta)) = best {
+            packing[target].depth = packing[base].depth + 1;
+            packing[target].base = Some((base, delta));
+        }
+    }
+}
+
+/// Appends one entry: its header, any delta base offset, and the compressed payload.
+fn write_entry(
+    out: &mut Vec<u8>,
+    kind: ObjectType,
+    data: &[u8],
+    offset: u64,
+    base: Option<(u64, &Vec<u8>)>,
+) {
+    let (type_number, payload) = match base {
+        Some((_, delta)) => (OFS_DELTA, delta.as_slice()),
+        None => (type_number(kind), data),
+    };
+
+    let mut size = payload.len();
+    let mut byte = (type_number << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size != 0 {
+        out.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    out.push(byte);
+
+    if let Some((base, _)) = base {
+        let mut distance = offset - base;
+        let mut bytes = vec![(distance & 0x7f) as u8];
+        distance >>= 7;
+        while distance != 0 {
+            distance -= 1;
+            bytes.push(0x80 | (distance & 0x7f) as u8);
+            distance >>= 7;
+        }
+        out.extend(bytes.iter().rev());
+    }
+
+    let mut encoder = ZlibEncoder::new(out, Compression::default());
+    encoder
+        .write_all(payload)
+        .and_then(|()| encoder.finish().map(|_| ()))
+        .expect("writing to a Vec cannot fail");
+}
+
+const OFS_DELTA: u8 = 6;
+const REF_DELTA: u8 = 7;
+
+/// The number that identifies an object type in entry headers.
+fn type_number(kind: ObjectType) -> u8 {
+    match kind {
+        ObjectType::Commit => 1,
+        ObjectType::Tree => 2,
+        ObjectType::Blob => 3,
+        ObjectType::Tag => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::odb::LooseObjects;
+
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha1;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn index_bytes(entries: Vec<(ObjectId, u64)>, pack_checksum: &ObjectId) -> Vec<u8> {
+        let entries: Vec<_> = entries
+            .into_iter()
+            .map(|(id, offset)| PackIndexEntry {
+                id,
+                offset,
+                crc32: 0,
+            })
+            .collect();
+        serialize_index(&entries, pack_checksum)
+    }
+
+    #[test]
+    fn deltas() {
+        let base = b"hello world\n";
+        let changed = b"hello there world\n";
+        let base_id = ObjectId::hash(ALGORITHM, ObjectType::Blob, base);
+        let changed_id = ObjectId::hash(ALGORITHM, ObjectType::Blob, changed);
+        let copy_id = ObjectId::hash(ALGORITHM, ObjectType::Blob, &changed[..6]);
+        let delta = [12, 18, 0x90, 6, 5, b't', b'h', b'e', b'r', b'e', 0x91, 5, 7];
+
+        let mut pack = PACK_MAGIC.to_vec();
+        pack.extend_from_slice(&2u32.to_be_bytes());
+        pack.extend_from_slice(&3u32.to_be_bytes());
+        // a whole blob of 12 bytes: type 3, size in two header bytes
+        let base_offset = pack.len() as u64;
+        pack.extend_from_slice(&[0x80 | 0x30 | 12, 0]);
+        pack.extend_from_slice(&compress(base));
+        // an OFS_DELTA against it
+        let ofs_offset = pack.len() as u64;
+        pack.extend_from_slice(&[0x60 | delta.len() as u8, (ofs_offset - base_offset) as u8]);
+        pack.extend_from_slice(&compress(&delta));
+        // a REF_DELTA against that, keeping the first six bytes
+        let ref_offset = pack.len() as u64;
+        pack.push(0x70 | 4);
+        pack.extend_from_slice(changed_id.as_bytes());
+        pack.extend_from_slice(&compress(&[18, 6, 0x90, 6]));
+        let checksum = ALGORITHM.digest(&[&pack]);
+        pack.extend_from_slice(checksum.as_bytes());
+
+        let tempdir = TempDir::new().unwrap();
+        let idx = tempdir.path().join("pack-test.idx");
+        fs::write(tempdir.path().join("pack-test.pack"), &pack).unwrap();
+        let entries = vec![
+            (base_id, base_offset),
+            (changed_id, ofs_offset),
+            (copy_id, ref_offset),
+        ];
+        fs::write(&idx, index_bytes(entries, &checksum)).unwrap();
+
+        let pack = Pack::open(&idx, ALGORITHM).unwrap();
+        assert_eq!(pack.index().len(), 3);
+        assert_eq!(pack.index().pack_checksum(), checksum.as_bytes());
+        assert_eq!(
+            pack.entry(ofs_offset).unwrap().kind,
+            EntryKind::OfsDelta(base_offset)
+        );
+        let no_external = |_: &ObjectId| None;
+        for (id, data) in [
+            (copy_id, &changed[..6]),
+            (changed_id, &changed[..]),
+            (base_id, &base[..]),
+        ] {
+            assert_eq!(
+                pack.read(&id, &no_external).unwrap(),
+                Some(RawObject {
+                    kind: ObjectType::Blob,
+                    data: data.to_vec()
+                })
+            );
+        }
+        let prefix = &copy_id.to_string()[..6];
+        assert_eq!(pack.index().find_prefix(prefix), [copy_id]);
+        let missing = ObjectId::hash(ALGORITHM, ObjectType::Blob, b"missing\n");
+        assert_eq!(pack.read(&missing, &no_external), Ok(None));
+    }
+
+    #[test]
+    fn large_offsets() {
+        let ids: Vec<_> = ["a", "b", "c"]
+            .map(|data| ObjectId::hash(ALGORITHM, ObjectType::Blob, data.as_bytes()))
+            .to_vec();
+        let offsets = [12, 0x8000_0000, 0x1_2345_6789];
+        let entries = ids.iter().copied().zip(offsets).collect();
+        let index =
+            PackIndex::parse(index_bytes(entries, &ObjectId::null(ALGORITHM)), ALGORITHM).unwrap();
+        for (id, offset) in ids.iter().zip(offsets) {
+            assert_eq!(index.offset(index.find(id).unwrap()), offset);
+        }
+
+        let mut data = index_bytes(Vec::new(), &ObjectId::null(ALGORITHM));
+        data[7] = 1;
+        assert!(matches!(
+            PackIndex::parse(data, ALGORITHM),
+            Err(Error::UnsupportedIndexVersion(_, 1))
+        ));
+    }
+
+    #[test]
+    fn build_and_read_back() {
+        let tempdir = TempDir::new().unwrap();
+        let store = LooseObjects::new(tempdir.path(), ALGORITHM);
+        let mut inputs = Vec::new();
+        let mut text = String::new();
+        for version in 0..20 {
+            text.push_str(&format!("line {version} of a file that keeps growing\n"));
+            let id = store.write(ObjectType::Blob, text.as_bytes()).unwrap();
+            inputs.push(PackInput {
+                id,
+                name_hash: name_hash(b"src/growing.txt"),
+            });
+        }
+        // duplicates are packed once
+        inputs.push(inputs[0]);
+
+        let options = PackOptions {
+            window: 10,
+            depth: 3,
+        };
+        let new = build(&store, &inputs, &options).unwrap();
+        let idx = new.save(tempdir.path().join("pack/pack")).unwrap();
+        assert_eq!(
+            idx,
+            tempdir
+                .path()
+                .join(format!("pack/pack-{}.idx", new.checksum))
+        );
+
+        let pack = Pack::open(&idx, ALGORITHM).unwrap();
+        assert_eq!(pack.index().len(), 20);
+        assert_eq!(pack.index().pack_checksum(), new.checksum.as_bytes());
+        let mut deltas = 0;
+        for input in &inputs {
+            let n = pack.index().find(&input.id).unwrap();
+            let mut entry = pack.entry(pack.index().offset(n)).unwrap();
+            let mut depth = 0;
+            while let EntryKind::OfsDelta(base) = entry.kind {
+                entry = pack.entry(base).unwrap();
+                depth += 1;
+            }
+            assert!(depth <= options.depth);
+            deltas += usize::from(depth > 0);
+            assert_eq!(
+                pack.read(&input.id, &|_| None).unwrap(),
+                Some(store.read(&input.id).unwrap())
+            );
+        }
+        assert!(deltas > 10, "{deltas}");
+
+        pack.verify_checksum().unwrap();
+        let mut data = fs::read(pack.path()).unwrap();
+        data[20] ^= 1;
+        fs::remove_file(pack.path()).unwrap();
+        fs::write(pack.path(), data).unwrap();
+        assert!(matches!(
+            pack.verify_checksum(),
+            Err(Error::Corrupt(_, msg)) if msg == "pack checksum mismatch"
+        ));
+    }
+
+    #[test]
+    fn name_hashes() {
+        assert_eq!(name_hash(b""), 0);
+        assert_eq!(name_hash(b"a b"), name_hash(b"ab"));
+        // files with the same extension end up close together
+        let (c, rs, rs2) = (
+            name_hash(b"x.c"),
+            name_hash(b"main.rs"),
+            name_hash(b"lib.rs"),
+        );
+        assert!(rs.abs_diff(rs2) < rs.abs_diff(c));
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    env,
+    error::Error,
+    io::{self, BufRead as _, Write as _},
+    path::PathBuf,
+};
+
+use application::clap;
+
+use crate::{
+    object::ObjectId,
+    odb::ObjectStore as _,
+    pack::{self, PackInput, PackOptions},
+    repo::RealRepo,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum PackObjectsError {
+    #[error("expected object ID, got garbage: {0}")]
+    InvalidLine(String),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// How many objects to try as delta bases for each object
+    #[arg(long, value_name = "n", default_value_t = PackOptions::default().window)]
+    window: usize,
+    /// The longest chain of deltas to create
+    #[arg(long, value_name = "n", default_value_t = PackOptions::default().depth)]
+    depth: usize,
+    /// Write the pack to standard output instead of files
+    #[arg(long)]
+    stdout: bool,
+    /// Write `<base-name>-<checksum>.pack` and `.idx`
+    #[arg(value_name = "base-name", required_unless_present = "stdout")]
+    base_name: Option<PathBuf>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let objects = repo.objects();
+
+        // one `<id> [<path>]` per line, the path only guiding delta selection
+        let mut inputs = Vec::new();
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let (id, name) = line.split_once(' ').unwrap_or((&line, ""));
+            let id: ObjectId = id
+                .parse()
+                .ok()
+                .filter(|id: &ObjectId| id.algorithm() == objects.algorithm())
+                .ok_or_else(|| PackObjectsError::InvalidLine(line.clone()))?;
+            inputs.push(PackInput {
+                id,
+                name_hash: pack::name_hash(name.as_bytes()),
+            });
+        }
+
+        let options = PackOptions {
+            window: self.window,
+            depth: self.depth,
+        };
+        let pack = pack::build(&objects, &inputs, &options)?;
+        match self.base_name {
+            Some(base_name) if !self.stdout => {
+                pack.save(base_name)?;
+                println!("{}", pack.checksum);
+            }
+            _ => io::stdout().lock().write_all(&pack.data)?,
+        }
+        Ok(())
+    }
+}
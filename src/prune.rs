@@ -0,0 +1,200 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fs,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use application::clap;
+
+use crate::{
+    config,
+    odb::{ObjectDatabase, ObjectStore as _},
+    reachable,
+    repo::RealRepo,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum PruneError {
+    #[error("cannot prune in a precious-objects repo")]
+    PreciousObjects,
+    #[error("malformed expiration date '{0}'")]
+    InvalidDate(String),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Only report what would be removed
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+    /// Report all removed objects
+    #[arg(short = 'v', long)]
+    verbose: bool,
+    /// Only remove unreachable objects older than <date>
+    #[arg(long, value_name = "date")]
+    expire: Option<String>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        // without `--expire`, every unreachable object goes
+        let expire = match &self.expire {
+            Some(date) => expiry_date(date)?,
+            None => u64::MAX,
+        };
+        prune(&repo, expire, self.dry_run, self.verbose)
+    }
+}
+
+/// Parses a `--prune`/`--expire` style date into a timestamp.
+pub(crate) fn expiry_date(date: &str) -> Result<u64, PruneError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs());
+    config::parse_expiry_date(date, now).ok_or_else(|| PruneError::InvalidDate(date.to_owned()))
+}
+
+/// Removes loose objects that nothing reaches and that were last written at
+/// or before `expire`, then any loose objects that are also packed.
+pub(crate) fn prune(
+    repo: &RealRepo,
+    expire: u64,
+    dry_run: bool,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    if repo.has_precious_objects() {
+        return Err(PruneError::PreciousObjects.into());
+    }
+    let objects = repo.objects();
+    let roots = reachable::roots(repo)?;
+    let reachable: HashSet<_> = reachable::walk(&objects, &roots)?
+        .into_iter()
+        .map(|reached| reached.id)
+        .collect();
+
+    for id in objects.loose().ids()? {
+        if reachable.contains(&id) {
+            continue;
+        }
+        let path = objects.loose().path(&id);
+        let modified = fs::metadata(&path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |modified| modified.as_secs());
+        if modified > expire {
+            continue;
+        }
+        if dry_run || verbose {
+            let kind = objects.loose().read(&id).map(|raw| raw.kind.to_string());
+            println!("{id} {}", kind.as_deref().unwrap_or("unknown"));
+        }
+        if !dry_run {
+            fs::remove_file(&path)?;
+            // git leaves no empty fan-out directories behind
+            let _ = fs::remove_dir(path.parent().expect("loose object path has a parent"));
+        }
+    }
+    prune_packed(&objects, dry_run)?;
+    Ok(())
+}
+
+/// Removes loose copies of objects that are already in a pack.
+pub(crate) fn prune_packed(objects: &ObjectDatabase, dry_run: bool) -> Result<(), Box<dyn Error>> {
+    let packs = objects.packs()?;
+    for id in objects.loose().ids()? {
+        if !packs.iter().any(|pack| pack.index().find(&id).is_some()) {
+            continue;
+        }
+        let path = objects.loose().path(&id);
+        if dry_run {
+            println!("rm -f {}", path.display());
+        } else {
+            fs::remove_file(&path)?;
+            let _ = fs::remove_dir(path.parent().expect("loose object path has a parent"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        index::{Index, IndexEntry},
+        object::{ObjectId, ObjectType},
+        refs::RefValue,
+        repo::{
+            InitOptions, LogAllRefUpdates, RealRepoCreator, RefChange, RefTransaction, RefUpdate,
+            RepoCreator as _,
+        },
+    };
+
+    #[test]
+    fn keeps_reachable() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = RealRepoCreator::create(tempdir.path(), &InitOptions::default()).unwrap();
+        let objects = repo.objects();
+        // two unrelated root commits, so the first is only in the reflog
+        let commit = |contents: &[u8]| {
+            let blob = objects.write(ObjectType::Blob, contents).unwrap();
+            let mut tree = b"100644 a\0".to_vec();
+            tree.extend_from_slice(blob.as_bytes());
+            let tree = objects.write(ObjectType::Tree, &tree).unwrap();
+            let commit =
+                format!("tree {tree}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+            let commit = objects
+                .write(ObjectType::Commit, commit.as_bytes())
+                .unwrap();
+            let committer = "A <a@b> 0 +0000".to_owned();
+            let mut transaction =
+                RefTransaction::new(repo.refs()).logged(committer, LogAllRefUpdates::True);
+            let change = RefChange::Set(RefValue::Direct(commit));
+            transaction.add(RefUpdate::new("HEAD", change, None));
+            transaction.commit().unwrap();
+            [blob, tree, commit]
+        };
+        let logged = commit(b"1\n");
+        let current = commit(b"2\n");
+        let staged = objects.write(ObjectType::Blob, b"staged\n").unwrap();
+        let metadata = fs::metadata(tempdir.path().join(".git/HEAD")).unwrap();
+        let mut index = Index::new(repo.object_format());
+        index.entries.push(IndexEntry::from_metadata(
+            b"b".to_vec(),
+            staged,
+            0o100644,
+            &metadata,
+        ));
+        index.write(repo.gitdir().join("index")).unwrap();
+        let unreachable = objects.write(ObjectType::Blob, b"unreachable\n").unwrap();
+
+        let mut all: Vec<ObjectId> = [&logged[..], &current, &[staged, unreachable]].concat();
+        all.sort();
+        let loose = || objects.loose().ids().unwrap();
+        // nothing was written before the epoch
+        prune(&repo, 0, false, false).unwrap();
+        assert_eq!(loose(), all);
+        prune(&repo, u64::MAX, true, false).unwrap();
+        assert_eq!(loose(), all);
+
+        prune(&repo, u64::MAX, false, false).unwrap();
+        all.retain(|&id| id != unreachable);
+        assert_eq!(loose(), all);
+        assert!(!objects
+            .loose()
+            .path(&unreachable)
+            .parent()
+            .unwrap()
+            .exists());
+    }
+}
@@ -0,0 +1,226 @@
+use std::{collections::HashSet, fs, io};
+
+use crate::{
+    index::{self, Index},
+    object::{self, GitObject, ObjectId},
+    odb::{self, ObjectStore},
+    reflog,
+    refs::{self, RefStore},
+    repo::{self, RealRepo},
+    tree::FileMode,
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error(transparent)]
+    Odb(#[from] odb::Error),
+    #[error(transparent)]
+    Object(#[from] object::Error),
+    #[error(transparent)]
+    Index(#[from] index::Error),
+    #[error(transparent)]
+    Repo(#[from] repo::Error),
+    #[error(transparent)]
+    Refs(#[from] refs::Error),
+    #[error(transparent)]
+    Reflog(#[from] reflog::Error),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// An object reached while walking history, with the path that led to it.
+///
+/// The path is empty for commits and tags; it lets packing group versions of
+/// the same file together.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Reached {
+    pub(crate) id: ObjectId,
+    pub(crate) path: Vec<u8>,
+}
+
+/// The ids that keep objects alive: each worktree's refs and `HEAD`, index
+/// entries and reflogs, paired with a path where one is known.
+pub(crate) fn roots(repo: &RealRepo) -> Result<Vec<Reached>, Error> {
+    let root = |id| Reached {
+        id,
+        path: Vec::new(),
+    };
+    let mut roots = Vec::new();
+    let mut logs = Vec::new();
+    for worktree in repo.worktrees()? {
+        // shared refs are listed for every worktree, along with its own
+        let refs = RefStore::new(&worktree.gitdir, repo.commondir());
+        roots.extend(refs.list()?.into_iter().map(|reference| root(reference.id)));
+        roots.extend(refs.resolve("HEAD")?.map(root));
+        logs.push(worktree.gitdir.join("logs/HEAD"));
+
+        let index = Index::read(worktree.gitdir.join("index"), repo.object_format())?;
+        for entry in index.entries {
+            // submodule commits live in another repository
+            if entry.mode != FileMode::GITLINK.bits() {
+                roots.push(Reached {
+                    id: entry.id,
+                    path: entry.path,
+                });
+            }
+        }
+    }
+
+    let mut dirs = vec![repo.commondir().join("logs/refs")];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(Error::Io(err.to_string())),
+        };
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::Io(err.to_string()))?;
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                dirs.push(entry.path());
+            } else {
+                logs.push(entry.path());
+            }
+        }
+    }
+    for log in logs {
+        for entry in reflog::read(&log)? {
+            for id in [entry.old, entry.new] {
+                if !id.is_null() {
+                    roots.push(root(id));
+                }
+            }
+        }
+    }
+    Ok(roots)
+}
+
+/// Every object reachable from `roots`, each once: commits and tags first, then
+/// trees and blobs in the order they are found.
+pub(crate) fn walk(objects: &impl ObjectStore, roots: &[Reached]) -> Result<Vec<Reached>, Error> {
+    let mut seen = HashSet::new();
+    let mut found = Vec::new();
+    let mut contents = Vec::new();
+
+    let mut pending: Vec<_> = roots.iter().rev().cloned().collect();
+    while let Some(reached) = pending.pop() {
+        if !seen.insert(reached.id) {
+            continue;
+        }
+        match objects.read_object(&reached.id)? {
+            GitObject::Commit(commit) => {
+                pending.extend(commit.parents()?.into_iter().rev().map(|id| Reached {
+                    id,
+                    path: Vec::new(),
+                }));
+                contents.push(Reached {
+                    id: commit.tree()?,
+                    path: Vec::new(),
+                });
+            }
+            GitObject::Tag(tag) => pending.push(Reached {
+                id: tag.object()?,
+                path: Vec::new(),
+            }),
+            GitObject::Tree(_) | GitObject::Blob(_) => {
+                contents.push(reached);
+                continue;
+            }
+        }
+        found.push(reached);
+    }
+
+    // the commits' trees, and any trees or blobs that were roots themselves;
+    // blobs named by a tree entry need not be read
+    let mut pending: Vec<_> = contents.into_iter().rev().map(|r| (r, true)).collect();
+    for (reached, _) in &pending {
+        seen.remove(&reached.id);
+    }
+    while let Some((reached, may_be_tree)) = pending.pop() {
+        if !seen.insert(reached.id) {
+            continue;
+        }
+        if may_be_tree {
+            if let GitObject::Tree(tree) = objects.read_object(&reached.id)? {
+                for entry in tree.entries.into_iter().rev() {
+                    if entry.mode == FileMode::GITLINK {
+                        continue;
+                    }
+                    let mut path = reached.path.clone();
+                    if !path.is_empty() {
+                        path.push(b'/');
+                    }
+                    path.extend_from_slice(&entry.name);
+                    pending.push((Reached { id: entry.id, path }, entry.mode.is_tree()));
+                }
+            }
+        }
+        found.push(reached);
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::{HashAlgorithm, ObjectType},
+        odb::LooseObjects,
+    };
+
+    #[test]
+    fn walk_order() {
+        let tempdir = TempDir::new().unwrap();
+        let store = LooseObjects::new(tempdir.path().join("objects"), HashAlgorithm::Sha1);
+        let blob = store.write(ObjectType::Blob, b"hello\n").unwrap();
+        let mut data = b"100644 a\0".to_vec();
+        data.extend_from_slice(blob.as_bytes());
+        let inner = store.write(ObjectType::Tree, &data).unwrap();
+        let mut data = b"40000 dir\0".to_vec();
+        data.extend_from_slice(inner.as_bytes());
+        let tree = store.write(ObjectType::Tree, &data).unwrap();
+        let commit = |parent: Option<ObjectId>| {
+            let mut data = format!("tree {tree}\n");
+            if let Some(parent) = parent {
+                data.push_str(&format!("parent {parent}\n"));
+            }
+            data.push_str("author A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nmsg\n");
+            store.write(ObjectType::Commit, data.as_bytes()).unwrap()
+        };
+        let first = commit(None);
+        let second = commit(Some(first));
+        let tag = format!("object {first}\ntype commit\ntag v1\ntagger A <a@b> 0 +0000\n\nt\n");
+        let tag = store.write(ObjectType::Tag, tag.as_bytes()).unwrap();
+        // never referenced
+        store.write(ObjectType::Blob, b"dangling\n").unwrap();
+
+        let roots: Vec<_> = [second, tag]
+            .map(|id| Reached {
+                id,
+                path: Vec::new(),
+            })
+            .into();
+        let found = walk(&store, &roots).unwrap();
+        let found: Vec<_> = found
+            .iter()
+            .map(|reached| {
+                (
+                    reached.id,
+                    String::from_utf8_lossy(&reached.path).into_owned(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (second, String::new()),
+                (first, String::new()),
+                (tag, String::new()),
+                (tree, String::new()),
+                (inner, "dir".to_owned()),
+                (blob, "dir/a".to_owned()),
+            ]
+        );
+    }
+}
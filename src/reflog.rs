@@ -0,0 +1,162 @@
+use std::{
+    fmt,
+    fs::{self, OpenOptions},
+    io::{self, Write as _},
+    path::Path,
+};
+
+use crate::{
+    lockfile::{self, Lockfile},
+    object::ObjectId,
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error(transparent)]
+    Lock(#[from] lockfile::Error),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// One line of a reflog: a ref moving from `old` to `new`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Entry {
+    /// The null id when the ref was created.
+    pub(crate) old: ObjectId,
+    pub(crate) new: ObjectId,
+    /// `Name <email> <timestamp> <timezone>`, as in a commit.
+    pub(crate) committer: String,
+    pub(crate) message: String,
+}
+
+impl Entry {
+    /// An entry whose message is squeezed onto one line, as git does.
+    pub(crate) fn new(old: ObjectId, new: ObjectId, committer: String, message: &str) -> Self {
+        Self {
+            old,
+            new,
+            committer,
+            message: message.split_whitespace().collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    /// Parses `<old> <new> <committer>\t<message>`, where the message may be
+    /// missing along with its tab.
+    fn parse(line: &str) -> Option<Self> {
+        let (head, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut fields = head.splitn(3, ' ');
+        let old = fields.next()?.parse().ok()?;
+        let new = fields.next()?.parse().ok()?;
+        let committer = fields.next()?.to_owned();
+        Some(Self {
+            old,
+            new,
+            committer,
+            message: message.to_owned(),
+        })
+    }
+
+    /// When the entry was written, in seconds since the epoch.
+    pub(crate) fn time(&self) -> Option<u64> {
+        self.committer.rsplit(' ').nth(1)?.parse().ok()
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.old, self.new, self.committer, self.message
+        )
+    }
+}
+
+/// The entries of the reflog at `path`, oldest first, or none if it does not
+/// exist; lines that cannot be parsed are skipped like git does.
+pub(crate) fn read(path: &Path) -> Result<Vec<Entry>, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(contents.lines().filter_map(Entry::parse).collect()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(Error::Io(err.to_string())),
+    }
+}
+
+/// Adds `entry` to the end of the reflog at `path`, creating it if needed.
+pub(crate) fn append(path: &Path, entry: &Entry) -> Result<(), Error> {
+    let io = |err: io::Error| Error::Io(err.to_string());
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(io)?;
+    }
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(io)?;
+    // one write, so that concurrent appends do not interleave
+    file.write_all(format!("{entry}\n").as_bytes()).map_err(io)
+}
+
+/// A reflog held under `<path>.lock` while its entries are rewritten.
+#[derive(Debug)]
+pub(crate) struct LockedReflog {
+    lock: Lockfile,
+    /// Oldest first; whatever is left replaces the reflog on commit.
+    pub(crate) entries: Vec<Entry>,
+}
+
+impl LockedReflog {
+    pub(crate) fn lock(path: &Path) -> Result<Self, Error> {
+        let lock = Lockfile::acquire(path)?;
+        let entries = read(path)?;
+        Ok(Self { lock, entries })
+    }
+
+    pub(crate) fn commit(mut self) -> Result<(), Error> {
+        let contents: String = self.entries.iter().map(|e| format!("{e}\n")).collect();
+        self.lock.write_all(contents.as_bytes())?;
+        Ok(self.lock.commit()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let tempdir = TempDir::new().unwrap();
+        let path = tempdir.path().join("logs/refs/heads/main");
+        let id = |hex: &str| hex.repeat(40).parse::<ObjectId>().unwrap();
+        let committer = "A U Thor <a@example.com> 1700000000 +0100".to_owned();
+
+        let created = Entry::new(id("0"), id("1"), committer.clone(), "");
+        let moved = Entry::new(id("1"), id("2"), committer, "commit:  two\nlines ");
+        assert_eq!(moved.message, "commit: two lines");
+        assert_eq!(moved.time(), Some(1_700_000_000));
+        append(&path, &created).unwrap();
+        fs::write(&path, fs::read_to_string(&path).unwrap() + "garbage\n").unwrap();
+        append(&path, &moved).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let expected = format!(
+            "{} {} A U Thor <a@example.com> 1700000000 +0100\t\n",
+            id("0"),
+            id("1")
+        );
+        assert!(contents.starts_with(&expected));
+        assert_eq!(read(&path), Ok(vec![created.clone(), moved]));
+
+        let mut locked = LockedReflog::lock(&path).unwrap();
+        assert!(matches!(
+            LockedReflog::lock(&path),
+            Err(Error::Lock(lockfile::Error::Locked(_)))
+        ));
+        locked.entries.truncate(1);
+        locked.commit().unwrap();
+        assert_eq!(read(&path), Ok(vec![created]));
+        assert_eq!(read(&tempdir.path().join("missing")), Ok(Vec::new()));
+    }
+}
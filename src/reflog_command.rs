@@ -0,0 +1,453 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fs,
+    io::{self, Write as _},
+};
+
+use application::clap;
+
+use crate::{
+    lockfile::Lockfile,
+    object::{GitObject, ObjectId},
+    odb::{ObjectDatabase, ObjectStore as _},
+    prune,
+    reflog::{self, Entry, LockedReflog},
+    refs::RefValue,
+    repo::{Config as _, RealRepo},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum ReflogError {
+    #[error("Ambiguous argument '{0}': unknown revision")]
+    UnknownRevision(String),
+    #[error("Not a reflog entry: {0}")]
+    NotAnEntry(String),
+    #[error("Reflog entry {0} does not exist")]
+    NoSuchEntry(String),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Defaults to `show HEAD`
+    #[command(subcommand)]
+    command: Option<Subcommand>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Subcommand {
+    /// Show the entries of a reflog, newest first
+    Show {
+        #[arg(value_name = "ref", default_value = "HEAD")]
+        name: String,
+    },
+    /// Prune old entries, and entries no longer reachable from the ref
+    Expire {
+        /// Prune entries older than <time> (default `gc.reflogExpire`, or 90 days)
+        #[arg(long, value_name = "time")]
+        expire: Option<String>,
+        /// Prune entries older than <time> that are not reachable from the ref
+        /// (default `gc.reflogExpireUnreachable`, or 30 days)
+        #[arg(long, value_name = "time")]
+        expire_unreachable: Option<String>,
+        /// Expire the reflogs of HEAD and every ref
+        #[arg(long)]
+        all: bool,
+        #[command(flatten)]
+        options: Options,
+        #[arg(value_name = "ref")]
+        names: Vec<String>,
+    },
+    /// Delete single entries, given as `<ref>@{<n>}`
+    Delete {
+        #[command(flatten)]
+        options: Options,
+        #[arg(value_name = "ref@{n}", required = true)]
+        entries: Vec<String>,
+    },
+    /// Exit with status 0 if <ref> has a reflog, and 1 otherwise
+    Exists {
+        #[arg(value_name = "ref")]
+        name: String,
+    },
+}
+
+/// How `expire` and `delete` rewrite a reflog.
+#[derive(Debug, clap::Args)]
+struct Options {
+    /// Point each entry's old value at the new value of the entry before it
+    #[arg(long)]
+    rewrite: bool,
+    /// Point the ref at its newest remaining entry if that changed
+    #[arg(long)]
+    updateref: bool,
+    /// Only report what would be pruned
+    #[arg(short = 'n', long)]
+    dry_run: bool,
+    /// Print every entry and whether it is kept
+    #[arg(long)]
+    verbose: bool,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let refs = repo.refs();
+        let command = self.command.unwrap_or(Subcommand::Show {
+            name: "HEAD".to_owned(),
+        });
+
+        match command {
+            Subcommand::Show { name } => show(&repo, &name),
+            Subcommand::Expire {
+                expire,
+                expire_unreachable,
+                all,
+                options,
+                mut names,
+            } => {
+                let config = repo.config()?;
+                let date = |value: Option<String>, key, default| match value {
+                    Some(date) => prune::expiry_date(&date),
+                    None => match config.get_value("gc", key) {
+                        Some(Some(date)) => prune::expiry_date(date),
+                        _ => prune::expiry_date(default),
+                    },
+                };
+                let expire = date(expire, "reflogExpire", "90.days.ago")?;
+                let unreachable =
+                    date(expire_unreachable, "reflogExpireUnreachable", "30.days.ago")?;
+
+                if all {
+                    names = logged_names(&repo)?;
+                }
+                let objects = repo.objects();
+                for name in names {
+                    let full = refs
+                        .dwim_log(&name)
+                        .ok_or_else(|| ReflogError::UnknownRevision(name.clone()))?;
+                    let tip = refs.resolve(&full)?;
+                    let reachable = tip.map_or_else(HashSet::new, |tip| ancestors(&objects, tip));
+                    rewrite(&repo, &full, &options, |_, entry| {
+                        expired(entry, expire, unreachable, &objects, &reachable)
+                    })?;
+                }
+                Ok(())
+            }
+            Subcommand::Delete { options, entries } => {
+                for spec in entries {
+                    let selected = spec
+                        .strip_suffix('}')
+                        .and_then(|spec| spec.rsplit_once("@{"))
+                        .and_then(|(name, n)| Some((name, n.parse::<usize>().ok()?)));
+                    let Some((name, n)) = selected else {
+                        return Err(ReflogError::NotAnEntry(spec).into());
+                    };
+                    let full = refs
+                        .dwim_log(name)
+                        .ok_or_else(|| ReflogError::UnknownRevision(name.to_owned()))?;
+                    let deleted = rewrite(&repo, &full, &options, |newest, _| newest == n)?;
+                    if deleted == 0 {
+                        return Err(ReflogError::NoSuchEntry(spec).into());
+                    }
+                }
+                Ok(())
+            }
+            Subcommand::Exists { name } => {
+                if !refs.log_path(&name).is_file() {
+                    std::process::exit(1);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Prints the reflog of `name` as `<id> <name>@{<n>}: <message>` lines.
+fn show(repo: &RealRepo, name: &str) -> Result<(), Box<dyn Error>> {
+    let refs = repo.refs();
+    let Some(full) = refs.dwim_log(name) else {
+        // a ref that has never been logged simply has nothing to show
+        if refs.dwim(name)?.is_some() {
+            return Ok(());
+        }
+        return Err(ReflogError::UnknownRevision(name.to_owned()).into());
+    };
+    let objects = repo.objects();
+    let mut stdout = io::stdout().lock();
+    for (n, entry) in reflog::read(&refs.log_path(&full))?
+        .iter()
+        .rev()
+        .enumerate()
+    {
+        let id = objects.abbreviate(&entry.new, 7)?;
+        writeln!(stdout, "{id} {name}@{{{n}}}: {}", entry.message)?;
+    }
+    Ok(())
+}
+
+/// `HEAD` and every ref that has a reflog.
+fn logged_names(repo: &RealRepo) -> Result<Vec<String>, Box<dyn Error>> {
+    let refs = repo.refs();
+    let names = refs.list()?.into_iter().map(|reference| reference.name);
+    Ok(std::iter::once("HEAD".to_owned())
+        .chain(names)
+        .filter(|name| refs.log_path(name).is_file())
+        .collect())
+}
+
+/// Removes the entries of the reflog of `name` that `prune` picks, given how
+/// many entries newer than each there are, and returns how many went.
+fn rewrite(
+    repo: &RealRepo,
+    name: &str,
+    options: &Options,
+    prune: impl FnMut(usize, &Entry) -> bool,
+) -> Result<usize, Box<dyn Error>> {
+    let refs = repo.refs();
+    // as in git, the ref itself is locked too, so that a ref update cannot
+    // append to the reflog while it is being rewritten
+    let path = refs.path(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let result = Lockfile::acquire(&path)
+        .map_err(Into::into)
+        .and_then(|ref_lock| rewrite_locked(repo, name, options, ref_lock, prune));
+    // the directories made for the lock, if the ref is only packed
+    refs.remove_empty_parents(name);
+    result
+}
+
+/// [`rewrite`], once `ref_lock` holds the ref.
+fn rewrite_locked(
+    repo: &RealRepo,
+    name: &str,
+    options: &Options,
+    mut ref_lock: Lockfile,
+    mut prune: impl FnMut(usize, &Entry) -> bool,
+) -> Result<usize, Box<dyn Error>> {
+    let refs = repo.refs();
+    let mut locked = LockedReflog::lock(&refs.log_path(name))?;
+    let newest = locked.entries.last().map(|entry| entry.new);
+    let count = locked.entries.len();
+    let null = ObjectId::null(repo.object_format());
+
+    let mut kept: Vec<Entry> = Vec::with_capacity(count);
+    for (i, mut entry) in locked.entries.drain(..).enumerate() {
+        // as in git, the rewritten entry is the one judged; before anything is
+        // kept, its old value is null
+        if options.rewrite {
+            entry.old = kept.last().map_or(null, |previous| previous.new);
+        }
+        let pruned = prune(count - 1 - i, &entry);
+        if options.verbose {
+            let verb = if pruned { "prune" } else { "keep" };
+            println!("{verb} {}", entry.message);
+        }
+        if !pruned {
+            kept.push(entry);
+        }
+    }
+    let pruned = count - kept.len();
+    if options.dry_run {
+        return Ok(pruned);
+    }
+
+    let top = kept.last().map(|entry| entry.new);
+    locked.entries = kept;
+    locked.commit()?;
+    // a symbolic ref such as HEAD is left pointing where it was
+    let symbolic = matches!(refs.read(name)?, Some(RefValue::Symbolic(_)));
+    if let Some(top) = top.filter(|&top| options.updateref && !symbolic && Some(top) != newest) {
+        ref_lock.write_all(format!("{top}\n").as_bytes())?;
+        ref_lock.commit()?;
+    }
+    Ok(pruned)
+}
+
+/// Whether `reflog expire` prunes `entry`: it is older than `expire`, or
+/// older than `unreachable` and moved the ref from or to a commit that is
+/// missing or not in `reachable`.
+fn expired(
+    entry: &Entry,
+    expire: u64,
+    unreachable: u64,
+    objects: &ObjectDatabase,
+    reachable: &HashSet<ObjectId>,
+) -> bool {
+    let time = entry.time().unwrap_or(0);
+    let kept = |id: &ObjectId| id.is_null() || reachable.contains(id);
+    time < expire
+        || time < unreachable
+            && !(objects.contains(&entry.new) && kept(&entry.old) && kept(&entry.new))
+}
+
+/// `tip` and every commit it descends from, leaving out any that are missing.
+fn ancestors(objects: &ObjectDatabase, tip: ObjectId) -> HashSet<ObjectId> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![tip];
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        if let Ok(GitObject::Commit(commit)) = objects.read_object(&id) {
+            pending.extend(commit.parents().unwrap_or_default());
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        lockfile,
+        object::ObjectType,
+        repo::{InitOptions, RealRepoCreator, RefTransaction, RefUpdate, RepoCreator as _},
+    };
+
+    fn id(hex: &str) -> ObjectId {
+        hex.repeat(40).parse().unwrap()
+    }
+
+    /// A repository whose `main` is at `4`, logged as moving `1`, `2`, `3`,
+    /// `4` at times 100, 200 and 300.
+    fn repo(root: &Path) -> RealRepo {
+        let repo = RealRepoCreator::create(root, &InitOptions::default()).unwrap();
+        let mut transaction = RefTransaction::new(repo.refs());
+        transaction.add(RefUpdate::create("refs/heads/main", id("4")));
+        transaction.commit().unwrap();
+        let path = repo.refs().log_path("refs/heads/main");
+        for (old, new, time) in [("1", "2", 100), ("2", "3", 200), ("3", "4", 300)] {
+            let committer = format!("A <a@b> {time} +0000");
+            reflog::append(&path, &Entry::new(id(old), id(new), committer, new)).unwrap();
+        }
+        repo
+    }
+
+    fn options(rewrite: bool, updateref: bool, dry_run: bool) -> Options {
+        Options {
+            rewrite,
+            updateref,
+            dry_run,
+            verbose: false,
+        }
+    }
+
+    /// The log of `main` as `old new` pairs of hex digits, oldest first.
+    fn log(repo: &RealRepo) -> Vec<String> {
+        let entries = reflog::read(&repo.refs().log_path("refs/heads/main")).unwrap();
+        let digit = |id: ObjectId| id.to_string()[..1].to_owned();
+        entries
+            .into_iter()
+            .map(|entry| format!("{} {}", digit(entry.old), digit(entry.new)))
+            .collect()
+    }
+
+    #[test]
+    fn delete() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = repo(tempdir.path());
+        let main = "refs/heads/main";
+        let nth = |n| move |newest, _: &Entry| newest == n;
+
+        assert_eq!(
+            rewrite(&repo, main, &options(false, false, true), nth(1)).unwrap(),
+            1
+        );
+        assert_eq!(log(&repo), ["1 2", "2 3", "3 4"]);
+        assert_eq!(
+            rewrite(&repo, main, &options(false, false, false), nth(5)).unwrap(),
+            0
+        );
+
+        // what was @{2} is @{1} once @{1} has gone
+        rewrite(&repo, main, &options(false, false, false), nth(1)).unwrap();
+        assert_eq!(log(&repo), ["1 2", "3 4"]);
+        rewrite(&repo, main, &options(true, false, false), nth(1)).unwrap();
+        assert_eq!(log(&repo), ["0 4"]);
+        assert_eq!(repo.refs().resolve(main).unwrap(), Some(id("4")));
+
+        let repo = self::repo(&tempdir.path().join("other"));
+        rewrite(&repo, main, &options(true, true, false), nth(0)).unwrap();
+        assert_eq!(log(&repo), ["0 2", "2 3"]);
+        assert_eq!(repo.refs().resolve(main).unwrap(), Some(id("3")));
+
+        // nothing may append to the log while it is rewritten
+        let lock = Lockfile::acquire(repo.refs().path(main)).unwrap();
+        let err = rewrite(&repo, main, &options(false, false, false), nth(0)).unwrap_err();
+        assert!(matches!(
+            err.downcast::<lockfile::Error>().map(|err| *err),
+            Ok(lockfile::Error::Locked(_))
+        ));
+        drop(lock);
+        assert_eq!(log(&repo), ["0 2", "2 3"]);
+    }
+
+    #[test]
+    fn expire() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = repo(tempdir.path());
+        let main = "refs/heads/main";
+        let before = |date| move |_, entry: &Entry| entry.time().unwrap_or(0) < date;
+
+        assert_eq!(
+            rewrite(&repo, main, &options(false, false, true), before(250)).unwrap(),
+            2
+        );
+        assert_eq!(log(&repo), ["1 2", "2 3", "3 4"]);
+        assert_eq!(
+            rewrite(&repo, main, &options(false, true, false), before(200)).unwrap(),
+            1
+        );
+        assert_eq!(log(&repo), ["2 3", "3 4"]);
+        assert_eq!(
+            rewrite(&repo, main, &options(true, true, false), before(1000)).unwrap(),
+            2
+        );
+        assert!(log(&repo).is_empty());
+        // with nothing left, the ref stays where it was
+        assert_eq!(repo.refs().resolve(main).unwrap(), Some(id("4")));
+
+        let objects = repo.objects();
+        let tree = objects.write(ObjectType::Tree, b"").unwrap();
+        let commit =
+            format!("tree {tree}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        let reachable = HashSet::from([commit]);
+        let entry = |new, time| Entry::new(id("0"), new, format!("A <a@b> {time} +0000"), "");
+        let prunes = |entry| expired(&entry, 100, 200, &objects, &reachable);
+        assert!(prunes(entry(commit, 50)));
+        assert!(!prunes(entry(commit, 150)));
+        // a missing object only counts once an entry is old enough to be unreachable
+        assert!(!prunes(entry(id("5"), 250)));
+        assert!(prunes(entry(id("5"), 150)));
+        assert!(!prunes(Entry::new(
+            commit,
+            commit,
+            "A <a@b> 150 +0000".to_owned(),
+            ""
+        )));
+        assert!(prunes(Entry::new(
+            id("5"),
+            commit,
+            "A <a@b> 150 +0000".to_owned(),
+            ""
+        )));
+    }
+}
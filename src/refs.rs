@@ -0,0 +1,620 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::object::ObjectId;
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub(crate) enum Error {
+    #[error("Invalid ref name: {0}")]
+    InvalidName(String),
+    #[error("Invalid ref {0}: {1}")]
+    InvalidRef(String, String),
+    #[error("Symbolic ref loop at {0}")]
+    SymrefLoop(String),
+    #[error("Too many levels of symbolic refs at {0}")]
+    SymrefTooDeep(String),
+    #[error("Error occurred during I/O: {0}")]
+    Io(String),
+}
+
+/// How many symbolic refs may be followed in a row, as in git.
+const MAX_SYMREF_DEPTH: usize = 5;
+/// How a short name expands into a ref, as a prefix and suffix, in order of precedence.
+const DWIM_RULES: [(&str, &str); 6] = [
+    ("", ""),
+    ("refs/", ""),
+    ("refs/tags/", ""),
+    ("refs/heads/", ""),
+    ("refs/remotes/", ""),
+    ("refs/remotes/", "/HEAD"),
+];
+/// Refs under these prefixes belong to one worktree, like `HEAD`.
+const PER_WORKTREE_PREFIXES: [&str; 3] = ["refs/worktree/", "refs/bisect/", "refs/rewritten/"];
+
+/// What a ref holds: an object id, or the name of another ref.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RefValue {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+impl RefValue {
+    fn parse(name: &str, contents: &str) -> Result<Self, Error> {
+        let contents = contents.trim_end();
+        match contents.strip_prefix("ref:") {
+            Some(target) => Ok(RefValue::Symbolic(target.trim_start().to_owned())),
+            None => contents
+                .parse()
+                .map(RefValue::Direct)
+                .map_err(|_| Error::InvalidRef(name.to_owned(), contents.to_owned())),
+        }
+    }
+}
+
+/// An entry of `packed-refs`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PackedRef {
+    pub(crate) id: ObjectId,
+    /// What an annotated tag ultimately points to, from the `^` line after it.
+    pub(crate) peeled: Option<ObjectId>,
+}
+
+/// A ref that resolves to an object.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Ref {
+    pub(crate) name: String,
+    pub(crate) id: ObjectId,
+    /// Known without reading objects only for refs that come from `packed-refs`.
+    pub(crate) peeled: Option<ObjectId>,
+}
+
+/// The refs one worktree sees: its own `HEAD` and per-worktree refs, and
+/// everything else under `refs/` shared through the common dir, either as
+/// loose files or in `packed-refs`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RefStore {
+    gitdir: PathBuf,
+    commondir: PathBuf,
+}
+
+impl RefStore {
+    pub(crate) fn new<P, Q>(gitdir: P, commondir: Q) -> Self
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        Self {
+            gitdir: gitdir.as_ref().to_owned(),
+            commondir: commondir.as_ref().to_owned(),
+        }
+    }
+
+    /// Where the loose file for `name` lives.
+    pub(crate) fn path(&self, name: &str) -> PathBuf {
+        self.dir(name).join(name)
+    }
+
+    /// Where `packed-refs` lives, shared by every worktree.
+    pub(crate) fn packed_path(&self) -> PathBuf {
+        self.commondir.join("packed-refs")
+    }
+
+    fn dir(&self, name: &str) -> &Path {
+        if is_per_worktree(name) {
+            &self.gitdir
+        } else {
+            &self.commondir
+        }
+    }
+
+    /// The value of `name` itself, without following symbolic refs.
+    pub(crate) fn read(&self, name: &str) -> Result<Option<RefValue>, Error> {
+        if !is_safe_name(name) {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+        match fs::read_to_string(self.path(name)) {
+            Ok(contents) => return RefValue::parse(name, &contents).map(Some),
+            // neither a directory such as `refs/heads`, nor a path below a ref
+            // such as `refs/heads/main/x`, is a ref
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+                ) || self.path(name).is_dir() => {}
+            Err(err) => return Err(Error::Io(err.to_string())),
+        }
+        if !name.starts_with("refs/") {
+            return Ok(None);
+        }
+        Ok(self
+            .packed()?
+            .get(name)
+            .map(|packed| RefValue::Direct(packed.id)))
+    }
+
+    /// Follows symbolic refs from `name`, returning the last ref reached and
+    /// its id, which is `None` if that ref does not exist yet (as for `HEAD`
+    /// on an unborn branch).
+    pub(crate) fn follow(&self, name: &str) -> Result<(String, Option<ObjectId>), Error> {
+        let mut seen = HashSet::new();
+        let mut name = name.to_owned();
+        loop {
+            if !seen.insert(name.clone()) {
+                return Err(Error::SymrefLoop(name));
+            }
+            if seen.len() > MAX_SYMREF_DEPTH + 1 {
+                return Err(Error::SymrefTooDeep(name));
+            }
+            match self.read(&name)? {
+                Some(RefValue::Direct(id)) => return Ok((name, Some(id))),
+                Some(RefValue::Symbolic(target)) => name = target,
+                None => return Ok((name, None)),
+            }
+        }
+    }
+
+    /// The object `name` resolves to, following symbolic refs.
+    pub(crate) fn resolve(&self, name: &str) -> Result<Option<ObjectId>, Error> {
+        Ok(self.follow(name)?.1)
+    }
+
+    /// Resolves a short name like `main` or `v1.0` the way git does, trying
+    /// `<name>`, then `refs/<name>`, tags, branches and remote-tracking refs.
+    ///
+    /// Returns the full name that matched, with its id.
+    pub(crate) fn dwim(&self, name: &str) -> Result<Option<(String, ObjectId)>, Error> {
+        for (prefix, suffix) in DWIM_RULES {
+            let candidate = format!("{prefix}{name}{suffix}");
+            if !is_safe_name(&candidate) {
+                continue;
+            }
+            if let Some(id) = self.resolve(&candidate)? {
+                return Ok(Some((candidate, id)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// The shortest name that [`RefStore::dwim`] expands back to `name`, such
+    /// as `main` for `refs/heads/main`, or `name` itself if there is none.
+    pub(crate) fn shorten(&self, name: &str) -> Result<String, Error> {
+        'rules: for (rule, (prefix, suffix)) in DWIM_RULES.iter().enumerate().skip(1).rev() {
+            let short = name
+                .strip_prefix(prefix)
+                .and_then(|rest| rest.strip_suffix(suffix));
+            let Some(short) = short.filter(|short| !short.is_empty()) else {
+                continue;
+            };
+            // a rule taking precedence must not find another ref
+            for (prefix, suffix) in &DWIM_RULES[..rule] {
+                let candidate = format!("{prefix}{short}{suffix}");
+                if is_safe_name(&candidate) && self.resolve(&candidate)?.is_some() {
+                    continue 'rules;
+                }
+            }
+            return Ok(short.to_owned());
+        }
+        Ok(name.to_owned())
+    }
+
+    /// Every ref under `refs/` that resolves, sorted by name.
+    ///
+    /// Loose refs take precedence over `packed-refs`; symbolic refs are listed
+    /// under their own name with the id they resolve to.
+    pub(crate) fn list(&self) -> Result<Vec<Ref>, Error> {
+        let packed = self.packed()?;
+        let mut values: BTreeMap<_, _> = packed
+            .iter()
+            .map(|(name, packed)| (name.clone(), (RefValue::Direct(packed.id), packed.peeled)))
+            .collect();
+        for (name, value) in self.loose()? {
+            values.insert(name, (value, None));
+        }
+
+        let mut refs = Vec::new();
+        for (name, (value, peeled)) in values {
+            let id = match value {
+                RefValue::Direct(id) => id,
+                // a symbolic ref to a branch that does not exist yet is skipped
+                RefValue::Symbolic(_) => match self.resolve(&name)? {
+                    Some(id) => id,
+                    None => continue,
+                },
+            };
+            refs.push(Ref { name, id, peeled });
+        }
+        Ok(refs)
+    }
+
+    /// The refs stored as files under `refs/`, by name.
+    ///
+    /// Files whose names are not valid refs, such as leftover `.lock` files,
+    /// are skipped.
+    pub(crate) fn loose(&self) -> Result<BTreeMap<String, RefValue>, Error> {
+        let mut refs = BTreeMap::new();
+        let mut dirs = vec![(self.commondir.clone(), "refs".to_owned())];
+        if self.gitdir != self.commondir {
+            for prefix in PER_WORKTREE_PREFIXES {
+                dirs.push((self.gitdir.clone(), prefix.trim_end_matches('/').to_owned()));
+            }
+        }
+        while let Some((base, dir)) = dirs.pop() {
+            let entries = match fs::read_dir(base.join(&dir)) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(Error::Io(err.to_string())),
+            };
+            for entry in entries {
+                let entry = entry.map_err(|err| Error::Io(err.to_string()))?;
+                let name = format!("{dir}/{}", entry.file_name().to_string_lossy());
+                // a linked worktree's own refs shadow the main worktree's
+                if base == self.commondir && self.dir(&name) != self.commondir {
+                    continue;
+                }
+                let file_type = entry
+                    .file_type()
+                    .map_err(|err| Error::Io(err.to_string()))?;
+                if file_type.is_dir() {
+                    dirs.push((base.clone(), name));
+                    continue;
+                }
+                if !is_valid_name(&name) {
+                    continue;
+                }
+                let contents =
+                    fs::read_to_string(entry.path()).map_err(|err| Error::Io(err.to_string()))?;
+                refs.insert(name.clone(), RefValue::parse(&name, &contents)?);
+            }
+        }
+        Ok(refs)
+    }
+
+    /// The contents of `packed-refs`, by name.
+    pub(crate) fn packed(&self) -> Result<BTreeMap<String, PackedRef>, Error> {
+        let packed = match fs::read_to_string(self.packed_path()) {
+            Ok(packed) => packed,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(err) => return Err(Error::Io(err.to_string())),
+        };
+        let invalid = |line: &str| Error::InvalidRef("packed-refs".to_owned(), line.to_owned());
+
+        let mut refs = BTreeMap::new();
+        let mut last = None;
+        for line in packed.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            // the peeled value of the tag on the line before
+            if let Some(peeled) = line.strip_prefix('^') {
+                let last: &mut PackedRef = last
+                    .and_then(|name| refs.get_mut(name))
+                    .ok_or_else(|| invalid(line))?;
+                last.peeled = Some(peeled.parse().map_err(|_| invalid(line))?);
+                continue;
+            }
+            let (id, name) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            let id = id.parse().map_err(|_| invalid(line))?;
+            refs.insert(name.to_owned(), PackedRef { id, peeled: None });
+            last = Some(name);
+        }
+        Ok(refs)
+    }
+
+    /// Removes the directories below `refs/<category>/` left empty once the
+    /// loose file for `name` is gone.
+    pub(crate) fn remove_empty_parents(&self, name: &str) {
+        remove_empty_parents(self.dir(name), &self.path(name));
+    }
+
+    /// Where the reflog of `name` lives, next to the ref itself.
+    pub(crate) fn log_path(&self, name: &str) -> PathBuf {
+        self.dir(name).join("logs").join(name)
+    }
+
+    /// Deletes the reflog of `name`, if it has one.
+    pub(crate) fn remove_log(&self, name: &str) -> Result<(), Error> {
+        let path = self.log_path(name);
+        match fs::remove_file(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(Error::Io(err.to_string())),
+        }
+        remove_empty_parents(&self.dir(name).join("logs"), &path);
+        Ok(())
+    }
+
+    /// The full name of the ref whose reflog a short name like `main` refers
+    /// to, found as [`RefStore::dwim`] does but among the refs that have one.
+    pub(crate) fn dwim_log(&self, name: &str) -> Option<String> {
+        DWIM_RULES
+            .iter()
+            .map(|(prefix, suffix)| format!("{prefix}{name}{suffix}"))
+            .find(|candidate| is_safe_name(candidate) && self.log_path(candidate).is_file())
+    }
+}
+
+/// Removes the parents of `path` left empty, but not `base` or the
+/// directories one or two levels below it, such as `refs/heads`.
+fn remove_empty_parents(base: &Path, path: &Path) {
+    let mut dir = path.parent();
+    while let Some(current) = dir {
+        let depth = current
+            .strip_prefix(base)
+            .map_or(0, |relative| relative.components().count());
+        if depth <= 2 || fs::remove_dir(current).is_err() {
+            break;
+        }
+        dir = current.parent();
+    }
+}
+
+/// The contents of a `packed-refs` file holding `refs`, each tag's peeled
+/// value included.
+pub(crate) fn format_packed(refs: &BTreeMap<String, PackedRef>) -> String {
+    let mut packed = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for (name, PackedRef { id, peeled }) in refs {
+        packed.push_str(&format!("{id} {name}\n"));
+        if let Some(peeled) = peeled {
+            packed.push_str(&format!("^{peeled}\n"));
+        }
+    }
+    packed
+}
+
+/// Whether `name` belongs to one worktree rather than being shared by all of them.
+pub(crate) fn is_per_worktree(name: &str) -> bool {
+    !name.starts_with("refs/")
+        || PER_WORKTREE_PREFIXES
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+}
+
+/// Whether `name` is a well-formed ref name, by the rules of `git check-ref-format`.
+pub(crate) fn is_valid_name(name: &str) -> bool {
+    let bad_char = |c: char| c.is_ascii_control() || " ~^:?*[\\".contains(c);
+    !name.is_empty()
+        && name != "@"
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.contains(bad_char)
+        && name.split('/').all(|component| {
+            !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+        })
+}
+
+/// Whether `name` may be looked up: a valid name under `refs/`, or an
+/// all-caps one like `HEAD` or `ORIG_HEAD` at the top of the gitdir.
+pub(crate) fn is_safe_name(name: &str) -> bool {
+    is_valid_name(name)
+        && (name.starts_with("refs/") || name.chars().all(|c| c.is_ascii_uppercase() || c == '_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const A: &str = "1111111111111111111111111111111111111111";
+    const B: &str = "2222222222222222222222222222222222222222";
+    const C: &str = "3333333333333333333333333333333333333333";
+
+    fn write(dir: &Path, name: &str, contents: &str) {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn id(hex: &str) -> ObjectId {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn symbolic_and_packed() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let store = RefStore::new(dir, dir);
+        write(dir, "HEAD", "ref: refs/heads/main\n");
+        assert_eq!(
+            store.follow("HEAD"),
+            Ok(("refs/heads/main".to_owned(), None))
+        );
+
+        write(
+            dir,
+            "packed-refs",
+            &format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {A} refs/heads/main\n{B} refs/tags/v1\n^{C}\n{A} refs/tags/v2\n"
+            ),
+        );
+        assert_eq!(store.resolve("HEAD"), Ok(Some(id(A))));
+        let packed = store.packed().unwrap();
+        assert_eq!(packed["refs/tags/v1"].peeled, Some(id(C)));
+        assert_eq!(packed["refs/tags/v2"].peeled, None);
+
+        // loose refs override packed ones
+        write(dir, "refs/heads/main", &format!("{B}\n"));
+        assert_eq!(store.resolve("HEAD"), Ok(Some(id(B))));
+        write(dir, "refs/heads/feature/x", &format!("{C}\n"));
+        write(
+            dir,
+            "refs/remotes/origin/HEAD",
+            "ref: refs/remotes/origin/main\n",
+        );
+        write(dir, "refs/heads/main.lock", &format!("{C}\n"));
+
+        let list: Vec<_> = store
+            .list()
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.name, r.id, r.peeled))
+            .collect();
+        assert_eq!(
+            list,
+            [
+                ("refs/heads/feature/x".to_owned(), id(C), None),
+                ("refs/heads/main".to_owned(), id(B), None),
+                ("refs/tags/v1".to_owned(), id(B), Some(id(C))),
+                ("refs/tags/v2".to_owned(), id(A), None),
+            ]
+        );
+
+        assert_eq!(store.read("refs/heads"), Ok(None));
+        assert_eq!(
+            store.read("refs/heads/../main"),
+            Err(Error::InvalidName("refs/heads/../main".to_owned()))
+        );
+        assert_eq!(
+            store.read("config"),
+            Err(Error::InvalidName("config".to_owned()))
+        );
+    }
+
+    #[test]
+    fn loops() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let store = RefStore::new(dir, dir);
+        write(dir, "refs/heads/a", "ref: refs/heads/b\n");
+        write(dir, "refs/heads/b", "ref: refs/heads/a\n");
+        assert_eq!(
+            store.resolve("refs/heads/a"),
+            Err(Error::SymrefLoop("refs/heads/a".to_owned()))
+        );
+
+        for i in 0..7 {
+            write(
+                dir,
+                &format!("refs/c{i}"),
+                &format!("ref: refs/c{}\n", i + 1),
+            );
+        }
+        write(dir, "refs/c7", &format!("{A}\n"));
+        assert_eq!(store.resolve("refs/c2"), Ok(Some(id(A))));
+        assert!(matches!(
+            store.resolve("refs/c0"),
+            Err(Error::SymrefTooDeep(_))
+        ));
+    }
+
+    #[test]
+    fn dwim() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        let store = RefStore::new(dir, dir);
+        write(dir, "refs/heads/x", &format!("{A}\n"));
+        write(dir, "refs/tags/x", &format!("{B}\n"));
+        write(
+            dir,
+            "refs/remotes/origin/HEAD",
+            "ref: refs/remotes/origin/main\n",
+        );
+        write(dir, "refs/remotes/origin/main", &format!("{C}\n"));
+
+        assert_eq!(store.dwim("x"), Ok(Some(("refs/tags/x".to_owned(), id(B)))));
+        assert_eq!(
+            store.dwim("heads/x"),
+            Ok(Some(("refs/heads/x".to_owned(), id(A))))
+        );
+        assert_eq!(
+            store.dwim("origin"),
+            Ok(Some(("refs/remotes/origin/HEAD".to_owned(), id(C))))
+        );
+        assert_eq!(store.dwim("y"), Ok(None));
+        assert_eq!(store.dwim("HEAD"), Ok(None));
+
+        assert_eq!(store.shorten("refs/heads/main"), Ok("main".to_owned()));
+        assert_eq!(store.shorten("refs/heads/x"), Ok("heads/x".to_owned()));
+        assert_eq!(store.shorten("refs/tags/x"), Ok("x".to_owned()));
+        assert_eq!(
+            store.shorten("refs/remotes/origin/HEAD"),
+            Ok("origin".to_owned())
+        );
+    }
+
+    #[test]
+    fn worktrees() {
+        let tempdir = TempDir::new().unwrap();
+        let common = tempdir.path();
+        let gitdir = common.join("worktrees/wt");
+        let main = RefStore::new(common, common);
+        let linked = RefStore::new(&gitdir, common);
+        write(common, "HEAD", &format!("{A}\n"));
+        write(&gitdir, "HEAD", &format!("{B}\n"));
+        write(common, "refs/heads/main", &format!("{A}\n"));
+        write(common, "refs/bisect/bad", &format!("{A}\n"));
+        write(&gitdir, "refs/bisect/bad", &format!("{C}\n"));
+
+        assert_eq!(main.resolve("HEAD"), Ok(Some(id(A))));
+        assert_eq!(linked.resolve("HEAD"), Ok(Some(id(B))));
+        assert_eq!(linked.resolve("refs/heads/main"), Ok(Some(id(A))));
+        let names = |store: &RefStore| {
+            store
+                .list()
+                .unwrap()
+                .into_iter()
+                .map(|r| (r.name, r.id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(&main),
+            [
+                ("refs/bisect/bad".to_owned(), id(A)),
+                ("refs/heads/main".to_owned(), id(A)),
+            ]
+        );
+        assert_eq!(
+            names(&linked),
+            [
+                ("refs/bisect/bad".to_owned(), id(C)),
+                ("refs/heads/main".to_owned(), id(A)),
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        for name in [
+            "refs/heads/main",
+            "refs/tags/v1.0",
+            "HEAD",
+            "refs/heads/a-b/c_d",
+        ] {
+            assert!(is_valid_name(name), "{name}");
+        }
+        for name in [
+            "",
+            "@",
+            "/refs",
+            "refs/heads/",
+            "refs/heads/a.",
+            "refs/heads/a..b",
+            "refs/heads/a@{1}",
+            "refs/heads/a b",
+            "refs/heads/a~1",
+            "refs/heads/a^",
+            "refs/heads/a:b",
+            "refs/heads/a?",
+            "refs/heads/a*",
+            "refs/heads/[a",
+            "refs/heads/a\\b",
+            "refs//heads",
+            "refs/.hidden",
+            "refs/heads/a.lock",
+        ] {
+            assert!(!is_valid_name(name), "{name}");
+        }
+        assert!(is_safe_name("ORIG_HEAD"));
+        assert!(!is_safe_name("index"));
+        assert!(is_per_worktree("HEAD"));
+        assert!(is_per_worktree("refs/worktree/x"));
+        assert!(!is_per_worktree("refs/heads/main"));
+    }
+}
@@ -0,0 +1,238 @@
+use std::{collections::HashSet, env, error::Error, fs, path::PathBuf};
+
+use application::clap;
+
+use crate::{
+    odb::ObjectStore as _,
+    pack::{self, PackInput, PackOptions},
+    prune, reachable,
+    repo::{Config as _, RealRepo},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum RepackError {
+    #[error("cannot delete packs in a precious-objects repo")]
+    PreciousObjects,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Pack everything reachable into a single pack, not just loose objects
+    #[arg(short = 'a')]
+    all: bool,
+    /// Like -a, but unreachable objects in old packs become loose instead of being dropped
+    #[arg(short = 'A')]
+    all_loosen: bool,
+    /// Remove the packs and loose objects the new pack makes redundant
+    #[arg(short = 'd')]
+    delete: bool,
+    /// Leave out objects borrowed from alternate object directories
+    #[arg(short = 'l', long)]
+    local: bool,
+    /// How many objects to try as delta bases for each object
+    #[arg(long, value_name = "n")]
+    window: Option<usize>,
+    /// The longest chain of deltas to create
+    #[arg(long, value_name = "n")]
+    depth: Option<usize>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let mut pack = pack_options(&repo)?;
+        pack.window = self.window.unwrap_or(pack.window);
+        pack.depth = self.depth.unwrap_or(pack.depth);
+        let options = RepackOptions {
+            all: self.all || self.all_loosen,
+            loosen_unreachable: self.all_loosen,
+            delete: self.delete,
+            local: self.local,
+            pack,
+        };
+        if repack(&repo, &options)?.is_none() {
+            println!("Nothing new to pack.");
+        }
+        Ok(())
+    }
+}
+
+/// What [`repack`] does, mirroring its flags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RepackOptions {
+    pub(crate) all: bool,
+    pub(crate) loosen_unreachable: bool,
+    pub(crate) delete: bool,
+    pub(crate) local: bool,
+    pub(crate) pack: PackOptions,
+}
+
+/// `pack.window` and `pack.depth`, or their defaults.
+pub(crate) fn pack_options(repo: &RealRepo) -> Result<PackOptions, Box<dyn Error>> {
+    let config = repo.config()?;
+    let defaults = PackOptions::default();
+    let get = |key, default: usize| -> Result<usize, Box<dyn Error>> {
+        Ok(match config.get_int("pack", key)? {
+            Some(value) => usize::try_from(value)?,
+            None => default,
+        })
+    };
+    Ok(PackOptions {
+        window: get("window", defaults.window)?,
+        depth: get("depth", defaults.depth)?,
+    })
+}
+
+/// Packs reachable objects: all of them, or only those still loose.
+///
+/// Returns the new pack's index, or `None` if there was nothing to pack.
+pub(crate) fn repack(
+    repo: &RealRepo,
+    options: &RepackOptions,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if options.delete && repo.has_precious_objects() {
+        return Err(RepackError::PreciousObjects.into());
+    }
+    let objects = repo.objects();
+    let roots = reachable::roots(repo)?;
+    let reached = reachable::walk(&objects, &roots)?;
+    let old_packs = objects.packs()?;
+    let alternates = objects.alternates()?;
+
+    let inputs: Vec<_> = reached
+        .iter()
+        .filter(|reached| {
+            options.all
+                || !old_packs
+                    .iter()
+                    .any(|pack| pack.index().find(&reached.id).is_some())
+        })
+        .filter(|reached| {
+            !options.local
+                || !alternates
+                    .iter()
+                    .any(|alternate| alternate.contains(&reached.id))
+        })
+        .map(|reached| PackInput {
+            id: reached.id,
+            name_hash: pack::name_hash(&reached.path),
+        })
+        .collect();
+    if inputs.is_empty() {
+        return Ok(None);
+    }
+    let new = pack::build(&objects, &inputs, &options.pack)?;
+    let idx = new.save(repo.commondir().join("objects/pack/pack"))?;
+
+    if options.delete {
+        if options.all {
+            let reachable: HashSet<_> = reached.iter().map(|reached| reached.id).collect();
+            for old in old_packs {
+                // a pack with a `.keep` file is never removed
+                if old.path() == idx.with_extension("pack")
+                    || old.path().with_extension("keep").exists()
+                {
+                    continue;
+                }
+                if options.loosen_unreachable {
+                    // keep the pack's age, so that prune expires them on schedule
+                    let modified = fs::metadata(old.path())?.modified()?;
+                    for id in old.index().ids().filter(|id| !reachable.contains(id)) {
+                        let raw = objects.read(&id)?;
+                        objects.loose().write(raw.kind, &raw.data)?;
+                        fs::File::options()
+                            .write(true)
+                            .open(objects.loose().path(&id))?
+                            .set_modified(modified)?;
+                    }
+                }
+                fs::remove_file(old.path().with_extension("idx"))?;
+                fs::remove_file(old.path())?;
+            }
+        }
+        prune::prune_packed(&repo.objects(), false)?;
+    }
+    Ok(Some(idx))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::{ObjectId, ObjectType},
+        refs::RefValue,
+        repo::{
+            InitOptions, RealRepoCreator, RefChange, RefTransaction, RefUpdate, RepoCreator as _,
+        },
+    };
+
+    /// Commits a file holding `contents` on top of `HEAD`.
+    fn commit(repo: &RealRepo, contents: &[u8], parent: Option<ObjectId>) -> ObjectId {
+        let objects = repo.objects();
+        let blob = objects.write(ObjectType::Blob, contents).unwrap();
+        let mut tree = b"100644 a\0".to_vec();
+        tree.extend_from_slice(blob.as_bytes());
+        let tree = objects.write(ObjectType::Tree, &tree).unwrap();
+        let mut commit = format!("tree {tree}\n");
+        if let Some(parent) = parent {
+            commit.push_str(&format!("parent {parent}\n"));
+        }
+        commit.push_str("author A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        let mut transaction = RefTransaction::new(repo.refs());
+        let change = RefChange::Set(RefValue::Direct(commit));
+        transaction.add(RefUpdate::new("HEAD", change, None));
+        transaction.commit().unwrap();
+        commit
+    }
+
+    #[test]
+    fn all_and_delete() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = RealRepoCreator::create(tempdir.path(), &InitOptions::default()).unwrap();
+        let dangling = repo
+            .objects()
+            .write(ObjectType::Blob, b"dangling\n")
+            .unwrap();
+        let first = commit(&repo, b"1\n", None);
+        let options = RepackOptions {
+            all: true,
+            loosen_unreachable: false,
+            delete: true,
+            local: true,
+            pack: PackOptions::default(),
+        };
+
+        let idx = repack(&repo, &options).unwrap().unwrap();
+        let objects = repo.objects();
+        let packs = objects.packs().unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].index().len(), 3);
+        // nothing reaches the dangling blob, so it is neither packed nor removed
+        assert_eq!(objects.loose().ids().unwrap(), [dangling]);
+
+        let second = commit(&repo, b"2\n", Some(first));
+        let new = repack(&repo, &options).unwrap().unwrap();
+        assert_ne!(new, idx);
+        // the pack list is read once per object database
+        let objects = repo.objects();
+        assert!(!idx.exists() && !idx.with_extension("pack").exists());
+        let packs = objects.packs().unwrap();
+        assert_eq!(packs.len(), 1);
+        assert_eq!(packs[0].path(), new.with_extension("pack"));
+        assert_eq!(packs[0].index().len(), 6);
+        assert!(packs[0].index().find(&second).is_some());
+        assert_eq!(objects.loose().ids().unwrap(), [dangling]);
+    }
+}
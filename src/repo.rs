@@ -1,12 +1,23 @@
 use std::{
+    collections::HashSet,
     convert::Infallible,
+    env,
     fs::{self, DirBuilder, OpenOptions},
-    io,
-    path::{Path, PathBuf},
+    io, iter,
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use configparser::ini::Ini;
-use indoc::indoc;
+use indoc::writedoc;
+
+use crate::{
+    config::{self, ConfigFile, ConfigSet, ConfigSources, Scope},
+    lockfile::{self, Lockfile},
+    object::{HashAlgorithm, ObjectId},
+    odb::ObjectDatabase,
+    reflog::{self, Entry},
+    refs::{self, RefStore, RefValue},
+};
 
 /// Actions that can be done to a repository.
 pub(crate) trait Repository: Sized {
@@ -16,7 +27,7 @@ pub(crate) trait Repository: Sized {
 }
 
 /// Loading/manipulating a config object.
-trait Config {
+pub(crate) trait Config {
     type Error: std::error::Error;
 
     fn load<P>(path: P) -> Result<Self, Self::Error>
@@ -24,7 +35,119 @@ trait Config {
         P: AsRef<Path>,
         Self: Sized;
 
-    fn getuint(&self, section: &str, field: &str) -> Result<u64, Self::Error>;
+    /// The value that wins for `key`, where `section` may include a subsection
+    /// (`remote.origin`); `Some(None)` for a key written without `=`.
+    fn get_value(&self, section: &str, key: &str) -> Option<Option<&str>>;
+
+    /// The lowercased names of the keys set in `section`, each listed once.
+    fn section_keys(&self, section: &str) -> Vec<String>;
+
+    /// `true/yes/on`, `false/no/off` or an integer; a key without `=` is true.
+    fn get_bool(&self, section: &str, key: &str) -> Result<Option<bool>, config::Error> {
+        match self.get_value(section, key) {
+            Some(None) => Ok(Some(true)),
+            value => typed(
+                value,
+                section,
+                key,
+                config::parse_bool,
+                |section, key, value| config::Error::InvalidBool {
+                    section,
+                    key,
+                    value,
+                },
+            ),
+        }
+    }
+
+    /// An integer with an optional `k`, `m` or `g` suffix.
+    fn get_int(&self, section: &str, key: &str) -> Result<Option<i64>, config::Error> {
+        let value = self.get_value(section, key);
+        typed(
+            value,
+            section,
+            key,
+            config::parse_int,
+            |section, key, value| config::Error::InvalidInt {
+                section,
+                key,
+                value,
+            },
+        )
+    }
+
+    /// A path, with `~/` and `%(prefix)/` expanded.
+    fn get_path(&self, section: &str, key: &str) -> Result<Option<PathBuf>, config::Error> {
+        let value = self.get_value(section, key);
+        typed(
+            value,
+            section,
+            key,
+            config::parse_path,
+            |section, key, value| config::Error::InvalidPath {
+                section,
+                key,
+                value,
+            },
+        )
+    }
+
+    /// A color, as the ANSI escape sequence that selects it.
+    fn get_color(&self, section: &str, key: &str) -> Result<Option<String>, config::Error> {
+        let value = self.get_value(section, key);
+        typed(
+            value,
+            section,
+            key,
+            config::parse_color,
+            |section, key, value| config::Error::InvalidColor {
+                section,
+                key,
+                value,
+            },
+        )
+    }
+
+    /// A timestamp before which things have expired, see [`config::parse_expiry_date`].
+    fn get_expiry_date(&self, section: &str, key: &str) -> Result<Option<u64>, config::Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let value = self.get_value(section, key);
+        let parse = |value: &str| config::parse_expiry_date(value, now);
+        typed(value, section, key, parse, |section, key, value| {
+            config::Error::InvalidExpiryDate {
+                section,
+                key,
+                value,
+            }
+        })
+    }
+}
+
+/// Converts a value for the typed getters of [`Config`].
+fn typed<T>(
+    value: Option<Option<&str>>,
+    section: &str,
+    key: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+    invalid: impl FnOnce(String, String, String) -> config::Error,
+) -> Result<Option<T>, config::Error> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let value = value.ok_or_else(|| config::Error::Missing {
+        section: section.to_owned(),
+        key: key.to_owned(),
+    })?;
+    match parse(value) {
+        Some(parsed) => Ok(Some(parsed)),
+        None => Err(invalid(
+            section.to_owned(),
+            key.to_owned(),
+            value.to_owned(),
+        )),
+    }
 }
 
 trait RepoPathHelper {
@@ -65,8 +188,8 @@ pub(crate) enum Error {
     NotGitRepository(PathBuf),
     #[error("Configuration file is missing")]
     ConfigFileMissing,
-    #[error("Unsupported repositoryformatversion: {0:?}")]
-    UnsupportedVersion(Option<u64>),
+    #[error("Unsupported repositoryformatversion: {0}")]
+    UnsupportedVersion(i64),
     #[error("Invalid config: {0}")]
     InvalidConfig(String),
     #[error("Error occurred during I/O: {0}")]
@@ -75,6 +198,40 @@ pub(crate) enum Error {
     NotADirectory(PathBuf),
     #[error("{0} is not empty")]
     NotEmpty(PathBuf),
+    #[error("Not a Git repository (or any parent up to mount point {0})")]
+    FilesystemBoundary(PathBuf),
+    #[error("{0} is outside repository")]
+    OutsideRepository(PathBuf),
+    #[error("This operation must be run in a work tree")]
+    BareRepository,
+    #[error("Invalid gitfile format: {0}")]
+    InvalidGitfile(PathBuf),
+    #[error("Unsupported repository extensions: {}", .0.join(", "))]
+    UnsupportedExtensions(Vec<String>),
+    #[error("Unsupported object format: {0}")]
+    UnsupportedObjectFormat(String),
+    #[error("Cannot lock ref '{0}': reference already exists")]
+    RefExists(String),
+    #[error("Cannot lock ref '{0}': unable to resolve reference")]
+    RefMissing(String),
+    #[error("Cannot lock ref '{0}': is at {1} but expected {2}")]
+    RefMoved(String, ObjectId, ObjectId),
+    #[error("Cannot lock ref '{0}': '{1}' exists")]
+    RefConflict(String, String),
+    #[error("Cannot lock ref '{0}': cannot process '{0}' and '{1}' at the same time")]
+    ConflictingUpdates(String, String),
+    #[error("Multiple updates for ref '{0}' not allowed")]
+    DuplicateRef(String),
+    #[error("Invalid date format: {0}")]
+    InvalidDate(String),
+    #[error(transparent)]
+    Config(#[from] crate::config::Error),
+    #[error(transparent)]
+    Refs(#[from] refs::Error),
+    #[error(transparent)]
+    Lock(#[from] lockfile::Error),
+    #[error(transparent)]
+    Reflog(#[from] reflog::Error),
 }
 
 /// A repository for which we have validated that `worktree` and `gitdir` exist.
@@ -82,28 +239,67 @@ pub(crate) enum Error {
 pub(crate) struct Repo<T> {
     inner: UnvalidatedRepo,
     config: T,
+    /// Where the repository was discovered from, relative to `worktree`.
+    prefix: PathBuf,
+    /// The gitdir holding shared state such as objects, refs and config.
+    ///
+    /// This differs from `gitdir` only for linked worktrees.
+    commondir: PathBuf,
+    /// The hash that names objects, from `extensions.objectFormat`.
+    object_format: HashAlgorithm,
+    /// `extensions.preciousObjects`: objects must never be deleted.
+    precious_objects: bool,
 }
 
-impl Config for Ini {
+/// Settings that influence repository discovery, mirroring git's environment variables.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct DiscoveryOptions {
+    /// `GIT_DIR`: use this gitdir instead of searching for one.
+    pub(crate) git_dir: Option<PathBuf>,
+    /// `GIT_WORK_TREE`: use this worktree instead of the one implied by the gitdir.
+    pub(crate) work_tree: Option<PathBuf>,
+    /// `GIT_CEILING_DIRECTORIES`: discovery never looks in these directories or above them.
+    pub(crate) ceiling_dirs: Vec<PathBuf>,
+    /// `GIT_DISCOVERY_ACROSS_FILESYSTEM`: whether discovery may cross mount points.
+    pub(crate) across_filesystems: bool,
+}
+
+impl DiscoveryOptions {
+    pub(crate) fn from_env() -> Self {
+        Self {
+            git_dir: env::var_os("GIT_DIR").map(PathBuf::from),
+            work_tree: env::var_os("GIT_WORK_TREE").map(PathBuf::from),
+            ceiling_dirs: env::var_os("GIT_CEILING_DIRECTORIES")
+                .map(|dirs| {
+                    env::split_paths(&dirs)
+                        .filter(|dir| dir.is_absolute())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            across_filesystems: env::var("GIT_DISCOVERY_ACROSS_FILESYSTEM")
+                .is_ok_and(|value| matches!(value.as_str(), "1" | "true" | "yes" | "on")),
+        }
+    }
+}
+
+impl Config for ConfigFile {
     type Error = Error;
 
     fn load<P>(path: P) -> Result<Self, Self::Error>
     where
         P: AsRef<Path>,
     {
-        let mut config = Self::new();
-        if !path.as_ref().exists() {
-            return Err(Error::ConfigFileMissing);
-        }
-        config.load(path).map_err(Error::InvalidConfig)?;
+        ConfigFile::read(path.as_ref())
+            .map_err(|err| Error::InvalidConfig(err.to_string()))?
+            .ok_or(Error::ConfigFileMissing)
+    }
 
-        Ok(config)
+    fn get_value(&self, section: &str, key: &str) -> Option<Option<&str>> {
+        self.get(&format!("{section}.{key}"))
     }
 
-    fn getuint(&self, section: &str, field: &str) -> Result<u64, Self::Error> {
-        self.getuint(section, field)
-            .map_err(Error::InvalidConfig)?
-            .ok_or(Error::UnsupportedVersion(None))
+    fn section_keys(&self, section: &str) -> Vec<String> {
+        config::section_keys(&self.entries(Scope::Local), section)
     }
 }
 
@@ -115,17 +311,822 @@ where
     type Error = Error;
 
     fn new(path: &Path) -> Result<Self, Self::Error> {
-        let unvalidated = UnvalidatedRepo::new(path).expect("UnvalidatedRepo::new() cannot fail");
+        let unvalidated = match find_gitdir(&path.join(".git"))? {
+            Some(gitdir) => UnvalidatedRepo {
+                worktree: Some(path.to_owned()),
+                gitdir,
+            },
+            None if is_git_directory(path) => UnvalidatedRepo::bare(path),
+            None => UnvalidatedRepo::new(path).expect("UnvalidatedRepo::new() cannot fail"),
+        };
         unvalidated.try_into()
     }
 }
 
+impl<T> Repo<T>
+where
+    T: Config,
+    Error: From<<T as Config>::Error>,
+{
+    /// Finds the repository containing `cwd`, honouring the `GIT_*` discovery variables.
+    pub(crate) fn discover(cwd: &Path) -> Result<Self, Error> {
+        Self::discover_with(cwd, &DiscoveryOptions::from_env())
+    }
+
+    /// Like [`Self::discover`], for commands that need a worktree: a bare
+    /// repository is [`Error::BareRepository`].
+    pub(crate) fn discover_worktree(cwd: &Path) -> Result<Self, Error> {
+        let repo = Self::discover(cwd)?;
+        repo.worktree()?;
+        Ok(repo)
+    }
+
+    pub(crate) fn discover_with(cwd: &Path, options: &DiscoveryOptions) -> Result<Self, Error> {
+        let (inner, prefix) = match &options.git_dir {
+            // like git, an explicit gitdir makes the current directory the top of the worktree
+            Some(git_dir) => {
+                let gitdir = cwd.join(git_dir);
+                let gitdir = find_gitdir(&gitdir)?.ok_or(Error::NotGitRepository(gitdir))?;
+                let inner = UnvalidatedRepo {
+                    worktree: Some(cwd.to_owned()),
+                    gitdir,
+                };
+                (inner, PathBuf::new())
+            }
+            None => UnvalidatedRepo::discover(cwd, options)?,
+        };
+        let mut repo = Self::try_from(inner)?;
+        repo.prefix = prefix;
+
+        // an explicit worktree wins even over `core.bare`
+        if let Some(work_tree) = &options.work_tree {
+            let work_tree = cwd.join(work_tree);
+            let work_tree = fs::canonicalize(&work_tree)
+                .ok()
+                .filter(|work_tree| work_tree.is_dir())
+                .ok_or(Error::NotADirectory(work_tree))?;
+            let cwd = fs::canonicalize(cwd).map_err(|err| Error::Io(err.to_string()))?;
+            repo.prefix = cwd
+                .strip_prefix(&work_tree)
+                .map(Path::to_owned)
+                .unwrap_or_default();
+            repo.inner.worktree = Some(work_tree);
+        }
+
+        Ok(repo)
+    }
+
+    /// The full configuration: system, global, local, worktree and command-line layers.
+    pub(crate) fn config(&self) -> Result<ConfigSet, Error> {
+        Ok(ConfigSet::load_sources(&self.config_sources()?, true)?)
+    }
+
+    /// The configuration layers that apply to this repository.
+    pub(crate) fn config_sources(&self) -> Result<ConfigSources, Error> {
+        let mut sources = ConfigSources::from_env()?;
+        sources.local = Some(self.commondir.join("config"));
+        if self.config.get_bool("extensions", "worktreeConfig")? == Some(true) {
+            sources.worktree = Some(self.gitdir().join("config.worktree"));
+        }
+        sources.includes.gitdir = Some(self.gitdir().to_owned());
+        sources.includes.branch = current_branch(self.gitdir());
+        Ok(sources)
+    }
+
+    /// Who is changing the repository and when, as `Name <email> <time> <zone>`.
+    ///
+    /// The identity comes from `GIT_COMMITTER_NAME` and `GIT_COMMITTER_EMAIL`,
+    /// or `user.name` and `user.email`; the time is now, in UTC, unless
+    /// `GIT_COMMITTER_DATE` gives it as `<seconds> <zone>`.
+    pub(crate) fn committer(&self) -> Result<String, Error> {
+        let config = self.config()?;
+        let value = |var, key| {
+            env::var(var).ok().or_else(|| {
+                let value = config.get_value("user", key).flatten();
+                value.map(str::to_owned)
+            })
+        };
+        let user = env::var("USER").unwrap_or_else(|_| "unknown".to_owned());
+        let name = value("GIT_COMMITTER_NAME", "name").unwrap_or_else(|| user.clone());
+        let email = value("GIT_COMMITTER_EMAIL", "email").unwrap_or(format!("{user}@localhost"));
+
+        let date = match env::var("GIT_COMMITTER_DATE") {
+            Ok(date) => {
+                let (seconds, zone) = date
+                    .trim_start_matches('@')
+                    .split_once(' ')
+                    .filter(|(seconds, zone)| {
+                        seconds.parse::<u64>().is_ok()
+                            && zone.len() == 5
+                            && zone.starts_with(['+', '-'])
+                            && zone[1..].bytes().all(|b| b.is_ascii_digit())
+                    })
+                    .ok_or_else(|| Error::InvalidDate(date.clone()))?;
+                format!("{seconds} {zone}")
+            }
+            Err(_) => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |now| now.as_secs());
+                format!("{now} +0000")
+            }
+        };
+        Ok(format!("{name} <{email}> {date}"))
+    }
+
+    /// A transaction updating the refs seen from this worktree.
+    pub(crate) fn transaction(&self) -> Result<RefTransaction, Error> {
+        self.transaction_for(self.refs())
+    }
+
+    /// A transaction updating `refs`, such as those of another worktree,
+    /// that records its changes in the reflogs as `core.logAllRefUpdates` says.
+    pub(crate) fn transaction_for(&self, refs: RefStore) -> Result<RefTransaction, Error> {
+        let config = self.config()?;
+        let create = match config.get_value("core", "logAllRefUpdates") {
+            Some(Some(value)) if value.eq_ignore_ascii_case("always") => LogAllRefUpdates::Always,
+            _ => match config.get_bool("core", "logAllRefUpdates")? {
+                Some(true) => LogAllRefUpdates::True,
+                Some(false) => LogAllRefUpdates::False,
+                // only repositories with a worktree keep reflogs by default
+                None if self.is_bare() => LogAllRefUpdates::False,
+                None => LogAllRefUpdates::True,
+            },
+        };
+        Ok(RefTransaction::new(refs).logged(self.committer()?, create))
+    }
+
+    /// The main worktree followed by the linked worktrees in `worktrees/`, sorted by name.
+    pub(crate) fn worktrees(&self) -> Result<Vec<Worktree>, Error> {
+        let bare = self.config.get_bool("core", "bare")? == Some(true);
+        let main_path = match self.commondir.file_name() {
+            Some(name) if !bare && name == ".git" => self.commondir.parent(),
+            _ => Some(self.commondir.as_path()),
+        };
+        let mut worktrees = vec![Worktree {
+            name: None,
+            path: main_path.map(Path::to_owned),
+            gitdir: self.commondir.clone(),
+            bare,
+            locked: None,
+            prunable: None,
+        }];
+
+        let entries = match fs::read_dir(self.commondir.join("worktrees")) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(worktrees),
+            Err(err) => return Err(Error::Io(err.to_string())),
+        };
+        let mut linked = Vec::new();
+        for entry in entries {
+            let entry = entry.map_err(|err| Error::Io(err.to_string()))?;
+            linked.push(Worktree::linked(&entry.path())?);
+        }
+        linked.sort_by(|a, b| a.name.cmp(&b.name));
+        worktrees.extend(linked);
+
+        Ok(worktrees)
+    }
+}
+
+/// A worktree attached to a repository, as recorded in its commondir.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Worktree {
+    /// The directory name under `worktrees/`; `None` for the main worktree.
+    pub(crate) name: Option<String>,
+    /// `None` when a linked worktree's `gitdir` file cannot be read.
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) gitdir: PathBuf,
+    pub(crate) bare: bool,
+    /// The contents of the `locked` file, which keeps `prune` away from worktrees
+    /// that are only missing because e.g. their removable drive is unmounted.
+    pub(crate) locked: Option<String>,
+    /// Why `prune` would remove this worktree.
+    pub(crate) prunable: Option<String>,
+}
+
+impl Worktree {
+    fn linked(gitdir: &Path) -> Result<Self, Error> {
+        let read = |file: &str| match fs::read_to_string(gitdir.join(file)) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::Io(err.to_string())),
+        };
+
+        let locked = if gitdir.is_dir() {
+            read("locked")?.map(|reason| reason.trim_end().to_owned())
+        } else {
+            None
+        };
+        // the `gitdir` file records where the worktree's `.git` file lives
+        let dotgit = if gitdir.is_dir() {
+            read("gitdir")?
+                .map(|path| PathBuf::from(path.trim_end()))
+                .filter(|path| !path.as_os_str().is_empty())
+        } else {
+            None
+        };
+        let path = dotgit.as_deref().and_then(Path::parent).map(Path::to_owned);
+
+        let prunable = if locked.is_some() {
+            None
+        } else if !gitdir.is_dir() {
+            Some("not a valid directory")
+        } else if !gitdir.join("gitdir").exists() {
+            Some("gitdir file does not exist")
+        } else if dotgit.is_none() {
+            Some("invalid gitdir file")
+        } else if !dotgit.as_deref().is_some_and(Path::exists) {
+            Some("gitdir file points to non-existent location")
+        } else {
+            None
+        };
+
+        Ok(Self {
+            name: gitdir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            path,
+            gitdir: gitdir.to_owned(),
+            bare: false,
+            locked,
+            prunable: prunable.map(str::to_owned),
+        })
+    }
+}
+
+impl<T> Repo<T> {
+    pub(crate) fn gitdir(&self) -> &Path {
+        &self.inner.gitdir
+    }
+
+    /// The worktree, or [`Error::BareRepository`] for commands that need one.
+    pub(crate) fn worktree(&self) -> Result<&Path, Error> {
+        self.inner.worktree.as_deref().ok_or(Error::BareRepository)
+    }
+
+    pub(crate) fn is_bare(&self) -> bool {
+        self.inner.worktree.is_none()
+    }
+
+    /// The directory discovery started from, relative to the worktree.
+    pub(crate) fn prefix(&self) -> &Path {
+        &self.prefix
+    }
+
+    /// Resolves a path given on the command line to a path relative to the worktree.
+    pub(crate) fn pathspec<P>(&self, path: P) -> Result<PathBuf, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let worktree = self.worktree()?;
+        let joined = if path.is_absolute() {
+            path.strip_prefix(worktree)
+                .map_err(|_| Error::OutsideRepository(path.to_owned()))?
+                .to_owned()
+        } else {
+            self.prefix.join(path)
+        };
+
+        let mut resolved = PathBuf::new();
+        for component in joined.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    if !resolved.pop() {
+                        return Err(Error::OutsideRepository(path.to_owned()));
+                    }
+                }
+                Component::Normal(part) => resolved.push(part),
+                Component::RootDir | Component::Prefix(_) => {
+                    return Err(Error::OutsideRepository(path.to_owned()))
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// The gitdir shared by all worktrees, see [`Repo::gitdir`] for per-worktree state.
+    pub(crate) fn commondir(&self) -> &Path {
+        &self.commondir
+    }
+
+    pub(crate) fn object_format(&self) -> HashAlgorithm {
+        self.object_format
+    }
+
+    /// Whether maintenance must keep every object, even unreachable ones.
+    pub(crate) fn has_precious_objects(&self) -> bool {
+        self.precious_objects
+    }
+
+    /// The refs seen from this worktree.
+    pub(crate) fn refs(&self) -> RefStore {
+        RefStore::new(self.gitdir(), self.commondir())
+    }
+
+    /// The object database, searching `GIT_ALTERNATE_OBJECT_DIRECTORIES` as well
+    /// as the alternates it lists itself.
+    pub(crate) fn objects(&self) -> ObjectDatabase {
+        let alternates = env::var_os("GIT_ALTERNATE_OBJECT_DIRECTORIES")
+            .map(|dirs| env::split_paths(&dirs).collect())
+            .unwrap_or_default();
+        ObjectDatabase::new(self.commondir.join("objects"), self.object_format)
+            .with_alternates(alternates)
+    }
+}
+
+/// What a [`RefUpdate`] does to its ref.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum RefChange {
+    Set(RefValue),
+    Delete,
+    /// Leaves the ref alone, only checking its old value.
+    Verify,
+}
+
+/// One change in a [`RefTransaction`].
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RefUpdate {
+    pub(crate) name: String,
+    pub(crate) change: RefChange,
+    /// The id the ref must have for the transaction to go ahead: the null id
+    /// if it must not exist, or `None` to accept any value.
+    pub(crate) old: Option<ObjectId>,
+    /// Whether a symbolic ref is updated by changing the ref it points to.
+    pub(crate) deref: bool,
+    /// Why the ref changed, for the reflog.
+    pub(crate) message: String,
+}
+
+impl RefUpdate {
+    pub(crate) fn new<S>(name: S, change: RefChange, old: Option<ObjectId>) -> Self
+    where
+        S: Into<String>,
+    {
+        Self {
+            name: name.into(),
+            change,
+            old,
+            deref: true,
+            message: String::new(),
+        }
+    }
+
+    /// Points a new ref at `id`, failing if the ref already exists.
+    pub(crate) fn create<S>(name: S, id: ObjectId) -> Self
+    where
+        S: Into<String>,
+    {
+        let null = ObjectId::null(id.algorithm());
+        Self::new(name, RefChange::Set(RefValue::Direct(id)), Some(null))
+    }
+
+    /// Changes a symbolic ref itself rather than the ref it points to.
+    pub(crate) fn no_deref(mut self) -> Self {
+        self.deref = false;
+        self
+    }
+
+    pub(crate) fn with_message<S>(mut self, message: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.message = message.into();
+        self
+    }
+}
+
+/// `core.logAllRefUpdates`: which refs get a reflog when they have none yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LogAllRefUpdates {
+    /// Only refs that already have a reflog keep one.
+    False,
+    /// `HEAD`, branches, remote-tracking refs and notes.
+    True,
+    Always,
+}
+
+impl LogAllRefUpdates {
+    fn creates_log(self, name: &str) -> bool {
+        match self {
+            LogAllRefUpdates::False => false,
+            LogAllRefUpdates::True => {
+                name == "HEAD"
+                    || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                        .iter()
+                        .any(|prefix| name.starts_with(prefix))
+            }
+            LogAllRefUpdates::Always => true,
+        }
+    }
+}
+
+/// Ref updates that happen together or not at all.
+///
+/// Every ref is locked with `<ref>.lock` and its old value checked before
+/// anything is written, so concurrent processes cannot lose each other's
+/// updates; if a lock or check fails, every lock is released and no ref
+/// changes. Should a write fail after that, the refs already written are put
+/// back as they were.
+#[derive(Debug)]
+pub(crate) struct RefTransaction {
+    refs: RefStore,
+    updates: Vec<RefUpdate>,
+    /// Who makes the changes, and which reflogs record them.
+    reflog: Option<(String, LogAllRefUpdates)>,
+}
+
+impl RefTransaction {
+    /// A transaction that leaves the reflogs alone.
+    pub(crate) fn new(refs: RefStore) -> Self {
+        Self {
+            refs,
+            updates: Vec::new(),
+            reflog: None,
+        }
+    }
+
+    /// Records each change in the reflog of the ref, and in that of `HEAD`
+    /// when it is the branch `HEAD` points to.
+    pub(crate) fn logged(mut self, committer: String, create: LogAllRefUpdates) -> Self {
+        self.reflog = Some((committer, create));
+        self
+    }
+
+    pub(crate) fn add(&mut self, update: RefUpdate) -> &mut Self {
+        self.updates.push(update);
+        self
+    }
+
+    pub(crate) fn commit(self) -> Result<(), Error> {
+        self.prepare()?.commit()
+    }
+
+    /// Locks and checks every ref, leaving only the writes to do.
+    pub(crate) fn prepare(self) -> Result<PreparedTransaction, Error> {
+        let names = self.check()?;
+        let refs = self.refs.clone();
+        self.lock(&names).inspect_err(|_| {
+            // the locks are gone by now, but not the directories made for them
+            for name in &names {
+                refs.remove_empty_parents(name);
+            }
+        })
+    }
+
+    /// The full name of the ref each update changes, once they are known to
+    /// be valid, distinct and free of conflicts; nothing is touched yet.
+    fn check(&self) -> Result<Vec<String>, Error> {
+        let mut seen = HashSet::new();
+        let mut names = Vec::new();
+        for update in &self.updates {
+            let name = if update.deref {
+                self.refs.follow(&update.name)?.0
+            } else {
+                update.name.clone()
+            };
+            if !refs::is_safe_name(&name) {
+                return Err(refs::Error::InvalidName(name).into());
+            }
+            if !seen.insert(name.clone()) {
+                return Err(Error::DuplicateRef(name));
+            }
+            names.push(name);
+        }
+
+        // `refs/heads/a` and `refs/heads/a/b` cannot both exist, unless one of
+        // them is being deleted
+        let updates = || names.iter().map(String::as_str).zip(&self.updates);
+        let created: Vec<_> = updates()
+            .filter(|(_, update)| matches!(update.change, RefChange::Set(_)))
+            .map(|(name, _)| name)
+            .collect();
+        let deleted: Vec<_> = updates()
+            .filter(|(_, update)| update.change == RefChange::Delete)
+            .map(|(name, _)| name)
+            .collect();
+        let existing = self.refs.list()?;
+        for name in &created {
+            let conflict = |other: &&str| nested(name, other) || nested(other, name);
+            if let Some(other) = created.iter().copied().find(conflict) {
+                return Err(Error::ConflictingUpdates(
+                    name.to_string(),
+                    other.to_string(),
+                ));
+            }
+            let mut kept = existing
+                .iter()
+                .map(|reference| reference.name.as_str())
+                .filter(|other| !deleted.contains(other));
+            if let Some(other) = kept.find(conflict) {
+                return Err(Error::RefConflict(name.to_string(), other.to_owned()));
+            }
+        }
+        Ok(names)
+    }
+
+    /// Locks each of the refs `names`, as [`RefTransaction::check`] found
+    /// them, and checks its old value.
+    fn lock(self, names: &[String]) -> Result<PreparedTransaction, Error> {
+        let io = |err: io::Error| Error::Io(err.to_string());
+        let deleted: Vec<_> = names
+            .iter()
+            .zip(&self.updates)
+            .filter(|(_, update)| update.change == RefChange::Delete)
+            .map(|(name, _)| name.as_str())
+            .collect();
+
+        let head = match self.refs.read("HEAD") {
+            Ok(Some(RefValue::Symbolic(target))) => Some(target),
+            _ => None,
+        };
+
+        let mut locked = Vec::new();
+        for (name, update) in names.iter().zip(self.updates) {
+            let path = self.refs.path(name);
+            // a ref being deleted may be where this one's directory must go,
+            // so this one is locked once that is gone
+            let blocked = deleted.iter().any(|deleted| nested(deleted, name));
+            let mut lock = None;
+            if !blocked {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).map_err(io)?;
+                }
+                lock = Some(Lockfile::acquire(&path)?);
+            }
+            // only now that it is locked can the value be trusted
+            let current = self.refs.resolve(name)?;
+            let backup = Backup::read(&path)?;
+            let name = name.clone();
+            match (update.old, current) {
+                (Some(old), Some(_)) if old.is_null() => return Err(Error::RefExists(name)),
+                (Some(old), None) if !old.is_null() => return Err(Error::RefMissing(name)),
+                (Some(old), Some(current)) if old != current => {
+                    return Err(Error::RefMoved(name, current, old))
+                }
+                _ => {}
+            }
+            if let (Some(lock), RefChange::Set(value)) = (&mut lock, &update.change) {
+                lock.write_all(loose_contents(value).as_bytes())?;
+            }
+            let mut logs = Vec::new();
+            if let (Some((committer, create)), RefChange::Set(value)) =
+                (&self.reflog, &update.change)
+            {
+                // pointing a symbolic ref elsewhere is only logged with a reason
+                let new = match value {
+                    RefValue::Direct(id) => Some(*id),
+                    RefValue::Symbolic(_) if update.message.is_empty() => None,
+                    RefValue::Symbolic(target) => self.refs.resolve(target)?,
+                };
+                if let Some(new) = new {
+                    let old = current.unwrap_or(ObjectId::null(new.algorithm()));
+                    let entry = Entry::new(old, new, committer.clone(), &update.message);
+                    let through_head = head.as_ref() == Some(&name);
+                    for logged in iter::once(name.as_str()).chain(through_head.then_some("HEAD")) {
+                        // read now, so that a reflog that cannot be fails the
+                        // transaction before anything is written
+                        let backup = Backup::read(&self.refs.log_path(logged))?;
+                        if backup.is_some() || create.creates_log(logged) {
+                            logs.push(PendingLog {
+                                name: logged.to_owned(),
+                                entry: entry.clone(),
+                                backup,
+                            });
+                        }
+                    }
+                }
+            }
+            locked.push(LockedRef {
+                name,
+                change: update.change,
+                lock,
+                backup,
+                logs,
+            });
+        }
+
+        let mut packed = None;
+        if !deleted.is_empty() {
+            let mut lock = Lockfile::acquire(self.refs.packed_path())?;
+            let mut refs = self.refs.packed()?;
+            let len = refs.len();
+            refs.retain(|name, _| !deleted.contains(&name.as_str()));
+            // `packed-refs` is left alone unless a deleted ref was in it
+            if refs.len() != len {
+                let backup = Backup::read(lock.path())?;
+                lock.write_all(refs::format_packed(&refs).as_bytes())?;
+                packed = Some((lock, backup));
+            }
+        }
+        Ok(PreparedTransaction {
+            refs: self.refs,
+            locked,
+            packed,
+        })
+    }
+}
+
+/// A ref locked by [`RefTransaction::prepare`].
+#[derive(Debug)]
+struct LockedRef {
+    name: String,
+    change: RefChange,
+    /// `None` while a ref deleted in the same transaction stands where its
+    /// directory must go; it is locked once that ref is gone.
+    lock: Option<Lockfile>,
+    /// The loose ref as it was, to put back if the transaction fails.
+    backup: Option<Vec<u8>>,
+    /// Appended while the ref is still locked, so that entries for the same
+    /// ref are written in the order its changes are.
+    logs: Vec<PendingLog>,
+}
+
+/// A file a [`PreparedTransaction`] is about to replace or remove, as it was
+/// beforehand, so that a commit that fails partway can put it back.
+#[derive(Debug)]
+enum Backup {
+    /// A loose ref, or `None` if there was none.
+    Ref(String, Option<Vec<u8>>),
+    /// The reflog of a ref.
+    Log(String, Option<Vec<u8>>),
+    Packed(Option<Vec<u8>>),
+}
+
+impl Backup {
+    /// The contents of `path`, or `None` if no file is there.
+    fn read(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::NotFound
+                        | io::ErrorKind::NotADirectory
+                        | io::ErrorKind::IsADirectory
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(Error::Io(err.to_string())),
+        }
+    }
+
+    /// Puts the file back as well as it can; one file that cannot be restored
+    /// must not stop the others.
+    fn restore(self, refs: &RefStore) {
+        let (path, contents) = match self {
+            Backup::Ref(name, None) => {
+                let _ = fs::remove_file(refs.path(&name));
+                refs.remove_empty_parents(&name);
+                return;
+            }
+            Backup::Ref(name, contents) => (refs.path(&name), contents),
+            Backup::Log(name, None) => {
+                let _ = refs.remove_log(&name);
+                return;
+            }
+            Backup::Log(name, contents) => (refs.log_path(&name), contents),
+            Backup::Packed(contents) => (refs.packed_path(), contents),
+        };
+        match contents {
+            Some(contents) => {
+                // a ref created below this one may have left its directory
+                let _ = fs::remove_dir(&path);
+                if let Some(parent) = path.parent() {
+                    let _ = fs::create_dir_all(parent);
+                }
+                let _ = fs::write(path, contents);
+            }
+            None => {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// A [`RefTransaction`] holding the locks on all its refs, with their old
+/// values checked; dropping it releases them without changing anything.
+#[derive(Debug)]
+pub(crate) struct PreparedTransaction {
+    refs: RefStore,
+    locked: Vec<LockedRef>,
+    /// The new `packed-refs` when refs are deleted from it, and the old one.
+    packed: Option<(Lockfile, Option<Vec<u8>>)>,
+}
+
+/// An entry for the reflog of the ref `name`, which held `backup` beforehand.
+#[derive(Debug)]
+struct PendingLog {
+    name: String,
+    entry: Entry,
+    backup: Option<Vec<u8>>,
+}
+
+impl PreparedTransaction {
+    /// Writes every change along with its reflog entries, or, if one fails,
+    /// puts back the refs and reflogs already changed and releases the
+    /// remaining locks.
+    pub(crate) fn commit(self) -> Result<(), Error> {
+        let refs = self.refs.clone();
+        let mut done = Vec::new();
+        let result = self.apply(&mut done);
+        if result.is_err() {
+            for backup in done.into_iter().rev() {
+                backup.restore(&refs);
+            }
+        }
+        result
+    }
+
+    /// Makes the changes in turn, noting in `done` what each replaces before
+    /// it does so.
+    fn apply(self, done: &mut Vec<Backup>) -> Result<(), Error> {
+        let io = |err: io::Error| Error::Io(err.to_string());
+        // deleted refs go first, in case they are in the way of new ones
+        let (deleted, locked): (Vec<_>, Vec<_>) = self
+            .locked
+            .into_iter()
+            .partition(|locked| locked.change == RefChange::Delete);
+        for locked in deleted.into_iter().chain(locked) {
+            let LockedRef {
+                name,
+                change,
+                lock,
+                backup,
+                logs,
+            } = locked;
+            match change {
+                RefChange::Set(value) => {
+                    done.push(Backup::Ref(name.clone(), backup));
+                    let lock = match lock {
+                        Some(lock) => lock,
+                        None => {
+                            let path = self.refs.path(&name);
+                            if let Some(parent) = path.parent() {
+                                fs::create_dir_all(parent).map_err(io)?;
+                            }
+                            let mut lock = Lockfile::acquire(&path)?;
+                            lock.write_all(loose_contents(&value).as_bytes())?;
+                            lock
+                        }
+                    };
+                    for log in logs {
+                        done.push(Backup::Log(log.name.clone(), log.backup));
+                        reflog::append(&self.refs.log_path(&log.name), &log.entry)?;
+                    }
+                    lock.commit()?;
+                }
+                RefChange::Delete => {
+                    done.push(Backup::Ref(name.clone(), backup));
+                    match fs::remove_file(self.refs.path(&name)) {
+                        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(io(err)),
+                        _ => {}
+                    }
+                    drop(lock);
+                    self.refs.remove_empty_parents(&name);
+                    let log = Backup::read(&self.refs.log_path(&name))?;
+                    done.push(Backup::Log(name.clone(), log));
+                    self.refs.remove_log(&name)?;
+                }
+                RefChange::Verify => drop(lock),
+            }
+        }
+        // after the loose refs, so that a failure here has them all to put back
+        if let Some((lock, backup)) = self.packed {
+            done.push(Backup::Packed(backup));
+            lock.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the ref `inner` would live in the directory of the ref `outer`.
+fn nested(outer: &str, inner: &str) -> bool {
+    inner
+        .strip_prefix(outer)
+        .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// What the file of a loose ref holding `value` contains.
+fn loose_contents(value: &RefValue) -> String {
+    match value {
+        RefValue::Direct(id) => format!("{id}\n"),
+        RefValue::Symbolic(target) => format!("ref: {target}\n"),
+    }
+}
+
+/// The repository type used by commands.
+pub(crate) type RealRepo = Repo<ConfigFile>;
+
 /// A repository where `worktree` and `gitdir` may or may not exist.
 ///
-/// This is primarily useful for `init`.
+/// This is primarily useful for `init`. Bare repositories have no `worktree`.
 #[derive(Debug, PartialEq)]
 struct UnvalidatedRepo {
-    worktree: PathBuf,
+    worktree: Option<PathBuf>,
     gitdir: PathBuf,
 }
 
@@ -134,12 +1135,135 @@ impl Repository for UnvalidatedRepo {
 
     fn new(path: &Path) -> Result<Self, Self::Error> {
         Ok(Self {
-            worktree: path.to_owned(),
+            worktree: Some(path.to_owned()),
             gitdir: path.join(".git"),
         })
     }
 }
 
+impl UnvalidatedRepo {
+    fn bare(path: &Path) -> Self {
+        Self {
+            worktree: None,
+            gitdir: path.to_owned(),
+        }
+    }
+
+    /// Walks up from `cwd` until a directory containing `.git`, or a bare gitdir, is found.
+    ///
+    /// Also returns the position of `cwd` relative to the worktree.
+    fn discover(cwd: &Path, options: &DiscoveryOptions) -> Result<(Self, PathBuf), Error> {
+        let cwd = fs::canonicalize(cwd).map_err(|err| Error::Io(err.to_string()))?;
+        // the ceiling itself is not searched, unless we start there
+        let ceiling = options
+            .ceiling_dirs
+            .iter()
+            .map(|dir| fs::canonicalize(dir).unwrap_or_else(|_| dir.to_owned()))
+            .filter(|dir| cwd.starts_with(dir) && cwd != *dir)
+            .map(|dir| dir.components().count())
+            .max();
+        let cwd_device = device(&cwd)?;
+
+        let mut dir = cwd.as_path();
+        loop {
+            if let Some(gitdir) = find_gitdir(&dir.join(".git"))? {
+                let prefix = cwd
+                    .strip_prefix(dir)
+                    .expect("cwd is below its ancestors")
+                    .to_owned();
+                let repo = Self {
+                    worktree: Some(dir.to_owned()),
+                    gitdir,
+                };
+                return Ok((repo, prefix));
+            }
+            if is_git_directory(dir) {
+                return Ok((Self::bare(dir), PathBuf::new()));
+            }
+
+            let Some(parent) = dir.parent() else {
+                return Err(Error::NotGitRepository(cwd));
+            };
+            if ceiling.is_some_and(|ceiling| parent.components().count() <= ceiling) {
+                return Err(Error::NotGitRepository(cwd));
+            }
+            if !options.across_filesystems && device(parent)? != cwd_device {
+                return Err(Error::FilesystemBoundary(dir.to_owned()));
+            }
+            dir = parent;
+        }
+    }
+}
+
+/// Whether `path` looks like a gitdir: it has `HEAD`, and its commondir has `objects` and `refs`.
+fn is_git_directory(path: &Path) -> bool {
+    let Ok(common) = common_dir(path) else {
+        return false;
+    };
+    path.join("HEAD").is_file() && common.join("objects").is_dir() && common.join("refs").is_dir()
+}
+
+/// The gitdir that a `.git` entry refers to, if it is a gitdir or a valid gitfile.
+fn find_gitdir(dotgit: &Path) -> Result<Option<PathBuf>, Error> {
+    if dotgit.is_file() {
+        read_gitfile(dotgit).map(Some)
+    } else if is_git_directory(dotgit) {
+        Ok(Some(dotgit.to_owned()))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Follows a `.git` file of the form `gitdir: <path>`, as written for linked worktrees
+/// and submodules. Relative paths are relative to the file's directory.
+fn read_gitfile(path: &Path) -> Result<PathBuf, Error> {
+    let contents = fs::read_to_string(path).map_err(|err| Error::Io(err.to_string()))?;
+    let target = contents
+        .strip_prefix("gitdir: ")
+        .map(str::trim_end)
+        .filter(|target| !target.is_empty())
+        .ok_or_else(|| Error::InvalidGitfile(path.to_owned()))?;
+
+    let gitdir = path
+        .parent()
+        .expect("a file has a parent directory")
+        .join(target);
+    if !is_git_directory(&gitdir) {
+        return Err(Error::NotGitRepository(gitdir));
+    }
+    Ok(gitdir)
+}
+
+/// The branch `HEAD` points to, without `refs/heads/`; `None` when detached.
+fn current_branch(gitdir: &Path) -> Option<String> {
+    let head = fs::read_to_string(gitdir.join("HEAD")).ok()?;
+    let branch = head.strip_prefix("ref: refs/heads/")?.trim_end();
+    Some(branch.to_owned())
+}
+
+/// Follows `<gitdir>/commondir`, which points a linked worktree's gitdir at the main gitdir.
+fn common_dir(gitdir: &Path) -> Result<PathBuf, Error> {
+    match fs::read_to_string(gitdir.join("commondir")) {
+        Ok(contents) => Ok(gitdir.join(contents.trim_end())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(gitdir.to_owned()),
+        Err(err) => Err(Error::Io(err.to_string())),
+    }
+}
+
+#[cfg(unix)]
+fn device(path: &Path) -> Result<u64, Error> {
+    use std::os::unix::fs::MetadataExt as _;
+
+    fs::metadata(path)
+        .map(|metadata| metadata.dev())
+        .map_err(|err| Error::Io(err.to_string()))
+}
+
+#[cfg(not(unix))]
+fn device(_path: &Path) -> Result<u64, Error> {
+    Ok(0)
+}
+
 impl<T> TryFrom<UnvalidatedRepo> for Repo<T>
 where
     T: Config,
@@ -147,44 +1271,112 @@ where
 {
     type Error = Error;
 
-    fn try_from(inner: UnvalidatedRepo) -> Result<Self, Self::Error> {
-        if !inner.worktree.is_dir() {
-            return Err(Error::NotGitRepository(inner.worktree));
+    fn try_from(mut inner: UnvalidatedRepo) -> Result<Self, Self::Error> {
+        if let Some(worktree) = inner.worktree.take_if(|worktree| !worktree.is_dir()) {
+            return Err(Error::NotGitRepository(worktree));
         }
 
-        // check that the version is equal to 0
-        let config = T::load(inner.gitdir.join("config"))?;
-        let version = config.getuint("core", "repositoryformatversion")?;
-        if version != 0 {
-            return Err(Error::UnsupportedVersion(Some(version)));
+        let commondir = common_dir(&inner.gitdir)?;
+        let config = T::load(commondir.join("config"))?;
+        let version = config
+            .get_int("core", "repositoryformatversion")?
+            .ok_or_else(|| config::Error::Missing {
+                section: "core".to_owned(),
+                key: "repositoryformatversion".to_owned(),
+            })?;
+        let (object_format, precious_objects) = match version {
+            0 => (HashAlgorithm::Sha1, false),
+            // version 1 must refuse extensions it does not understand
+            1 => (
+                check_extensions(&config)?,
+                config.get_bool("extensions", "preciousObjects")? == Some(true),
+            ),
+            _ => return Err(Error::UnsupportedVersion(version)),
+        };
+
+        // like git, `core.bare` describes the main worktree only
+        if commondir == inner.gitdir && config.get_bool("core", "bare")? == Some(true) {
+            inner.worktree = None;
         }
 
-        Ok(Self { inner, config })
+        Ok(Self {
+            inner,
+            config,
+            prefix: PathBuf::new(),
+            commondir,
+            object_format,
+            precious_objects,
+        })
     }
 }
 
+/// The `extensions.*` keys this implementation understands.
+const KNOWN_EXTENSIONS: [&str; 5] = [
+    "noop",
+    "noop-v1",
+    "objectformat",
+    "preciousobjects",
+    "worktreeconfig",
+];
+
+/// Validates `extensions.*`, returning the object format they select.
+fn check_extensions<T: Config>(config: &T) -> Result<HashAlgorithm, Error> {
+    let unknown: Vec<String> = config
+        .section_keys("extensions")
+        .into_iter()
+        .filter(|key| !KNOWN_EXTENSIONS.contains(&key.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(Error::UnsupportedExtensions(unknown));
+    }
+
+    match config.get_value("extensions", "objectFormat") {
+        None => Ok(HashAlgorithm::Sha1),
+        Some(format) => {
+            let format = format.unwrap_or_default();
+            format
+                .parse()
+                .map_err(|_| Error::UnsupportedObjectFormat(format.to_owned()))
+        }
+    }
+}
+
+/// How `init` lays out a new repository.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct InitOptions {
+    /// Put the gitdir layout directly in the target path, without a worktree.
+    pub(crate) bare: bool,
+    /// The hash that names objects; anything but SHA-1 needs format version 1.
+    pub(crate) object_format: HashAlgorithm,
+}
+
 pub(crate) trait RepoCreator {
     type Repo: Repository;
     type Error: std::error::Error;
 
-    fn create<P>(path: P) -> Result<Self::Repo, Self::Error>
+    fn create<P>(path: P, options: &InitOptions) -> Result<Self::Repo, Self::Error>
     where
         P: AsRef<Path>;
 }
 
 pub(crate) struct RealRepoCreator;
 impl RepoCreator for RealRepoCreator {
-    type Repo = Repo<Ini>;
+    type Repo = Repo<ConfigFile>;
     type Error = Error;
 
-    fn create<P>(path: P) -> Result<Self::Repo, Self::Error>
+    fn create<P>(path: P, options: &InitOptions) -> Result<Self::Repo, Self::Error>
     where
         P: AsRef<Path>,
     {
-        let Ok(repo) = UnvalidatedRepo::new(path.as_ref());
-        if repo.worktree.exists() {
-            if !repo.worktree.is_dir() {
-                return Err(Error::NotADirectory(repo.worktree));
+        let root = path.as_ref();
+        let repo = if options.bare {
+            UnvalidatedRepo::bare(root)
+        } else {
+            UnvalidatedRepo::new(root).expect("UnvalidatedRepo::new() cannot fail")
+        };
+        if root.exists() {
+            if !root.is_dir() {
+                return Err(Error::NotADirectory(root.to_owned()));
             }
             if repo.gitdir.exists()
                 && fs::read_dir(&repo.gitdir)
@@ -195,8 +1387,7 @@ impl RepoCreator for RealRepoCreator {
                 return Err(Error::NotEmpty(repo.gitdir));
             }
         } else {
-            PathHelper::ensure_dir_exists(&repo.worktree)
-                .map_err(|err| Error::Io(err.to_string()))?;
+            PathHelper::ensure_dir_exists(root).map_err(|err| Error::Io(err.to_string()))?;
         }
 
         PathHelper::ensure_dir_exists(repo.gitdir.join("branches"))
@@ -215,23 +1406,37 @@ impl RepoCreator for RealRepoCreator {
         .map_err(|err| Error::Io(err.to_string()))?;
         fs::write(repo.gitdir.join("HEAD"), "ref: refs/heads/master\n")
             .map_err(|err| Error::Io(err.to_string()))?;
-        fs::write(repo.gitdir.join("config"), DefaultConfig.to_string())
+        let config = DefaultConfig {
+            bare: options.bare,
+            object_format: options.object_format,
+        };
+        fs::write(repo.gitdir.join("config"), config.to_string())
             .map_err(|err| Error::Io(err.to_string()))?;
 
         repo.try_into()
     }
 }
 
-struct DefaultConfig;
+struct DefaultConfig {
+    bare: bool,
+    object_format: HashAlgorithm,
+}
 impl std::fmt::Display for DefaultConfig {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let config = indoc! {"
+        let sha1 = self.object_format == HashAlgorithm::Sha1;
+        writedoc! {f, "
             [core]
-            repositoryformatversion = 0
+            repositoryformatversion = {version}
             filemode = false
-            bare = false
-        "};
-        write!(f, "{config}")
+            bare = {bare}
+        ", version = if sha1 { 0 } else { 1 }, bare = self.bare}?;
+        if !sha1 {
+            writedoc! {f, "
+                [extensions]
+                objectformat = {format}
+            ", format = self.object_format}?;
+        }
+        Ok(())
     }
 }
 
@@ -240,10 +1445,11 @@ mod tests {
     use tempfile::TempDir;
 
     use super::*;
+    use crate::{object::ObjectType, odb::ObjectStore as _};
 
     #[derive(Debug, PartialEq)]
     struct FakeConfig {
-        version: u64,
+        version: String,
     }
 
     impl Config for FakeConfig {
@@ -253,24 +1459,504 @@ mod tests {
         where
             P: AsRef<Path>,
         {
-            Ok(Self { version: 1 })
+            Ok(Self {
+                version: "2".to_owned(),
+            })
+        }
+
+        fn get_value(&self, section: &str, key: &str) -> Option<Option<&str>> {
+            let is_version = (section, key) == ("core", "repositoryformatversion");
+            is_version.then_some(Some(&self.version))
         }
 
-        fn getuint(&self, _section: &str, _field: &str) -> Result<u64, Self::Error> {
-            Ok(self.version)
+        fn section_keys(&self, _section: &str) -> Vec<String> {
+            Vec::new()
         }
     }
 
     #[test]
-    fn nonzero_version() {
+    fn unsupported_version() {
         let repo: Result<Repo<FakeConfig>, _> = Repo::new(&std::env::temp_dir());
-        assert_eq!(repo, Err(Error::UnsupportedVersion(Some(1))));
+        assert_eq!(repo, Err(Error::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn extensions() {
+        let tempdir = TempDir::new().unwrap();
+        let worktree = tempdir.path().join("test");
+        RealRepoCreator::create(&worktree, &InitOptions::default()).unwrap();
+        let config = worktree.join(".git/config");
+        let original = fs::read_to_string(&config).unwrap();
+
+        let set_config = |extra: &str| {
+            let text =
+                original.replace("repositoryformatversion = 0", "repositoryformatversion = 1");
+            fs::write(&config, format!("{text}{extra}")).unwrap();
+        };
+
+        set_config("[extensions]\n\tnoop = true\n\tobjectFormat = sha1\n\tworktreeConfig\n");
+        assert!(RealRepo::new(&worktree).is_ok());
+
+        set_config("[extensions]\n\tpartialClone = origin\n\tfancy = 1\n\tnoop\n");
+        assert_eq!(
+            RealRepo::new(&worktree),
+            Err(Error::UnsupportedExtensions(vec![
+                "partialclone".to_owned(),
+                "fancy".to_owned()
+            ]))
+        );
+
+        // extensions mean nothing in version 0
+        fs::write(&config, format!("{original}[extensions]\n\tfancy = 1\n")).unwrap();
+        assert!(RealRepo::new(&worktree).is_ok());
+    }
+
+    #[test]
+    fn sha256() {
+        let tempdir = TempDir::new().unwrap();
+        let worktree = tempdir.path().join("test");
+        let options = InitOptions {
+            object_format: HashAlgorithm::Sha256,
+            ..InitOptions::default()
+        };
+        let repo = RealRepoCreator::create(&worktree, &options).unwrap();
+        assert_eq!(repo.object_format(), HashAlgorithm::Sha256);
+        let id = repo.objects().write(ObjectType::Blob, b"hello\n").unwrap();
+        assert_eq!(
+            id.to_string(),
+            "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4"
+        );
+
+        let config = worktree.join(".git/config");
+        let text = fs::read_to_string(&config).unwrap();
+        assert!(text.contains("repositoryformatversion = 1"));
+        fs::write(&config, text.replace("sha256", "sha512")).unwrap();
+        assert_eq!(
+            RealRepo::new(&worktree),
+            Err(Error::UnsupportedObjectFormat("sha512".to_owned()))
+        );
+    }
+
+    #[test]
+    fn discover_from_subdirectory() {
+        let tempdir = TempDir::new().unwrap();
+        let worktree = fs::canonicalize(tempdir.path()).unwrap().join("test");
+        RealRepoCreator::create(&worktree, &InitOptions::default()).unwrap();
+        let subdir = worktree.join("a/b");
+        fs::create_dir_all(&subdir).unwrap();
+
+        let repo = RealRepo::discover_with(&subdir, &DiscoveryOptions::default()).unwrap();
+        assert_eq!(repo.worktree(), Ok(worktree.as_path()));
+        assert_eq!(repo.gitdir(), worktree.join(".git"));
+        assert_eq!(repo.prefix(), Path::new("a/b"));
+        assert_eq!(repo.pathspec("../c.txt"), Ok(PathBuf::from("a/c.txt")));
+        assert_eq!(repo.pathspec(worktree.join("d")), Ok(PathBuf::from("d")));
+        assert_eq!(
+            repo.pathspec("../../../e"),
+            Err(Error::OutsideRepository(PathBuf::from("../../../e")))
+        );
+    }
+
+    #[test]
+    fn discover_stops_at_ceiling() {
+        let tempdir = TempDir::new().unwrap();
+        let worktree = tempdir.path().join("test");
+        RealRepoCreator::create(&worktree, &InitOptions::default()).unwrap();
+        let subdir = worktree.join("a/b");
+        fs::create_dir_all(&subdir).unwrap();
+
+        let options = DiscoveryOptions {
+            ceiling_dirs: vec![worktree.join("a")],
+            ..DiscoveryOptions::default()
+        };
+        assert!(matches!(
+            RealRepo::discover_with(&subdir, &options),
+            Err(Error::NotGitRepository(_))
+        ));
+
+        // the ceiling itself is not searched, but everything below it is
+        let options = DiscoveryOptions {
+            ceiling_dirs: vec![worktree.clone()],
+            ..DiscoveryOptions::default()
+        };
+        assert!(RealRepo::discover_with(&subdir, &options).is_err());
+        let options = DiscoveryOptions {
+            ceiling_dirs: vec![tempdir.path().to_owned()],
+            ..DiscoveryOptions::default()
+        };
+        assert!(RealRepo::discover_with(&subdir, &options).is_ok());
+    }
+
+    #[test]
+    fn bare() {
+        let tempdir = TempDir::new().unwrap();
+        let gitdir = fs::canonicalize(tempdir.path()).unwrap().join("test.git");
+        let options = InitOptions {
+            bare: true,
+            ..InitOptions::default()
+        };
+        let repo = RealRepoCreator::create(&gitdir, &options).unwrap();
+        assert!(repo.is_bare());
+        assert!(gitdir.join("HEAD").is_file());
+        assert!(!gitdir.join(".git").exists());
+
+        let repo =
+            RealRepo::discover_with(&gitdir.join("refs"), &DiscoveryOptions::default()).unwrap();
+        assert_eq!(repo.gitdir(), gitdir);
+        assert_eq!(repo.worktree(), Err(Error::BareRepository));
+        assert_eq!(repo.pathspec("a"), Err(Error::BareRepository));
+        assert!(RealRepo::new(&gitdir).unwrap().is_bare());
+    }
+
+    #[test]
+    fn bare_by_config() {
+        let tempdir = TempDir::new().unwrap();
+        let worktree = tempdir.path().join("test");
+        RealRepoCreator::create(&worktree, &InitOptions::default()).unwrap();
+        fs::write(
+            worktree.join(".git/config"),
+            "[core]\nrepositoryformatversion = 0\nbare = true\n",
+        )
+        .unwrap();
+        assert!(RealRepo::new(&worktree).unwrap().is_bare());
+    }
+
+    #[test]
+    fn explicit_git_dir_and_work_tree() {
+        let tempdir = TempDir::new().unwrap();
+        let root = fs::canonicalize(tempdir.path()).unwrap();
+        RealRepoCreator::create(
+            root.join("store.git"),
+            &InitOptions {
+                bare: true,
+                ..InitOptions::default()
+            },
+        )
+        .unwrap();
+        let work_tree = root.join("files");
+        fs::create_dir_all(work_tree.join("sub")).unwrap();
+
+        let options = DiscoveryOptions {
+            git_dir: Some(PathBuf::from("../store.git")),
+            ..DiscoveryOptions::default()
+        };
+        // `core.bare` still applies when only the gitdir is given
+        let repo = RealRepo::discover_with(&work_tree, &options).unwrap();
+        assert!(repo.is_bare());
+
+        let options = DiscoveryOptions {
+            git_dir: Some(root.join("store.git")),
+            work_tree: Some(PathBuf::from("..")),
+            ..DiscoveryOptions::default()
+        };
+        let repo = RealRepo::discover_with(&work_tree.join("sub"), &options).unwrap();
+        assert_eq!(repo.gitdir(), root.join("store.git"));
+        assert_eq!(repo.worktree(), Ok(work_tree.as_path()));
+        assert_eq!(repo.prefix(), Path::new("sub"));
+
+        let options = DiscoveryOptions {
+            git_dir: Some(root.join("missing.git")),
+            ..DiscoveryOptions::default()
+        };
+        assert_eq!(
+            RealRepo::discover_with(&work_tree, &options),
+            Err(Error::NotGitRepository(root.join("missing.git")))
+        );
+    }
+
+    #[test]
+    fn linked_worktree() {
+        let tempdir = TempDir::new().unwrap();
+        let root = fs::canonicalize(tempdir.path()).unwrap();
+        let main = RealRepoCreator::create(root.join("main"), &InitOptions::default()).unwrap();
+        let id = main.objects().write(ObjectType::Blob, b"shared\n").unwrap();
+
+        // the layout `git worktree add ../linked` produces
+        let gitdir = root.join("main/.git/worktrees/linked");
+        fs::create_dir_all(&gitdir).unwrap();
+        fs::write(gitdir.join("HEAD"), "ref: refs/heads/linked\n").unwrap();
+        fs::write(gitdir.join("commondir"), "../..\n").unwrap();
+        fs::create_dir_all(root.join("linked/sub")).unwrap();
+        fs::write(
+            root.join("linked/.git"),
+            "gitdir: ../main/.git/worktrees/linked\n",
+        )
+        .unwrap();
+
+        let repo = RealRepo::discover_with(&root.join("linked/sub"), &DiscoveryOptions::default())
+            .unwrap();
+        assert_eq!(repo.worktree(), Ok(root.join("linked").as_path()));
+        assert_eq!(fs::canonicalize(repo.gitdir()).unwrap(), gitdir);
+        assert_eq!(
+            fs::canonicalize(repo.commondir()).unwrap(),
+            root.join("main/.git")
+        );
+        assert_eq!(repo.prefix(), Path::new("sub"));
+        assert!(repo.objects().contains(&id));
+    }
+
+    #[test]
+    fn invalid_gitfile() {
+        let tempdir = TempDir::new().unwrap();
+        fs::write(tempdir.path().join(".git"), "not a gitfile\n").unwrap();
+        assert_eq!(
+            RealRepo::discover_with(tempdir.path(), &DiscoveryOptions::default()),
+            Err(Error::InvalidGitfile(
+                fs::canonicalize(tempdir.path()).unwrap().join(".git")
+            ))
+        );
+    }
+
+    #[test]
+    fn ref_transaction() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        fs::create_dir(dir.join("refs")).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let refs = RefStore::new(dir, dir);
+        let commit = |updates: Vec<RefUpdate>| {
+            let mut transaction = RefTransaction::new(refs.clone());
+            for update in updates {
+                transaction.add(update);
+            }
+            transaction.commit()
+        };
+        let id = |byte: &str| byte.repeat(20).parse::<ObjectId>().unwrap();
+        let set = |id| RefChange::Set(RefValue::Direct(id));
+
+        // through `HEAD` to the unborn branch
+        commit(vec![RefUpdate::create("HEAD", id("11"))]).unwrap();
+        assert_eq!(refs.resolve("refs/heads/main"), Ok(Some(id("11"))));
+        assert_eq!(
+            commit(vec![RefUpdate::create("refs/heads/main", id("22"))]),
+            Err(Error::RefExists("refs/heads/main".to_owned()))
+        );
+
+        // a failed check leaves every ref as it was, and no locks behind
+        let result = commit(vec![
+            RefUpdate::create("refs/tags/v1", id("22")),
+            RefUpdate::new("refs/heads/main", set(id("33")), Some(id("22"))),
+        ]);
+        assert_eq!(
+            result,
+            Err(Error::RefMoved(
+                "refs/heads/main".to_owned(),
+                id("11"),
+                id("22")
+            ))
+        );
+        assert_eq!(refs.resolve("refs/tags/v1"), Ok(None));
+        assert!(!dir.join("refs/tags/v1.lock").exists());
+        assert!(!dir.join("refs/heads/main.lock").exists());
+
+        let held = Lockfile::acquire(dir.join("refs/heads/main")).unwrap();
+        assert!(matches!(
+            commit(vec![RefUpdate::new("HEAD", set(id("33")), None)]),
+            Err(Error::Lock(lockfile::Error::Locked(_)))
+        ));
+        drop(held);
+
+        commit(vec![
+            RefUpdate::new("HEAD", set(id("22")), Some(id("11"))),
+            RefUpdate::create("refs/tags/v1", id("22")),
+            RefUpdate::new(
+                "refs/heads/topic",
+                RefChange::Verify,
+                Some(ObjectId::null(id("11").algorithm())),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(refs.resolve("HEAD"), Ok(Some(id("22"))));
+        assert_eq!(refs.read("refs/heads/topic"), Ok(None));
+        assert_eq!(
+            commit(vec![RefUpdate::create("refs/tags/v1/x", id("22"))]),
+            Err(Error::RefConflict(
+                "refs/tags/v1/x".to_owned(),
+                "refs/tags/v1".to_owned()
+            ))
+        );
+        assert_eq!(
+            commit(vec![
+                RefUpdate::new("HEAD", RefChange::Verify, None),
+                RefUpdate::new("refs/heads/main", RefChange::Verify, None),
+            ]),
+            Err(Error::DuplicateRef("refs/heads/main".to_owned()))
+        );
+
+        // a prepared transaction holds its locks until it is committed or dropped
+        let mut transaction = RefTransaction::new(refs.clone());
+        transaction.add(RefUpdate::create("refs/tags/v2", id("22")));
+        let prepared = transaction.prepare().unwrap();
+        assert!(dir.join("refs/tags/v2.lock").exists());
+        drop(prepared);
+        assert!(!dir.join("refs/tags/v2.lock").exists());
+        assert_eq!(refs.read("refs/tags/v2"), Ok(None));
+
+        // a deleted ref goes from `packed-refs` as well
+        let packed = format!("{} refs/tags/v1\n{} refs/tags/v2\n", id("33"), id("44"));
+        fs::write(dir.join("packed-refs"), packed).unwrap();
+        commit(vec![RefUpdate::new(
+            "refs/tags/v1",
+            RefChange::Delete,
+            Some(id("22")),
+        )])
+        .unwrap();
+        assert_eq!(refs.read("refs/tags/v1"), Ok(None));
+        assert_eq!(refs.resolve("refs/tags/v2"), Ok(Some(id("44"))));
+
+        // `HEAD` itself becomes detached
+        let update = RefUpdate::new("HEAD", set(id("44")), None).no_deref();
+        commit(vec![update]).unwrap();
+        assert_eq!(refs.read("HEAD"), Ok(Some(RefValue::Direct(id("44")))));
+        assert_eq!(refs.resolve("refs/heads/main"), Ok(Some(id("22"))));
+    }
+
+    #[test]
+    fn ref_transaction_nested() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        fs::create_dir_all(dir.join("refs/heads")).unwrap();
+        let refs = RefStore::new(dir, dir);
+        let commit = |updates: Vec<RefUpdate>| {
+            let mut transaction = RefTransaction::new(refs.clone());
+            for update in updates {
+                transaction.add(update);
+            }
+            transaction.commit()
+        };
+        let id = |byte: &str| byte.repeat(20).parse::<ObjectId>().unwrap();
+        let delete = |name| RefUpdate::new(name, RefChange::Delete, None);
+
+        // two new refs in the same batch conflict before anything is made
+        assert_eq!(
+            commit(vec![
+                RefUpdate::create("refs/heads/q", id("11")),
+                RefUpdate::create("refs/heads/q/r", id("11")),
+            ]),
+            Err(Error::ConflictingUpdates(
+                "refs/heads/q".to_owned(),
+                "refs/heads/q/r".to_owned()
+            ))
+        );
+        assert!(!dir.join("refs/heads/q").exists());
+
+        // a ref being deleted is no conflict, whichever side it is on
+        commit(vec![RefUpdate::create("refs/heads/y", id("11"))]).unwrap();
+        commit(vec![
+            delete("refs/heads/y"),
+            RefUpdate::create("refs/heads/y/sub", id("22")),
+        ])
+        .unwrap();
+        assert_eq!(refs.resolve("refs/heads/y/sub"), Ok(Some(id("22"))));
+        commit(vec![
+            RefUpdate::create("refs/heads/y", id("33")),
+            delete("refs/heads/y/sub"),
+        ])
+        .unwrap();
+        assert_eq!(refs.resolve("refs/heads/y"), Ok(Some(id("33"))));
+
+        // directories made for locks go again when a check fails
+        let result = commit(vec![
+            RefUpdate::create("refs/heads/a/b/c", id("11")),
+            RefUpdate::create("refs/heads/y", id("11")),
+        ]);
+        assert_eq!(result, Err(Error::RefExists("refs/heads/y".to_owned())));
+        assert!(!dir.join("refs/heads/a").exists());
+    }
+
+    #[test]
+    fn ref_transaction_rollback() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        fs::create_dir_all(dir.join("refs/heads")).unwrap();
+        let refs = RefStore::new(dir, dir);
+        let id = |byte: &str| byte.repeat(20).parse::<ObjectId>().unwrap();
+        let set = |id| RefChange::Set(RefValue::Direct(id));
+        fs::write(dir.join("refs/heads/a"), format!("{}\n", id("11"))).unwrap();
+        let packed = format!("{} refs/heads/c\n", id("33"));
+        fs::write(dir.join("packed-refs"), &packed).unwrap();
+
+        let mut transaction = RefTransaction::new(refs.clone());
+        transaction
+            .add(RefUpdate::new("refs/heads/a", set(id("22")), None))
+            .add(RefUpdate::new("refs/heads/c", RefChange::Delete, None))
+            .add(RefUpdate::create("refs/heads/b", id("22")));
+        let prepared = transaction.prepare().unwrap();
+        // `b` can no longer be renamed into place once `a` has been
+        fs::create_dir_all(dir.join("refs/heads/b/x")).unwrap();
+        assert!(matches!(prepared.commit(), Err(Error::Lock(_))));
+
+        assert_eq!(refs.resolve("refs/heads/a"), Ok(Some(id("11"))));
+        assert_eq!(refs.resolve("refs/heads/c"), Ok(Some(id("33"))));
+        assert_eq!(fs::read_to_string(dir.join("packed-refs")).unwrap(), packed);
+        assert!(!dir.join("refs/heads/b.lock").exists());
+        assert!(!dir.join("packed-refs.lock").exists());
+    }
+
+    #[test]
+    fn logged_ref_transaction() {
+        let tempdir = TempDir::new().unwrap();
+        let dir = tempdir.path();
+        fs::create_dir(dir.join("refs")).unwrap();
+        fs::write(dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        let refs = RefStore::new(dir, dir);
+        let committer = "A U Thor <a@example.com> 1700000000 +0000";
+        let commit = |update: RefUpdate| {
+            let mut transaction = RefTransaction::new(refs.clone())
+                .logged(committer.to_owned(), LogAllRefUpdates::True);
+            transaction.add(update);
+            transaction.commit()
+        };
+        let id = |byte: &str| byte.repeat(20).parse::<ObjectId>().unwrap();
+        let null = ObjectId::null(HashAlgorithm::Sha1);
+        let entry = |old, new, message| Entry::new(old, new, committer.to_owned(), message);
+        let log = |name| reflog::read(&refs.log_path(name)).unwrap();
+
+        // the branch `HEAD` points to is logged for both
+        commit(RefUpdate::create("refs/heads/main", id("11")).with_message("create")).unwrap();
+        let moved = RefChange::Set(RefValue::Direct(id("22")));
+        commit(RefUpdate::new("HEAD", moved, None).with_message("move")).unwrap();
+        let expected = vec![
+            entry(null, id("11"), "create"),
+            entry(id("11"), id("22"), "move"),
+        ];
+        assert_eq!(log("refs/heads/main"), expected);
+        assert_eq!(log("HEAD"), expected);
+
+        // tags are only logged once something else has created their log
+        commit(RefUpdate::create("refs/tags/v1", id("11"))).unwrap();
+        assert!(!refs.log_path("refs/tags/v1").exists());
+
+        // a deleted ref takes its log with it
+        commit(RefUpdate::create("refs/heads/topic", id("11"))).unwrap();
+        assert_eq!(log("refs/heads/topic"), vec![entry(null, id("11"), "")]);
+        commit(RefUpdate::new("refs/heads/topic", RefChange::Delete, None)).unwrap();
+        assert!(!refs.log_path("refs/heads/topic").exists());
+        assert_eq!(log("HEAD").len(), 2);
+
+        // a reflog that cannot be written undoes the refs written with it
+        fs::create_dir_all(refs.log_path("refs/heads/zz/sub")).unwrap();
+        let mut transaction =
+            RefTransaction::new(refs.clone()).logged(committer.to_owned(), LogAllRefUpdates::True);
+        transaction
+            .add(RefUpdate::create("refs/heads/aa", id("11")).with_message("aa"))
+            .add(RefUpdate::new("HEAD", RefChange::Delete, None).no_deref())
+            .add(RefUpdate::create("refs/heads/zz", id("11")));
+        assert!(matches!(transaction.commit(), Err(Error::Reflog(_))));
+        assert_eq!(refs.resolve("refs/heads/aa"), Ok(None));
+        assert!(!refs.log_path("refs/heads/aa").exists());
+        assert_eq!(
+            refs.read("HEAD"),
+            Ok(Some(RefValue::Symbolic("refs/heads/main".to_owned())))
+        );
+        assert_eq!(log("HEAD"), expected);
     }
 
     #[test]
     fn create() {
         let tempdir = TempDir::new().unwrap();
-        let _ = RealRepoCreator::create(tempdir.as_ref().join("test")).unwrap();
+        let _ = RealRepoCreator::create(tempdir.as_ref().join("test"), &InitOptions::default())
+            .unwrap();
         assert!(!fs::read(tempdir.as_ref().join("test/.git/config"))
             .unwrap()
             .is_empty());
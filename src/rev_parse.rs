@@ -1,8 +1,241 @@
+use std::{
+    env,
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
 use application::clap;
 
-use crate::Execute;
+use crate::{
+    config,
+    object::ObjectId,
+    odb::ObjectStore as _,
+    reflog::{self, Entry},
+    repo::RealRepo,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error, PartialEq)]
+enum RevParseError {
+    #[error("Ambiguous argument '{0}': unknown revision")]
+    UnknownRevision(String),
+    #[error("Needed a single revision")]
+    NeedSingle,
+    #[error("Log for '{0}' is empty")]
+    EmptyLog(String),
+    #[error("Log for '{0}' only has {1} entries")]
+    ShortLog(String, usize),
+    #[error("Invalid reflog selector: @{{{0}}}")]
+    InvalidSelector(String),
+}
 
 #[derive(Debug, clap::Args)]
-pub(crate) struct Args;
+pub(crate) struct Args {
+    /// Require exactly one revision that names an object
+    #[arg(long)]
+    verify: bool,
+    /// With --verify, exit with status 1 instead of failing
+    #[arg(short, long, requires = "verify")]
+    quiet: bool,
+    revisions: Vec<String>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        if self.verify {
+            let resolved = match self.revisions.as_slice() {
+                [revision] => resolve(&repo, revision).ok(),
+                _ => None,
+            };
+            match resolved {
+                Some(id) => println!("{id}"),
+                None if self.quiet => std::process::exit(1),
+                None => return Err(RevParseError::NeedSingle.into()),
+            }
+            return Ok(());
+        }
+
+        for revision in &self.revisions {
+            println!("{}", resolve(&repo, revision)?);
+        }
+        Ok(())
+    }
+}
+
+/// Resolves `revision`: a ref name, short or full, or a full or abbreviated
+/// object name.
+///
+/// A ref name may be followed by `@{<n>}` for the value it had `n` changes
+/// ago, or `@{<date>}` for the value it had then, both read from its reflog;
+/// without a name, these look back through the branch `HEAD` points to. `@`
+/// alone means `HEAD`.
+pub(crate) fn resolve(repo: &RealRepo, revision: &str) -> Result<ObjectId, Box<dyn Error>> {
+    let refs = repo.refs();
+    let unknown = || RevParseError::UnknownRevision(revision.to_owned());
+
+    if let Some((name, selector)) = split_selector(revision) {
+        let name = match name {
+            "" => refs.shorten(&refs.follow("HEAD")?.0)?,
+            "@" => "HEAD".to_owned(),
+            name => name.to_owned(),
+        };
+        let Some(full) = refs.dwim_log(&name) else {
+            return match refs.dwim(&name)? {
+                Some(_) => Err(RevParseError::EmptyLog(name).into()),
+                None => Err(unknown().into()),
+            };
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+        let selector = Selector::parse(selector, now)?;
+        let entries = reflog::read(&refs.log_path(&full))?;
+        return Ok(reflog_at(&name, &entries, selector)?);
+    }
+
+    let name = if revision == "@" { "HEAD" } else { revision };
+    if let Some((_, id)) = refs.dwim(name)? {
+        return Ok(id);
+    }
+    Ok(repo.objects().resolve(name).map_err(|_| unknown())?)
+}
+
+/// Splits `<name>@{<selector>}` into its name, which may be empty, and
+/// selector.
+fn split_selector(revision: &str) -> Option<(&str, &str)> {
+    revision
+        .strip_suffix('}')
+        .and_then(|revision| revision.rsplit_once("@{"))
+}
+
+/// Which entry of a reflog `@{...}` picks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Selector {
+    /// The value `n` changes back from the newest.
+    Nth(usize),
+    /// The value at a time, in seconds since the epoch.
+    Date(u64),
+}
+
+impl Selector {
+    fn parse(selector: &str, now: u64) -> Result<Self, RevParseError> {
+        match selector.parse::<u64>() {
+            // as in git, a number this large is a timestamp rather than a count
+            Ok(date) if date >= 100_000_000 => Ok(Selector::Date(date)),
+            Ok(n) => Ok(Selector::Nth(n as usize)),
+            Err(_) => config::parse_expiry_date(selector, now)
+                .map(Selector::Date)
+                .ok_or_else(|| RevParseError::InvalidSelector(selector.to_owned())),
+        }
+    }
+}
+
+/// What the reflog `entries` of `name`, oldest first, say it pointed to at
+/// `selector`.
+fn reflog_at(name: &str, entries: &[Entry], selector: Selector) -> Result<ObjectId, RevParseError> {
+    let Some(oldest) = entries.first() else {
+        return Err(RevParseError::EmptyLog(name.to_owned()));
+    };
+    let date = match selector {
+        Selector::Nth(n) => {
+            return match entries.len().checked_sub(n + 1) {
+                Some(index) => Ok(entries[index].new),
+                // one further back is where the oldest entry moved the ref from
+                None if n == entries.len() && !oldest.old.is_null() => Ok(oldest.old),
+                None => Err(RevParseError::ShortLog(name.to_owned(), entries.len())),
+            };
+        }
+        Selector::Date(date) => date,
+    };
+    let newest_before = entries
+        .iter()
+        .rev()
+        .find(|entry| entry.time().is_some_and(|time| time <= date));
+    Ok(match newest_before {
+        Some(entry) => entry.new,
+        // the log only goes back so far; before it, the ref was wherever its
+        // first entry moved it from
+        None if !oldest.old.is_null() => oldest.old,
+        None => oldest.new,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors() {
+        assert_eq!(split_selector("main@{1}"), Some(("main", "1")));
+        assert_eq!(split_selector("@{2.days.ago}"), Some(("", "2.days.ago")));
+        assert_eq!(split_selector("a@{b}@{3}"), Some(("a@{b}", "3")));
+        assert_eq!(split_selector("main"), None);
+        assert_eq!(split_selector("@"), None);
+
+        let now = 1_700_000_000;
+        assert_eq!(Selector::parse("0", now), Ok(Selector::Nth(0)));
+        assert_eq!(
+            Selector::parse("99999999", now),
+            Ok(Selector::Nth(99_999_999))
+        );
+        assert_eq!(
+            Selector::parse("100000000", now),
+            Ok(Selector::Date(100_000_000))
+        );
+        assert_eq!(
+            Selector::parse("1.day.ago", now),
+            Ok(Selector::Date(now - 86400))
+        );
+        assert_eq!(
+            Selector::parse("soon", now),
+            Err(RevParseError::InvalidSelector("soon".to_owned()))
+        );
+    }
+
+    #[test]
+    fn lookup() {
+        let id = |hex: &str| hex.repeat(40).parse::<ObjectId>().unwrap();
+        let entry = |old, new, time| {
+            let committer = format!("A U Thor <a@example.com> {time} +0000");
+            Entry::new(id(old), id(new), committer, "")
+        };
+        // `1` was already there when the log began
+        let entries = [
+            entry("1", "2", 100),
+            entry("2", "3", 200),
+            entry("3", "4", 300),
+        ];
+        let at = |selector| reflog_at("main", &entries, selector);
+
+        assert_eq!(at(Selector::Nth(0)), Ok(id("4")));
+        assert_eq!(at(Selector::Nth(2)), Ok(id("2")));
+        assert_eq!(at(Selector::Nth(3)), Ok(id("1")));
+        assert_eq!(
+            at(Selector::Nth(4)),
+            Err(RevParseError::ShortLog("main".to_owned(), 3))
+        );
+        assert_eq!(at(Selector::Date(250)), Ok(id("3")));
+        assert_eq!(at(Selector::Date(300)), Ok(id("4")));
+        assert_eq!(at(Selector::Date(50)), Ok(id("1")));
 
-impl Execute for Args {}
+        // a log that starts with the ref's creation can go no further back
+        let created = [entry("0", "2", 100), entry("2", "3", 200)];
+        let at = |selector| reflog_at("main", &created, selector);
+        assert_eq!(at(Selector::Date(50)), Ok(id("2")));
+        assert_eq!(
+            at(Selector::Nth(2)),
+            Err(RevParseError::ShortLog("main".to_owned(), 2))
+        );
+        assert_eq!(
+            reflog_at("main", &[], Selector::Nth(0)),
+            Err(RevParseError::EmptyLog("main".to_owned()))
+        );
+    }
+}
@@ -1,8 +1,21 @@
+use std::{env, error::Error};
+
 use application::clap;
 
-use crate::Execute;
+use crate::{repo::RealRepo, Execute};
 
 #[derive(Debug, clap::Args)]
 pub(crate) struct Args;
 
-impl Execute for Args {}
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        RealRepo::discover_worktree(&env::current_dir()?)?;
+        Ok(())
+    }
+}
@@ -1,8 +1,407 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
 use application::clap;
 
-use crate::Execute;
+use crate::{
+    object::{GitObject, ObjectId},
+    odb::{ObjectDatabase, ObjectStore as _},
+    refs::{self, Ref, RefStore},
+    repo::RealRepo,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum ShowRefError {
+    #[error("--verify requires a reference")]
+    NoReference,
+    #[error("'{0}' - not a valid ref")]
+    InvalidRef(String),
+    #[error("bad ref {0} ({1})")]
+    BadRef(String, ObjectId),
+}
 
 #[derive(Debug, clap::Args)]
-pub(crate) struct Args;
+pub(crate) struct Args {
+    /// Only show branches
+    #[arg(long)]
+    heads: bool,
+    /// Only show tags
+    #[arg(long)]
+    tags: bool,
+    /// Show HEAD as well, even if it matches no pattern
+    #[arg(long)]
+    head: bool,
+    /// Only show the refs named in full, failing if any does not exist
+    #[arg(long)]
+    verify: bool,
+    /// Also show what annotated tags point to, as `<ref>^{}`
+    #[arg(short, long)]
+    dereference: bool,
+    /// Only show object names, abbreviated to at least n characters if given
+    #[arg(short = 's', long, value_name = "n", require_equals = true)]
+    hash: Option<Option<usize>>,
+    /// Show nothing; only the exit status tells whether anything matched
+    #[arg(short, long)]
+    quiet: bool,
+    /// Print the lines of stdin naming refs that do not exist, optionally only
+    /// refs starting with pattern
+    #[arg(long, value_name = "pattern", require_equals = true)]
+    exclude_existing: Option<Option<String>>,
+    /// Show only refs whose last components match one of these
+    patterns: Vec<String>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        let refs = repo.refs();
+        let mut stdout = io::stdout().lock();
+        if let Some(pattern) = &self.exclude_existing {
+            let stdin = io::stdin().lock();
+            return exclude_existing(&refs, pattern.as_deref(), stdin, &mut stdout);
+        }
+        let objects = repo.objects();
+
+        if self.verify {
+            if !self.verify(&refs, &objects, &mut stdout)? {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+
+        let mut found = false;
+        if self.head {
+            if let Some(id) = refs.resolve("HEAD")? {
+                let head = Ref {
+                    name: "HEAD".to_owned(),
+                    id,
+                    peeled: None,
+                };
+                self.show(&mut stdout, &objects, &head)?;
+                found = true;
+            }
+        }
+        for reference in refs.list()? {
+            if self.matches(&reference.name) {
+                self.show(&mut stdout, &objects, &reference)?;
+                found = true;
+            }
+        }
+        if !found {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+
+    /// Shows each pattern, which must be the full name of a ref that exists;
+    /// `Ok(false)` if one does not and `--quiet` leaves that to the exit status.
+    fn verify(
+        &self,
+        refs: &RefStore,
+        objects: &ObjectDatabase,
+        stdout: &mut impl Write,
+    ) -> Result<bool, Box<dyn Error>> {
+        if self.patterns.is_empty() {
+            return Err(ShowRefError::NoReference.into());
+        }
+        for pattern in &self.patterns {
+            let full = pattern.starts_with("refs/") || pattern == "HEAD";
+            match full.then(|| refs.resolve(pattern).ok().flatten()).flatten() {
+                Some(id) => {
+                    let reference = Ref {
+                        name: pattern.clone(),
+                        id,
+                        peeled: None,
+                    };
+                    self.show(stdout, objects, &reference)?;
+                }
+                None if self.quiet => return Ok(false),
+                None => return Err(ShowRefError::InvalidRef(pattern.clone()).into()),
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether `name` passes `--heads`/`--tags` and matches a pattern, if any
+    /// were given, either whole or as its last components.
+    fn matches(&self, name: &str) -> bool {
+        if (self.heads || self.tags)
+            && !(self.heads && name.starts_with("refs/heads/")
+                || self.tags && name.starts_with("refs/tags/"))
+        {
+            return false;
+        }
+        self.patterns.is_empty()
+            || self.patterns.iter().any(|pattern| {
+                name.strip_suffix(pattern.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.ends_with('/'))
+            })
+    }
+
+    fn show(
+        &self,
+        stdout: &mut impl Write,
+        objects: &ObjectDatabase,
+        reference: &Ref,
+    ) -> Result<(), Box<dyn Error>> {
+        if !objects.contains(&reference.id) {
+            return Err(ShowRefError::BadRef(reference.name.clone(), reference.id).into());
+        }
+        if self.quiet {
+            return Ok(());
+        }
+        let hex = |id: &ObjectId| match self.hash {
+            Some(Some(len)) if len > 0 => objects.abbreviate(id, len),
+            _ => Ok(id.to_string()),
+        };
+
+        if self.hash.is_some() {
+            writeln!(stdout, "{}", hex(&reference.id)?)?;
+        } else {
+            writeln!(stdout, "{} {}", hex(&reference.id)?, reference.name)?;
+        }
+        if self.dereference {
+            if let Some(peeled) = peel(objects, reference)? {
+                writeln!(stdout, "{} {}^{{}}", hex(&peeled)?, reference.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What `reference` ultimately points to if it names an annotated tag, from
+/// `packed-refs` when it is recorded there.
+fn peel(objects: &ObjectDatabase, reference: &Ref) -> Result<Option<ObjectId>, Box<dyn Error>> {
+    if reference.peeled.is_some() {
+        return Ok(reference.peeled);
+    }
+    let (mut id, mut peeled) = (reference.id, None);
+    while let GitObject::Tag(tag) = objects.read_object(&id)? {
+        id = tag.object()?;
+        peeled = Some(id);
+    }
+    Ok(peeled)
+}
+
+/// Copies the lines of `input` whose last word does not name an existing ref,
+/// as a fetch does to find which advertised refs are new.
+fn exclude_existing(
+    refs: &RefStore,
+    pattern: Option<&str>,
+    input: impl BufRead,
+    stdout: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut existing: HashSet<_> = refs.list()?.into_iter().map(|r| r.name).collect();
+    for line in input.lines() {
+        let line = line?;
+        let line = line.strip_suffix("^{}").unwrap_or(&line);
+        let name = line.rsplit(char::is_whitespace).next().unwrap_or(line);
+        if pattern.is_some_and(|pattern| !name.starts_with(pattern)) {
+            continue;
+        }
+        // as in git, a name must have at least two components here
+        if !refs::is_valid_name(name) || !name.contains('/') {
+            eprintln!("warning: ref '{name}' ignored");
+            continue;
+        }
+        if !existing.contains(name) {
+            writeln!(stdout, "{line}")?;
+            // a name repeated on stdin is only printed once
+            existing.insert(name.to_owned());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, fs, path::Path};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::ObjectType,
+        refs::PackedRef,
+        repo::{InitOptions, RealRepoCreator, RefTransaction, RefUpdate, RepoCreator as _},
+    };
+
+    fn args(patterns: &[&str]) -> Args {
+        Args {
+            heads: false,
+            tags: false,
+            head: false,
+            verify: false,
+            dereference: false,
+            hash: None,
+            quiet: false,
+            exclude_existing: None,
+            patterns: patterns.iter().map(|&pattern| pattern.to_owned()).collect(),
+        }
+    }
+
+    /// A repository with `main` at a commit, the tag `loose` kept loose and
+    /// the tag `packed` in `packed-refs`, both annotated; returns the commit
+    /// and the two tags.
+    fn repo(root: &Path) -> (RealRepo, [ObjectId; 3]) {
+        let repo = RealRepoCreator::create(root, &InitOptions::default()).unwrap();
+        let objects = repo.objects();
+        let tree = objects.write(ObjectType::Tree, b"").unwrap();
+        let commit =
+            format!("tree {tree}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        let tag = |name: &str| {
+            let tag =
+                format!("object {commit}\ntype commit\ntag {name}\ntagger A <a@b> 0 +0000\n\nt\n");
+            objects.write(ObjectType::Tag, tag.as_bytes()).unwrap()
+        };
+        let (loose, packed) = (tag("loose"), tag("packed"));
+
+        let mut transaction = RefTransaction::new(repo.refs());
+        transaction.add(RefUpdate::create("refs/heads/main", commit));
+        transaction.add(RefUpdate::create("refs/tags/loose", loose));
+        transaction.commit().unwrap();
+        let packed_ref = PackedRef {
+            id: packed,
+            peeled: Some(commit),
+        };
+        let refs = BTreeMap::from([("refs/tags/packed".to_owned(), packed_ref)]);
+        fs::write(repo.refs().packed_path(), refs::format_packed(&refs)).unwrap();
+        (repo, [commit, loose, packed])
+    }
+
+    fn show(repo: &RealRepo, args: &Args, name: &str) -> String {
+        let reference = repo
+            .refs()
+            .list()
+            .unwrap()
+            .into_iter()
+            .find(|reference| reference.name == name)
+            .unwrap();
+        let mut out = Vec::new();
+        args.show(&mut out, &repo.objects(), &reference).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn patterns() {
+        let main = "refs/heads/main";
+        assert!(args(&[]).matches(main));
+        for pattern in ["main", "heads/main", "refs/heads/main"] {
+            assert!(args(&[pattern]).matches(main), "{pattern}");
+        }
+        // only whole components count
+        for pattern in ["in", "ain", "eads/main", "heads"] {
+            assert!(!args(&[pattern]).matches(main), "{pattern}");
+        }
+        assert!(args(&["in", "main"]).matches("refs/remotes/origin/main"));
+
+        let mut heads = args(&["main"]);
+        heads.heads = true;
+        assert!(heads.matches(main));
+        assert!(!heads.matches("refs/tags/main"));
+        heads.tags = true;
+        assert!(heads.matches("refs/tags/main"));
+        assert!(!heads.matches("refs/remotes/origin/main"));
+    }
+
+    #[test]
+    fn verify() {
+        let tempdir = TempDir::new().unwrap();
+        let (repo, [commit, ..]) = repo(tempdir.path());
+        let (refs, objects) = (repo.refs(), repo.objects());
+        let verify = |args: &Args| {
+            let mut out = Vec::new();
+            let found = args.verify(&refs, &objects, &mut out);
+            found.map(|found| (found, String::from_utf8(out).unwrap()))
+        };
+        let error = |args: &Args| verify(args).unwrap_err().to_string();
+
+        assert_eq!(
+            verify(&args(&["refs/heads/main"])).unwrap(),
+            (true, format!("{commit} refs/heads/main\n"))
+        );
+        assert_eq!(error(&args(&[])), "--verify requires a reference");
+        // short names are not looked up
+        assert_eq!(error(&args(&["main"])), "'main' - not a valid ref");
+        assert_eq!(
+            error(&args(&["refs/heads/main", "refs/heads/missing"])),
+            "'refs/heads/missing' - not a valid ref"
+        );
+        let mut quiet = args(&["refs/heads/missing"]);
+        quiet.quiet = true;
+        assert_eq!(verify(&quiet).unwrap(), (false, String::new()));
+    }
+
+    #[test]
+    fn dereference_and_hash() {
+        let tempdir = TempDir::new().unwrap();
+        let (repo, [commit, loose, packed]) = repo(tempdir.path());
+
+        let mut args = args(&[]);
+        args.dereference = true;
+        assert_eq!(
+            show(&repo, &args, "refs/tags/loose"),
+            format!("{loose} refs/tags/loose\n{commit} refs/tags/loose^{{}}\n")
+        );
+        assert_eq!(
+            show(&repo, &args, "refs/tags/packed"),
+            format!("{packed} refs/tags/packed\n{commit} refs/tags/packed^{{}}\n")
+        );
+        assert_eq!(
+            show(&repo, &args, "refs/heads/main"),
+            format!("{commit} refs/heads/main\n")
+        );
+
+        let short = |id: ObjectId| id.to_string()[..5].to_owned();
+        // as in git, the peeled line keeps its name
+        args.hash = Some(Some(5));
+        assert_eq!(
+            show(&repo, &args, "refs/tags/packed"),
+            format!(
+                "{}\n{} refs/tags/packed^{{}}\n",
+                short(packed),
+                short(commit)
+            )
+        );
+        args.dereference = false;
+        args.hash = Some(None);
+        assert_eq!(show(&repo, &args, "refs/heads/main"), format!("{commit}\n"));
+        // too short a length is ignored
+        args.hash = Some(Some(0));
+        assert_eq!(show(&repo, &args, "refs/heads/main"), format!("{commit}\n"));
+    }
 
-impl Execute for Args {}
+    #[test]
+    fn exclude_existing() {
+        let tempdir = TempDir::new().unwrap();
+        let (repo, [commit, ..]) = repo(tempdir.path());
+        let input = format!(
+            "{commit} refs/heads/main\n{commit} refs/heads/new\n{commit} refs/heads/new\n\
+             {commit} refs/tags/packed^{{}}\n{commit} refs/tags/v1^{{}}\nrefs/tags/v1\nHEAD\n"
+        );
+        let run = |pattern| {
+            let mut out = Vec::new();
+            super::exclude_existing(&repo.refs(), pattern, input.as_bytes(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            run(None),
+            format!("{commit} refs/heads/new\n{commit} refs/tags/v1\n")
+        );
+        assert_eq!(run(Some("refs/tags/")), format!("{commit} refs/tags/v1\n"));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    env,
+    error::Error,
+    io::{self, Write},
+};
+
+use application::clap;
+
+use crate::{
+    refs::{self, RefValue},
+    repo::{RealRepo, RefChange, RefUpdate},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum SymbolicRefError {
+    #[error("Ref {0} is not a symbolic ref")]
+    NotSymbolic(String),
+    #[error("Refusing to point HEAD outside of refs/")]
+    OutsideRefs,
+    #[error("Refusing to set '{0}' to invalid ref '{1}'")]
+    InvalidTarget(String, String),
+    #[error("Deleting '{0}' is not allowed")]
+    DeleteHead(String),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Delete the symbolic ref
+    #[arg(short, long, conflicts_with = "target")]
+    delete: bool,
+    /// Exit with status 1 instead of failing when <name> is not a symbolic ref
+    #[arg(short, long)]
+    quiet: bool,
+    /// Print the ref shortened, such as `main` for `refs/heads/main`
+    #[arg(long)]
+    short: bool,
+    /// Reason for the update, for the reflog
+    #[arg(short, value_name = "reason")]
+    message: Option<String>,
+    /// The symbolic ref, such as HEAD
+    name: String,
+    /// Point <name> at this ref instead of printing what it points to
+    #[arg(value_name = "ref")]
+    target: Option<String>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        if !self.symbolic_ref(&repo, &mut io::stdout().lock())? {
+            std::process::exit(1);
+        }
+        Ok(())
+    }
+
+    /// Reads, sets or deletes the symbolic ref, returning false when
+    /// `--quiet` and it is not symbolic.
+    fn symbolic_ref(&self, repo: &RealRepo, out: &mut impl Write) -> Result<bool, Box<dyn Error>> {
+        let refs = repo.refs();
+        let is_symbolic = matches!(refs.read(&self.name)?, Some(RefValue::Symbolic(_)));
+
+        if let Some(target) = &self.target {
+            if self.name == "HEAD" && !target.starts_with("refs/") {
+                return Err(SymbolicRefError::OutsideRefs.into());
+            }
+            if !refs::is_valid_name(target) {
+                let err = SymbolicRefError::InvalidTarget(self.name.clone(), target.clone());
+                return Err(err.into());
+            }
+            let change = RefChange::Set(RefValue::Symbolic(target.clone()));
+            let update = RefUpdate::new(self.name.clone(), change, None)
+                .no_deref()
+                .with_message(self.message.clone().unwrap_or_default());
+            let mut transaction = repo.transaction()?;
+            transaction.add(update);
+            transaction.commit()?;
+            return Ok(true);
+        }
+
+        if !is_symbolic {
+            if self.quiet {
+                return Ok(false);
+            }
+            return Err(SymbolicRefError::NotSymbolic(self.name.clone()).into());
+        }
+        if self.delete {
+            if self.name == "HEAD" {
+                return Err(SymbolicRefError::DeleteHead(self.name.clone()).into());
+            }
+            let mut transaction = repo.transaction()?;
+            let update = RefUpdate::new(self.name.clone(), RefChange::Delete, None).no_deref();
+            transaction.add(update);
+            transaction.commit()?;
+            return Ok(true);
+        }
+
+        let (target, _) = refs.follow(&self.name)?;
+        if self.short {
+            writeln!(out, "{}", refs.shorten(&target)?)?;
+        } else {
+            writeln!(out, "{target}")?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::ObjectType,
+        odb::ObjectStore as _,
+        repo::{InitOptions, RealRepoCreator, RefTransaction, RepoCreator as _},
+    };
+
+    fn args(name: &str, target: Option<&str>) -> Args {
+        Args {
+            delete: false,
+            quiet: false,
+            short: false,
+            message: None,
+            name: name.to_owned(),
+            target: target.map(str::to_owned),
+        }
+    }
+
+    fn symbolic_ref(repo: &RealRepo, args: &Args) -> Result<(bool, String), Box<dyn Error>> {
+        let mut out = Vec::new();
+        let symbolic = args.symbolic_ref(repo, &mut out)?;
+        Ok((symbolic, String::from_utf8(out)?))
+    }
+
+    fn repo(tempdir: &TempDir) -> RealRepo {
+        let repo = RealRepoCreator::create(tempdir.path(), &InitOptions::default()).unwrap();
+        let id = repo.objects().write(ObjectType::Blob, b"x").unwrap();
+        let mut transaction = RefTransaction::new(repo.refs());
+        transaction.add(RefUpdate::create("refs/heads/master", id));
+        transaction.commit().unwrap();
+        repo
+    }
+
+    #[test]
+    fn read() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = repo(&tempdir);
+
+        let mut head = args("HEAD", None);
+        assert_eq!(
+            symbolic_ref(&repo, &head).unwrap(),
+            (true, "refs/heads/master\n".into())
+        );
+        head.short = true;
+        assert_eq!(
+            symbolic_ref(&repo, &head).unwrap(),
+            (true, "master\n".into())
+        );
+
+        // a ref that holds an object id is not symbolic
+        let mut master = args("refs/heads/master", None);
+        let err = symbolic_ref(&repo, &master).unwrap_err();
+        assert!(matches!(
+            err.downcast::<SymbolicRefError>().as_deref(),
+            Ok(SymbolicRefError::NotSymbolic(name)) if name == "refs/heads/master"
+        ));
+        master.quiet = true;
+        assert_eq!(
+            symbolic_ref(&repo, &master).unwrap(),
+            (false, String::new())
+        );
+        let mut missing = args("refs/heads/missing", None);
+        missing.quiet = true;
+        assert_eq!(
+            symbolic_ref(&repo, &missing).unwrap(),
+            (false, String::new())
+        );
+    }
+
+    #[test]
+    fn set() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = repo(&tempdir);
+        let error = |name, target| {
+            let err = symbolic_ref(&repo, &args(name, Some(target))).unwrap_err();
+            err.downcast::<SymbolicRefError>().unwrap().to_string()
+        };
+
+        for target in ["heads/master", "master", "HEAD"] {
+            assert_eq!(
+                error("HEAD", target),
+                "Refusing to point HEAD outside of refs/"
+            );
+        }
+        assert_eq!(
+            error("HEAD", "refs/heads/a..b"),
+            "Refusing to set 'HEAD' to invalid ref 'refs/heads/a..b'"
+        );
+        assert_eq!(
+            error("refs/remotes/o/HEAD", "refs/heads/x.lock"),
+            "Refusing to set 'refs/remotes/o/HEAD' to invalid ref 'refs/heads/x.lock'"
+        );
+        assert!(matches!(
+            repo.refs().read("HEAD").unwrap(),
+            Some(RefValue::Symbolic(target)) if target == "refs/heads/master"
+        ));
+
+        // other symbolic refs may point anywhere valid, even at a missing ref
+        let name = "refs/remotes/o/HEAD";
+        assert_eq!(
+            symbolic_ref(&repo, &args(name, Some("refs/remotes/o/main"))).unwrap(),
+            (true, String::new())
+        );
+        assert!(matches!(
+            repo.refs().read(name).unwrap(),
+            Some(RefValue::Symbolic(target)) if target == "refs/remotes/o/main"
+        ));
+    }
+
+    #[test]
+    fn delete() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = repo(&tempdir);
+        let name = "refs/remotes/o/HEAD";
+        symbolic_ref(&repo, &args(name, Some("refs/heads/master"))).unwrap();
+
+        let mut head = args("HEAD", None);
+        head.delete = true;
+        let err = symbolic_ref(&repo, &head).unwrap_err();
+        assert!(matches!(
+            err.downcast::<SymbolicRefError>().as_deref(),
+            Ok(SymbolicRefError::DeleteHead(name)) if name == "HEAD"
+        ));
+        assert!(repo.refs().read("HEAD").unwrap().is_some());
+
+        // deleting only removes the symbolic ref, not the ref it points to
+        let mut remote = args(name, None);
+        remote.delete = true;
+        assert_eq!(symbolic_ref(&repo, &remote).unwrap(), (true, String::new()));
+        assert!(repo.refs().read(name).unwrap().is_none());
+        assert!(repo.refs().read("refs/heads/master").unwrap().is_some());
+
+        // a plain ref cannot be deleted through symbolic-ref
+        let mut master = args("refs/heads/master", None);
+        master.delete = true;
+        assert!(symbolic_ref(&repo, &master).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+use std::{cmp::Ordering, fmt};
+
+use crate::object::{Error, HashAlgorithm, Object, ObjectId, ObjectType};
+
+/// The octal mode of a tree entry.
+///
+/// The number of digits is remembered so that legacy zero-padded modes survive a round trip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileMode {
+    bits: u32,
+    width: usize,
+}
+
+impl FileMode {
+    pub(crate) const TREE: Self = Self::new(0o40000);
+    pub(crate) const BLOB: Self = Self::new(0o100644);
+    pub(crate) const EXECUTABLE: Self = Self::new(0o100755);
+    pub(crate) const SYMLINK: Self = Self::new(0o120000);
+    pub(crate) const GITLINK: Self = Self::new(0o160000);
+
+    pub(crate) const fn new(bits: u32) -> Self {
+        let mut width = 1;
+        let mut rest = bits >> 3;
+        while rest != 0 {
+            width += 1;
+            rest >>= 3;
+        }
+        Self { bits, width }
+    }
+
+    fn parse(raw: &[u8]) -> Option<Self> {
+        if raw.is_empty() || !raw.iter().all(|b| (b'0'..=b'7').contains(b)) {
+            return None;
+        }
+        let bits = u32::from_str_radix(std::str::from_utf8(raw).ok()?, 8).ok()?;
+        Some(Self {
+            bits,
+            width: raw.len(),
+        })
+    }
+
+    pub(crate) fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub(crate) fn is_tree(&self) -> bool {
+        self.bits == Self::TREE.bits
+    }
+
+    /// Whether this is one of the modes git itself writes, without padding.
+    pub(crate) fn is_canonical(&self) -> bool {
+        [
+            Self::TREE,
+            Self::BLOB,
+            Self::EXECUTABLE,
+            Self::SYMLINK,
+            Self::GITLINK,
+        ]
+        .contains(self)
+    }
+
+    /// The type of object an entry with this mode points to.
+    pub(crate) fn object_type(&self) -> ObjectType {
+        match self.bits {
+            0o40000 => ObjectType::Tree,
+            0o160000 => ObjectType::Commit,
+            _ => ObjectType::Blob,
+        }
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:0width$o}", self.bits, width = self.width)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TreeEntry {
+    pub(crate) mode: FileMode,
+    pub(crate) name: Vec<u8>,
+    pub(crate) id: ObjectId,
+}
+
+impl TreeEntry {
+    /// Git's tree order: names compare bytewise, with directories compared as if they end in `/`.
+    pub(crate) fn cmp_git(&self, other: &Self) -> Ordering {
+        let key = |entry: &Self| {
+            let suffix = entry.mode.is_tree().then_some(b'/');
+            entry.name.iter().copied().chain(suffix).collect::<Vec<_>>()
+        };
+        key(self).cmp(&key(other))
+    }
+}
+
+/// The list of entries in a tree object, in the order they are stored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Tree {
+    pub(crate) entries: Vec<TreeEntry>,
+}
+
+impl Object for Tree {
+    const TYPE: ObjectType = ObjectType::Tree;
+
+    fn parse(mut data: &[u8], algorithm: HashAlgorithm) -> Result<Self, Error> {
+        let mut entries = Vec::new();
+        while !data.is_empty() {
+            let space = data
+                .iter()
+                .position(|&b| b == b' ')
+                .ok_or_else(|| Error::MalformedTree("missing mode terminator".to_owned()))?;
+            let mode = FileMode::parse(&data[..space])
+                .ok_or_else(|| Error::MalformedTree("invalid mode".to_owned()))?;
+            data = &data[space + 1..];
+
+            let nul = data
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| Error::MalformedTree("missing name terminator".to_owned()))?;
+            let name = data[..nul].to_vec();
+            data = &data[nul + 1..];
+
+            let id = data
+                .get(..algorithm.len())
+                .and_then(ObjectId::from_bytes)
+                .ok_or_else(|| Error::MalformedTree("truncated object id".to_owned()))?;
+            data = &data[algorithm.len()..];
+
+            entries.push(TreeEntry { mode, name, id });
+        }
+
+        Ok(Self { entries })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        for entry in &self.entries {
+            out.extend_from_slice(entry.mode.to_string().as_bytes());
+            out.push(b' ');
+            out.extend_from_slice(&entry.name);
+            out.push(0);
+            out.extend_from_slice(entry.id.as_bytes());
+        }
+        out
+    }
+}
+
+impl Tree {
+    /// Puts the entries in the order git requires before writing.
+    pub(crate) fn sort(&mut self) {
+        self.entries.sort_by(TreeEntry::cmp_git);
+    }
+
+    pub(crate) fn is_sorted(&self) -> bool {
+        self.entries
+            .windows(2)
+            .all(|pair| pair[0].cmp_git(&pair[1]) == Ordering::Less)
+    }
+}
+
+impl fmt::Display for Tree {
+    /// The `cat-file -p` / `ls-tree` listing.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{:06o} {} {}\t{}",
+                entry.mode.bits(),
+                entry.mode.object_type(),
+                entry.id,
+                String::from_utf8_lossy(&entry.name)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: FileMode, name: &str) -> TreeEntry {
+        TreeEntry {
+            mode,
+            name: name.as_bytes().to_vec(),
+            id: ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Blob, name.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let tree = Tree {
+            entries: vec![
+                entry(FileMode::BLOB, "a.txt"),
+                entry(FileMode::TREE, "src"),
+                entry(FileMode::EXECUTABLE, "x"),
+            ],
+        };
+        let data = tree.serialize();
+        assert!(data.starts_with(b"100644 a.txt\0"));
+        assert_eq!(Tree::parse(&data, HashAlgorithm::Sha1).unwrap(), tree);
+    }
+
+    #[test]
+    fn sha256_ids() {
+        let hello = ObjectId::hash(HashAlgorithm::Sha256, ObjectType::Blob, b"hello\n");
+        let tree = Tree {
+            entries: vec![TreeEntry {
+                mode: FileMode::BLOB,
+                name: b"a".to_vec(),
+                id: hello,
+            }],
+        };
+        // `git write-tree` in a SHA-256 repository
+        assert_eq!(
+            tree.id(HashAlgorithm::Sha256).to_string(),
+            "6ebf092cf7d68fcf2cebb8713cd1ecd134ff0c527197b2f7c60d1611d4885ea5"
+        );
+        let data = tree.serialize();
+        assert_eq!(Tree::parse(&data, HashAlgorithm::Sha256).unwrap(), tree);
+        assert!(Tree::parse(&data, HashAlgorithm::Sha1).is_err());
+    }
+
+    #[test]
+    fn zero_padded_mode() {
+        let mut data = b"040000 dir\0".to_vec();
+        data.extend_from_slice(
+            ObjectId::hash(HashAlgorithm::Sha1, ObjectType::Tree, b"").as_bytes(),
+        );
+        let tree = Tree::parse(&data, HashAlgorithm::Sha1).unwrap();
+        assert!(tree.entries[0].mode.is_tree());
+        assert!(!tree.entries[0].mode.is_canonical());
+        assert_eq!(tree.serialize(), data);
+    }
+
+    #[test]
+    fn directories_sort_with_trailing_slash() {
+        let mut tree = Tree {
+            entries: vec![
+                entry(FileMode::TREE, "foo"),
+                entry(FileMode::BLOB, "foo.txt"),
+                entry(FileMode::BLOB, "foo-bar"),
+            ],
+        };
+        assert!(!tree.is_sorted());
+        tree.sort();
+        let names: Vec<_> = tree.entries.iter().map(|e| e.name.as_slice()).collect();
+        // '-' (0x2d) < '.' (0x2e) < '/' (0x2f)
+        assert_eq!(names, [&b"foo-bar"[..], b"foo.txt", b"foo"]);
+        assert!(tree.is_sorted());
+    }
+
+    #[test]
+    fn truncated() {
+        assert!(Tree::parse(b"100644 a\0abc", HashAlgorithm::Sha1).is_err());
+        assert!(Tree::parse(b"1x0644 a\0", HashAlgorithm::Sha1).is_err());
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    env,
+    error::Error,
+    io::{self, BufRead as _, Write as _},
+    mem,
+};
+
+use application::clap;
+
+use crate::{
+    object::{ObjectId, ObjectType},
+    odb::ObjectStore as _,
+    refs::RefValue,
+    repo::{RealRepo, RefChange, RefUpdate},
+    rev_parse::resolve,
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum UpdateRefError {
+    #[error("Usage: update-ref [-d] [-m <reason>] [--no-deref] <ref> [<new>] [<old>] | --stdin")]
+    Usage,
+    #[error("{0}: not a valid object name")]
+    InvalidValue(String),
+    #[error("Trying to write ref '{0}' with nonexistent object {1}")]
+    MissingObject(String, ObjectId),
+    #[error("Trying to write non-commit object {1} to branch '{0}'")]
+    NotACommit(String, ObjectId),
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+    #[error("{0}: wrong number of arguments")]
+    Arguments(String),
+    #[error("{0}: zero {1}")]
+    ZeroValue(String, &'static str),
+    #[error("Cannot restart ongoing transaction")]
+    Restart,
+    #[error("Prepared transactions can only be closed")]
+    Prepared,
+    #[error("Transaction is closed")]
+    Closed,
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// Delete the ref, after checking its old value if one is given
+    #[arg(short)]
+    delete: bool,
+    /// Reason for the update, for the reflog
+    #[arg(short, value_name = "reason")]
+    message: Option<String>,
+    /// Update a symbolic ref itself rather than the ref it points to
+    #[arg(long)]
+    no_deref: bool,
+    /// Read instructions from stdin, applying them together or not at all
+    #[arg(long, conflicts_with_all = ["delete", "args"])]
+    stdin: bool,
+    /// `<ref> <new> [<old>]`, or `<ref> [<old>]` with -d; an empty or
+    /// all-zero old value means the ref must not exist yet
+    #[arg(value_name = "args", num_args = 0..=3)]
+    args: Vec<String>,
+}
+
+/// How far `--stdin` has got with the current transaction; a command may only
+/// move it forward, except that `start` opens a new one once it is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    /// No `start` yet: updates are queued and committed at the end of input.
+    Open,
+    /// After `start`: updates are queued until `prepare`, `commit` or `abort`.
+    Started,
+    /// After `prepare`: the refs are locked, and only `commit` or `abort` may
+    /// follow.
+    Prepared,
+    /// After `commit` or `abort`: only `start` may follow.
+    Closed,
+}
+
+impl State {
+    /// The state after `command`, if it is allowed in this one.
+    fn next(self, command: &str) -> Result<Self, UpdateRefError> {
+        let next = match command {
+            "start" => State::Started,
+            "prepare" => State::Prepared,
+            "commit" | "abort" => State::Closed,
+            _ => State::Open,
+        };
+        match self {
+            State::Started if next == State::Started => Err(UpdateRefError::Restart),
+            State::Open | State::Started => Ok(self.max(next)),
+            State::Prepared if next != State::Closed => Err(UpdateRefError::Prepared),
+            State::Closed if next != State::Started => Err(UpdateRefError::Closed),
+            State::Prepared | State::Closed => Ok(next),
+        }
+    }
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let repo = RealRepo::discover(&env::current_dir()?)?;
+        if self.stdin {
+            return self.run_stdin(&repo);
+        }
+
+        let mut update = match (self.delete, self.args.as_slice()) {
+            (true, [name]) => RefUpdate::new(name, RefChange::Delete, None),
+            (true, [name, old]) => {
+                RefUpdate::new(name, RefChange::Delete, Some(old_id(&repo, old)?))
+            }
+            (false, [name, new]) => RefUpdate::new(name, change(&repo, name, new)?, None),
+            (false, [name, new, old]) => {
+                RefUpdate::new(name, change(&repo, name, new)?, Some(old_id(&repo, old)?))
+            }
+            _ => return Err(UpdateRefError::Usage.into()),
+        };
+        if self.no_deref {
+            update = update.no_deref();
+        }
+        let mut transaction = repo.transaction()?;
+        transaction.add(update.with_message(self.message.unwrap_or_default()));
+        Ok(transaction.commit()?)
+    }
+
+    /// Applies `update`, `create`, `delete` and `verify` instructions from
+    /// stdin, one per line, in transactions that `start`, `prepare`, `commit`
+    /// and `abort` control explicitly; without them, everything is committed
+    /// together at the end.
+    fn run_stdin(&self, repo: &RealRepo) -> Result<(), Box<dyn Error>> {
+        let mut stdout = io::stdout().lock();
+        let mut state = State::Open;
+        let mut transaction = repo.transaction()?;
+        let mut prepared = None;
+        // `option no-deref` applies to the next instruction only
+        let mut no_deref = false;
+
+        for line in io::stdin().lock().lines() {
+            let line = line?;
+            let (command, args) = match line.split_once(' ') {
+                Some((command, args)) => (command, args.split(' ').collect()),
+                None => (line.as_str(), Vec::new()),
+            };
+            state = state.next(command)?;
+
+            match command {
+                "start" => {
+                    transaction = repo.transaction()?;
+                    writeln!(stdout, "start: ok")?;
+                }
+                "prepare" => {
+                    let queued = mem::replace(&mut transaction, repo.transaction()?);
+                    prepared = Some(queued.prepare()?);
+                    writeln!(stdout, "prepare: ok")?;
+                }
+                "commit" => {
+                    match prepared.take() {
+                        Some(prepared) => prepared.commit()?,
+                        None => mem::replace(&mut transaction, repo.transaction()?).commit()?,
+                    }
+                    writeln!(stdout, "commit: ok")?;
+                }
+                "abort" => {
+                    prepared = None;
+                    transaction = repo.transaction()?;
+                    writeln!(stdout, "abort: ok")?;
+                }
+                "option" => match args.as_slice() {
+                    ["no-deref"] => no_deref = true,
+                    _ => return Err(UpdateRefError::UnknownCommand(line).into()),
+                },
+                _ => {
+                    let mut update = instruction(repo, command, &args)?;
+                    if self.no_deref || mem::take(&mut no_deref) {
+                        update = update.no_deref();
+                    }
+                    if let Some(message) = &self.message {
+                        update = update.with_message(message);
+                    }
+                    transaction.add(update);
+                }
+            }
+        }
+
+        // an explicit transaction that was never committed is aborted
+        if state == State::Open {
+            transaction.commit()?;
+        }
+        Ok(())
+    }
+}
+
+/// The update for one line of `--stdin`.
+fn instruction(repo: &RealRepo, command: &str, args: &[&str]) -> Result<RefUpdate, Box<dyn Error>> {
+    let zero = |name: &str, what| UpdateRefError::ZeroValue(format!("{command} {name}"), what);
+    let update = match (command, args) {
+        ("update", [name, new]) => RefUpdate::new(*name, change(repo, name, new)?, None),
+        ("update", [name, new, old]) => {
+            RefUpdate::new(*name, change(repo, name, new)?, Some(old_id(repo, old)?))
+        }
+        ("create", [name, new]) => match change(repo, name, new)? {
+            RefChange::Delete => return Err(zero(name, "<newvalue>").into()),
+            change => RefUpdate::new(*name, change, Some(ObjectId::null(repo.object_format()))),
+        },
+        ("delete", [name]) => RefUpdate::new(*name, RefChange::Delete, None),
+        ("delete", [name, old]) => match old_id(repo, old)? {
+            old if old.is_null() => return Err(zero(name, "<oldvalue>").into()),
+            old => RefUpdate::new(*name, RefChange::Delete, Some(old)),
+        },
+        // a missing old value means the ref must not exist
+        ("verify", [name]) => {
+            let null = ObjectId::null(repo.object_format());
+            RefUpdate::new(*name, RefChange::Verify, Some(null))
+        }
+        ("verify", [name, old]) => {
+            RefUpdate::new(*name, RefChange::Verify, Some(old_id(repo, old)?))
+        }
+        ("update" | "create" | "delete" | "verify", _) => {
+            return Err(UpdateRefError::Arguments(command.to_owned()).into())
+        }
+        _ => return Err(UpdateRefError::UnknownCommand(command.to_owned()).into()),
+    };
+    Ok(update)
+}
+
+/// What setting `name` to the revision `new` does: the null id deletes it.
+fn change(repo: &RealRepo, name: &str, new: &str) -> Result<RefChange, UpdateRefError> {
+    let id = resolve(repo, new).map_err(|_| UpdateRefError::InvalidValue(new.to_owned()))?;
+    if id.is_null() {
+        return Ok(RefChange::Delete);
+    }
+    let object = repo
+        .objects()
+        .read(&id)
+        .map_err(|_| UpdateRefError::MissingObject(name.to_owned(), id))?;
+    if name.starts_with("refs/heads/") && object.kind != ObjectType::Commit {
+        return Err(UpdateRefError::NotACommit(name.to_owned(), id));
+    }
+    Ok(RefChange::Set(RefValue::Direct(id)))
+}
+
+/// The id a ref must have before it is updated; empty means it must not exist.
+fn old_id(repo: &RealRepo, old: &str) -> Result<ObjectId, UpdateRefError> {
+    if old.is_empty() {
+        return Ok(ObjectId::null(repo.object_format()));
+    }
+    resolve(repo, old).map_err(|_| UpdateRefError::InvalidValue(old.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::repo::{InitOptions, RealRepoCreator, RepoCreator as _};
+
+    #[test]
+    fn transitions() {
+        let run = |commands: &[&str]| {
+            commands
+                .iter()
+                .try_fold(State::Open, |state, command| state.next(command))
+        };
+        assert!(matches!(run(&["update", "create"]), Ok(State::Open)));
+        assert!(matches!(run(&["start", "update"]), Ok(State::Started)));
+        assert!(matches!(
+            run(&["start", "prepare", "commit"]),
+            Ok(State::Closed)
+        ));
+        assert!(matches!(
+            run(&["start", "abort", "start"]),
+            Ok(State::Started)
+        ));
+        assert!(matches!(run(&["update", "commit"]), Ok(State::Closed)));
+        assert!(matches!(run(&["prepare", "abort"]), Ok(State::Closed)));
+        assert!(matches!(run(&["update", "start"]), Ok(State::Started)));
+
+        assert!(matches!(
+            run(&["start", "start"]),
+            Err(UpdateRefError::Restart)
+        ));
+        for command in ["update", "start", "prepare"] {
+            let result = run(&["start", "prepare", command]);
+            assert!(matches!(result, Err(UpdateRefError::Prepared)));
+        }
+        for command in ["update", "prepare", "commit", "abort"] {
+            let result = run(&["start", "commit", command]);
+            assert!(matches!(result, Err(UpdateRefError::Closed)));
+        }
+    }
+
+    #[test]
+    fn zero_values() {
+        let tempdir = TempDir::new().unwrap();
+        let repo = RealRepoCreator::create(tempdir.path(), &InitOptions::default()).unwrap();
+        let null = ObjectId::null(repo.object_format()).to_string();
+        let error = |command, args: &[&str]| {
+            let err = instruction(&repo, command, args).unwrap_err();
+            err.downcast::<UpdateRefError>().unwrap().to_string()
+        };
+
+        assert_eq!(
+            error("create", &["refs/heads/x", &null]),
+            "create refs/heads/x: zero <newvalue>"
+        );
+        for old in [null.as_str(), ""] {
+            assert_eq!(
+                error("delete", &["refs/heads/x", old]),
+                "delete refs/heads/x: zero <oldvalue>"
+            );
+        }
+        assert_eq!(
+            error("create", &["refs/heads/x"]),
+            "create: wrong number of arguments"
+        );
+
+        // elsewhere, the null id means the ref must not exist, or is deleted
+        let update = instruction(&repo, "verify", &["refs/heads/x"]).unwrap();
+        assert_eq!(update.old, Some(null.parse().unwrap()));
+        let update = instruction(&repo, "update", &["refs/heads/x", &null, ""]).unwrap();
+        assert_eq!(update.change, RefChange::Delete);
+        assert_eq!(update.old, Some(null.parse().unwrap()));
+    }
+}
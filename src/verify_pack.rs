@@ -0,0 +1,355 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    error::Error,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use application::clap;
+use flate2::Crc;
+
+use crate::{
+    object::{HashAlgorithm, ObjectId},
+    odb::ObjectStore as _,
+    pack::{self, EntryKind, Pack},
+    repo::{self, RealRepo},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum VerifyPackError {
+    #[error("{0} of the packs are corrupt")]
+    Failed(usize),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    /// List every object, then the delta chain histogram
+    #[arg(short, long)]
+    verbose: bool,
+    /// Only print the delta chain histogram
+    #[arg(short, long)]
+    stat_only: bool,
+    /// The `.idx` (or `.pack`) files to check
+    #[arg(value_name = "pack", required = true)]
+    packs: Vec<PathBuf>,
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        // outside a repository, packs are assumed to use the default algorithm
+        let algorithm = match RealRepo::discover(&env::current_dir()?) {
+            Ok(repo) => repo.objects().algorithm(),
+            Err(repo::Error::NotGitRepository(_) | repo::Error::FilesystemBoundary(_)) => {
+                HashAlgorithm::default()
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut failed = 0;
+        for path in &self.packs {
+            let idx = path.with_extension("idx");
+            let pack = path.with_extension("pack");
+            match self.verify(&idx, algorithm, &mut io::stdout().lock()) {
+                Ok(()) if self.verbose => println!("{}: ok", pack.display()),
+                Ok(()) => {}
+                Err(err) => {
+                    eprintln!("error: {err}");
+                    println!("{}: bad", pack.display());
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(VerifyPackError::Failed(failed).into());
+        }
+        Ok(())
+    }
+
+    /// Checks the pack's checksum, and each entry's CRC and object id,
+    /// writing the listing and histogram the flags ask for to `out`.
+    fn verify(
+        &self,
+        idx: &Path,
+        algorithm: HashAlgorithm,
+        out: &mut impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        let pack = Pack::open(idx, algorithm)?;
+        pack.verify_checksum()?;
+        let data = fs::read(pack.path())?;
+        let index = pack.index();
+        let corrupt = |msg: String| pack::Error::Corrupt(pack.path().to_owned(), msg);
+
+        // in pack order, each entry running until the next one or the trailer
+        let mut entries: Vec<_> = (0..index.len()).map(|n| (index.offset(n), n)).collect();
+        entries.sort();
+        let ids: HashMap<_, _> = entries
+            .iter()
+            .map(|&(offset, n)| (offset, index.id(n)))
+            .collect();
+
+        // offsets come from the index, which may point anywhere; the last one
+        // is the largest
+        let end = (data.len() - algorithm.len()) as u64;
+        let beyond_end = |offset, n| {
+            let id = index.id(n);
+            corrupt(format!(
+                "offset {offset} of object {id} is beyond the end of the pack"
+            ))
+        };
+        if let Some(&(offset, n)) = entries.last().filter(|&&(offset, _)| offset >= end) {
+            return Err(beyond_end(offset, n).into());
+        }
+
+        let mut depths = HashMap::new();
+        let mut histogram = BTreeMap::new();
+        for (i, &(offset, n)) in entries.iter().enumerate() {
+            let next = entries.get(i + 1).map_or(end, |&(next, _)| next);
+            let id = index.id(n);
+            let Some(raw) = data.get(offset as usize..next as usize) else {
+                return Err(beyond_end(offset, n).into());
+            };
+            let mut crc = Crc::new();
+            crc.update(raw);
+            if crc.sum() != index.crc32(n) {
+                return Err(corrupt(format!("CRC mismatch for object {id}")).into());
+            }
+            // a pack on disk must hold every base it needs
+            let object = pack.read_at(offset, &|_| None)?;
+            if ObjectId::hash(algorithm, object.kind, &object.data) != id {
+                return Err(corrupt(format!("hash mismatch for object {id}")).into());
+            }
+
+            let entry = pack.entry(offset)?;
+            let base = match entry.kind {
+                EntryKind::Base(_) => None,
+                EntryKind::OfsDelta(base) => match ids.get(&base) {
+                    Some(&base) => Some(base),
+                    None => {
+                        let msg = format!(
+                            "delta base of object {id} at offset {base} is not in the index"
+                        );
+                        return Err(corrupt(msg).into());
+                    }
+                },
+                EntryKind::RefDelta(base) => Some(base),
+            };
+            let depth = depth(&pack, offset, &mut depths)?;
+            *histogram.entry(depth).or_insert(0) += 1;
+
+            if self.verbose {
+                let kind = object.kind.as_str();
+                let size = next - offset;
+                match base {
+                    Some(base) => writeln!(
+                        out,
+                        "{id} {kind:<6} {} {size} {offset} {depth} {base}",
+                        entry.size
+                    )?,
+                    None => writeln!(out, "{id} {kind:<6} {} {size} {offset}", entry.size)?,
+                }
+            }
+        }
+
+        if self.verbose || self.stat_only {
+            for (depth, count) in histogram {
+                let objects = if count == 1 { "object" } else { "objects" };
+                match depth {
+                    0 => writeln!(out, "non delta: {count} {objects}")?,
+                    depth => writeln!(out, "chain length = {depth}: {count} {objects}")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How many deltas must be applied to rebuild the entry at `offset`.
+fn depth(pack: &Pack, offset: u64, depths: &mut HashMap<u64, usize>) -> Result<usize, pack::Error> {
+    // the entries whose depth is still unknown, from `offset` down
+    let mut chain = Vec::new();
+    let mut current = offset;
+    let mut depth = loop {
+        if let Some(&depth) = depths.get(&current) {
+            break depth;
+        }
+        current = match pack.entry(current)?.kind {
+            EntryKind::Base(_) => {
+                depths.insert(current, 0);
+                break 0;
+            }
+            EntryKind::OfsDelta(base) => {
+                chain.push(current);
+                base
+            }
+            EntryKind::RefDelta(base) => {
+                chain.push(current);
+                let n = pack
+                    .index()
+                    .find(&base)
+                    .ok_or(pack::Error::MissingBase(base))?;
+                pack.index().offset(n)
+            }
+        };
+    };
+    while let Some(offset) = chain.pop() {
+        depth += 1;
+        depths.insert(offset, depth);
+    }
+    Ok(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        object::ObjectType,
+        odb::LooseObjects,
+        pack::{PackIndexEntry, PackInput, PackOptions},
+    };
+
+    const ALGORITHM: HashAlgorithm = HashAlgorithm::Sha1;
+
+    /// A pack of three versions of one file, each adding a line to the last,
+    /// with the versions' ids in order.
+    fn build(dir: &Path) -> (Vec<u8>, Vec<ObjectId>) {
+        let store = LooseObjects::new(dir.join("objects"), ALGORITHM);
+        let mut contents: String = (0..100).map(|n| format!("line {n}\n")).collect();
+        let mut ids = Vec::new();
+        for n in 0..3 {
+            contents.push_str(&format!("added {n}\n"));
+            ids.push(store.write(ObjectType::Blob, contents.as_bytes()).unwrap());
+        }
+        let inputs: Vec<_> = ids
+            .iter()
+            .map(|&id| PackInput {
+                id,
+                name_hash: pack::name_hash(b"file"),
+            })
+            .collect();
+        let new = pack::build(&store, &inputs, &PackOptions::default()).unwrap();
+        (new.data, ids)
+    }
+
+    /// Stores the pack `data` with an index listing only `entries`, each
+    /// with the CRC of what lies between it and the next.
+    fn save(dir: &Path, data: &[u8], entries: &[(ObjectId, u64)]) -> PathBuf {
+        let mut data = data[..data.len() - ALGORITHM.len()].to_vec();
+        data[8..12].copy_from_slice(&(entries.len() as u32).to_be_bytes());
+        let checksum = ALGORITHM.digest(&[&data]);
+        data.extend_from_slice(checksum.as_bytes());
+
+        let end = data.len() - ALGORITHM.len();
+        let mut offsets: Vec<_> = entries.iter().map(|&(_, offset)| offset).collect();
+        offsets.sort();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|&(id, offset)| {
+                let next = offsets.iter().find(|&&next| next > offset);
+                let next = next.map_or(end, |&next| next as usize);
+                let mut crc = Crc::new();
+                crc.update(data.get(offset as usize..next).unwrap_or_default());
+                PackIndexEntry {
+                    id,
+                    offset,
+                    crc32: crc.sum(),
+                }
+            })
+            .collect();
+        fs::write(dir.join("pack-test.pack"), &data).unwrap();
+        let idx = dir.join("pack-test.idx");
+        fs::write(&idx, pack::serialize_index(&entries, &checksum)).unwrap();
+        idx
+    }
+
+    fn verify(idx: &Path, verbose: bool, stat_only: bool) -> Result<String, Box<dyn Error>> {
+        let args = Args {
+            verbose,
+            stat_only,
+            packs: Vec::new(),
+        };
+        let mut out = Vec::new();
+        args.verify(idx, ALGORITHM, &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn corrupt(result: Result<String, Box<dyn Error>>) -> String {
+        match *result.unwrap_err().downcast::<pack::Error>().unwrap() {
+            pack::Error::Corrupt(_, msg) => msg,
+            err => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn listing() {
+        let tempdir = TempDir::new().unwrap();
+        let (data, ids) = build(tempdir.path());
+        // the newest, largest version is stored whole, the others as a chain
+        let offsets = [242, 224, 12];
+        let entries: Vec<_> = ids.iter().copied().zip(offsets).collect();
+        let idx = save(tempdir.path(), &data, &entries);
+
+        let out = verify(&idx, true, false).unwrap();
+        // id, type, size, size in the pack (left out), offset, depth, base
+        let lines: Vec<Vec<_>> = out
+            .lines()
+            .map(|line| {
+                let fields = line.split_whitespace().enumerate();
+                fields
+                    .filter(|&(i, _)| i != 3)
+                    .map(|(_, field)| field)
+                    .collect()
+            })
+            .collect();
+        let (whole, first, second) = (ids[2].to_string(), ids[1].to_string(), ids[0].to_string());
+        assert_eq!(lines[0], [&whole, "blob", "814", "12"]);
+        assert_eq!(lines[1], [&first, "blob", "7", "224", "1", &whole]);
+        assert_eq!(lines[2], [&second, "blob", "7", "242", "2", &first]);
+        let histogram =
+            "non delta: 1 object\nchain length = 1: 1 object\nchain length = 2: 1 object\n";
+        assert!(out.ends_with(&format!("\n{histogram}")));
+        assert_eq!(lines.len(), 6);
+
+        assert_eq!(verify(&idx, false, true).unwrap(), histogram);
+        assert_eq!(verify(&idx, false, false).unwrap(), "");
+    }
+
+    #[test]
+    fn corrupt_index() {
+        let tempdir = TempDir::new().unwrap();
+        let (data, ids) = build(tempdir.path());
+
+        // an offset only the 64-bit table can hold, far beyond the pack
+        let entries = [(ids[2], 12), (ids[1], 224), (ids[0], u64::MAX)];
+        let idx = save(tempdir.path(), &data, &entries);
+        assert_eq!(
+            corrupt(verify(&idx, false, false)),
+            format!(
+                "offset {} of object {} is beyond the end of the pack",
+                u64::MAX,
+                ids[0]
+            )
+        );
+
+        // the whole object is still in the pack, but not in the index
+        let entries = [(ids[1], 224), (ids[0], 242)];
+        let idx = save(tempdir.path(), &data, &entries);
+        assert_eq!(
+            corrupt(verify(&idx, false, false)),
+            format!(
+                "delta base of object {} at offset 12 is not in the index",
+                ids[1]
+            )
+        );
+    }
+}
@@ -0,0 +1,176 @@
+/// Matches `text` against a glob the way git's `wildmatch` does with `WM_PATHNAME`.
+///
+/// `*`, `?` and `[...]` never match `/`; `**` matches across directories when
+/// it makes up a whole path component (`**/`, `/**/` or a trailing `/**`).
+pub(crate) fn wildmatch(pattern: &str, text: &str, case_insensitive: bool) -> bool {
+    Matcher {
+        pattern: pattern.as_bytes(),
+        text: text.as_bytes(),
+        case_insensitive,
+    }
+    .matches(0, 0)
+}
+
+struct Matcher<'a> {
+    pattern: &'a [u8],
+    text: &'a [u8],
+    case_insensitive: bool,
+}
+
+impl Matcher<'_> {
+    fn matches(&self, mut p: usize, mut t: usize) -> bool {
+        let (pattern, text) = (self.pattern, self.text);
+        while p < pattern.len() {
+            match pattern[p] {
+                b'*' if self.is_double_star(p) => {
+                    // a trailing `**` matches everything that is left
+                    if p + 2 == pattern.len() {
+                        return true;
+                    }
+                    // `**/` matches zero or more whole directories
+                    return (t..=text.len())
+                        .filter(|&k| k == t || text[k - 1] == b'/')
+                        .any(|k| self.matches(p + 3, k));
+                }
+                b'*' => {
+                    let rest = p + pattern[p..].iter().take_while(|&&c| c == b'*').count();
+                    for k in t..=text.len() {
+                        if self.matches(rest, k) {
+                            return true;
+                        }
+                        if k < text.len() && text[k] == b'/' {
+                            break;
+                        }
+                    }
+                    return false;
+                }
+                b'?' => {
+                    if t == text.len() || text[t] == b'/' {
+                        return false;
+                    }
+                }
+                b'[' => match self.class(p, text.get(t).copied()) {
+                    Some((true, end)) => p = end,
+                    Some((false, _)) => return false,
+                    // an unterminated class is a literal `[`
+                    None => {
+                        if !self.literal(b'[', text.get(t).copied()) {
+                            return false;
+                        }
+                    }
+                },
+                b'\\' if p + 1 < pattern.len() => {
+                    p += 1;
+                    if !self.literal(pattern[p], text.get(t).copied()) {
+                        return false;
+                    }
+                }
+                c => {
+                    if !self.literal(c, text.get(t).copied()) {
+                        return false;
+                    }
+                }
+            }
+            p += 1;
+            t += 1;
+        }
+        t == text.len()
+    }
+
+    fn is_double_star(&self, p: usize) -> bool {
+        let pattern = self.pattern;
+        pattern.get(p + 1) == Some(&b'*')
+            && (p == 0 || pattern[p - 1] == b'/')
+            && (p + 2 == pattern.len() || pattern[p + 2] == b'/')
+    }
+
+    fn literal(&self, expected: u8, actual: Option<u8>) -> bool {
+        match actual {
+            Some(actual) if self.case_insensitive => expected.eq_ignore_ascii_case(&actual),
+            Some(actual) => expected == actual,
+            None => false,
+        }
+    }
+
+    /// Matches the bracket expression at `p`, returning whether it matched and
+    /// where it ends, or `None` if it is not terminated.
+    fn class(&self, p: usize, actual: Option<u8>) -> Option<(bool, usize)> {
+        let pattern = self.pattern;
+        let mut i = p + 1;
+        let negated = matches!(pattern.get(i), Some(b'!' | b'^'));
+        if negated {
+            i += 1;
+        }
+        let mut matched = false;
+        let mut first = true;
+        loop {
+            let c = *pattern.get(i)?;
+            if c == b']' && !first {
+                break;
+            }
+            first = false;
+            let (low, next) = match c {
+                b'\\' => (*pattern.get(i + 1)?, i + 2),
+                c => (c, i + 1),
+            };
+            let (high, next) = match (pattern.get(next), pattern.get(next + 1)) {
+                (Some(b'-'), Some(&high)) if high != b']' => (high, next + 2),
+                _ => (low, next),
+            };
+            if let Some(actual) = actual {
+                let in_range = |c: u8| low <= c && c <= high;
+                matched |= in_range(actual)
+                    || (self.case_insensitive
+                        && (in_range(actual.to_ascii_lowercase())
+                            || in_range(actual.to_ascii_uppercase())));
+            }
+            i = next;
+        }
+        let matched = match actual {
+            Some(b'/') | None => false,
+            Some(_) => matched != negated,
+        };
+        Some((matched, i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        for (pattern, text, expected) in [
+            ("foo", "foo", true),
+            ("f?o", "foo", true),
+            ("f*", "foo/bar", false),
+            ("f*/bar", "foo/bar", true),
+            ("**/bar", "bar", true),
+            ("**/bar", "a/b/bar", true),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**", "a/x/y", true),
+            ("a**b", "a/b", false),
+            ("[a-c]x", "bx", true),
+            ("[!a-c]x", "bx", false),
+            ("[]]", "]", true),
+            ("[a", "[a", true),
+            ("\\*", "*", true),
+            ("\\*", "x", false),
+            ("/home/*/work/**", "/home/me/work/repo/.git", true),
+            (
+                "https://*.example.com/**",
+                "https://git.example.com/org/repo",
+                true,
+            ),
+        ] {
+            assert_eq!(
+                wildmatch(pattern, text, false),
+                expected,
+                "{pattern} {text}"
+            );
+        }
+        assert!(wildmatch("/Work/**", "/work/a", true));
+        assert!(!wildmatch("/Work/**", "/work/a", false));
+    }
+}
@@ -0,0 +1,699 @@
+use std::{
+    collections::HashSet,
+    env,
+    error::Error,
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+use application::clap;
+
+use crate::{
+    index::{Index, IndexEntry},
+    object::{GitObject, ObjectId, ObjectType},
+    odb::ObjectStore,
+    refs::{RefStore, RefValue},
+    repo::{RealRepo, RefChange, RefUpdate, Worktree},
+    Execute,
+};
+
+#[derive(Debug, thiserror::Error)]
+enum WorktreeError {
+    #[error("'{0}' already exists")]
+    AlreadyExists(PathBuf),
+    #[error("A branch named '{0}' already exists")]
+    BranchExists(String),
+    #[error("'{0}' is already checked out at '{1}'")]
+    BranchCheckedOut(String, PathBuf),
+    #[error("Invalid reference: {0}")]
+    InvalidReference(String),
+    #[error("'{0}' is not a working tree")]
+    NotAWorktree(PathBuf),
+    #[error("'{0}' is a main working tree")]
+    MainWorktree(PathBuf),
+    #[error("'{0}' is already locked")]
+    AlreadyLocked(PathBuf),
+    #[error("'{0}' is not locked")]
+    NotLocked(PathBuf),
+    #[error("Cannot remove a locked working tree; use 'remove -f -f' to override or unlock first")]
+    Locked,
+    #[error("'{0}' contains modified or untracked files, use --force to delete it")]
+    Dirty(PathBuf),
+    #[error("Invalid path in tree: {0}")]
+    InvalidPath(String),
+}
+
+#[derive(Debug, clap::Args)]
+pub(crate) struct Args {
+    #[command(subcommand)]
+    command: Subcommand,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Subcommand {
+    /// Create a linked worktree at <path> and check out <commit-ish> into it
+    Add {
+        /// Create a new branch for the worktree
+        #[arg(short = 'b', value_name = "new-branch")]
+        branch: Option<String>,
+        /// Detach HEAD in the new worktree
+        #[arg(long, conflicts_with = "branch")]
+        detach: bool,
+        /// Keep the worktree locked after creation
+        #[arg(long)]
+        lock: bool,
+        /// The reason to record with --lock
+        #[arg(long, requires = "lock")]
+        reason: Option<String>,
+        path: PathBuf,
+        commit_ish: Option<String>,
+    },
+    /// List the main worktree and all linked worktrees
+    List {
+        /// Output in an easy-to-parse format
+        #[arg(long)]
+        porcelain: bool,
+    },
+    /// Protect a worktree from being pruned or removed
+    Lock {
+        #[arg(long)]
+        reason: Option<String>,
+        worktree: PathBuf,
+    },
+    /// Allow a locked worktree to be pruned or removed again
+    Unlock { worktree: PathBuf },
+    /// Remove administrative files of worktrees that no longer exist
+    Prune {
+        /// Only report what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+        /// Report all removals
+        #[arg(short, long)]
+        verbose: bool,
+    },
+    /// Delete a linked worktree and its administrative files
+    Remove {
+        /// Remove even with local changes; give twice to remove a locked worktree
+        #[arg(short, long, action = clap::ArgAction::Count)]
+        force: u8,
+        worktree: PathBuf,
+    },
+}
+
+impl Execute for Args {
+    fn execute(self) -> Result<(), crate::GitError> {
+        Ok(self.run()?)
+    }
+}
+
+impl Args {
+    fn run(self) -> Result<(), Box<dyn Error>> {
+        let cwd = env::current_dir()?;
+        let repo = RealRepo::discover(&cwd)?;
+        match self.command {
+            Subcommand::Add {
+                branch,
+                detach,
+                lock,
+                reason,
+                path,
+                commit_ish,
+            } => {
+                let lock = lock.then(|| reason.unwrap_or_default());
+                add(&repo, &cwd.join(path), branch, detach, commit_ish, lock)
+            }
+            Subcommand::List { porcelain } => list(&repo, porcelain),
+            Subcommand::Lock { reason, worktree } => {
+                let worktree = find_linked(&repo, &cwd, &worktree)?;
+                if worktree.locked.is_some() {
+                    return Err(WorktreeError::AlreadyLocked(display_path(&worktree)).into());
+                }
+                fs::write(worktree.gitdir.join("locked"), reason.unwrap_or_default())?;
+                Ok(())
+            }
+            Subcommand::Unlock { worktree } => {
+                let worktree = find_linked(&repo, &cwd, &worktree)?;
+                if worktree.locked.is_none() {
+                    return Err(WorktreeError::NotLocked(display_path(&worktree)).into());
+                }
+                fs::remove_file(worktree.gitdir.join("locked"))?;
+                Ok(())
+            }
+            Subcommand::Prune { dry_run, verbose } => prune(&repo, dry_run, verbose),
+            Subcommand::Remove { force, worktree } => {
+                let worktree = find_linked(&repo, &cwd, &worktree)?;
+                remove(&repo, &worktree, force)
+            }
+        }
+    }
+}
+
+/// Resolves `HEAD`, a branch, a tag or a hex name, and peels it to a commit.
+fn resolve_commit(repo: &RealRepo, name: &str) -> Result<ObjectId, Box<dyn Error>> {
+    let invalid = || WorktreeError::InvalidReference(name.to_owned());
+    let mut id = match repo.refs().dwim(name)? {
+        Some((_, id)) => id,
+        None => repo.objects().resolve(name).map_err(|_| invalid())?,
+    };
+
+    loop {
+        match repo.objects().read_object(&id)? {
+            GitObject::Commit(_) => return Ok(id),
+            GitObject::Tag(tag) => id = tag.object()?,
+            _ => return Err(invalid().into()),
+        }
+    }
+}
+
+fn add(
+    repo: &RealRepo,
+    path: &Path,
+    branch: Option<String>,
+    detach: bool,
+    commit_ish: Option<String>,
+    lock: Option<String>,
+) -> Result<(), Box<dyn Error>> {
+    if path.exists() && (!path.is_dir() || fs::read_dir(path)?.next().is_some()) {
+        return Err(WorktreeError::AlreadyExists(path.to_owned()).into());
+    }
+    let basename = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| WorktreeError::AlreadyExists(path.to_owned()))?;
+    let branch_exists = |name: &str| repo.refs().resolve(&format!("refs/heads/{name}"));
+
+    // work out what the new HEAD is, and whether a branch must be created for it
+    let message = format!(
+        "branch: Created from {}",
+        commit_ish.as_deref().unwrap_or("HEAD")
+    );
+    let (head, commit, new_branch) = match (branch, commit_ish) {
+        (Some(branch), commit_ish) => {
+            if branch_exists(&branch)?.is_some() {
+                return Err(WorktreeError::BranchExists(branch).into());
+            }
+            let commit = resolve_commit(repo, commit_ish.as_deref().unwrap_or("HEAD"))?;
+            let head = RefValue::Symbolic(format!("refs/heads/{branch}"));
+            (head, commit, Some(branch))
+        }
+        (None, Some(name)) if !detach && branch_exists(&name)?.is_some() => {
+            let commit = resolve_commit(repo, &name)?;
+            (
+                RefValue::Symbolic(format!("refs/heads/{name}")),
+                commit,
+                None,
+            )
+        }
+        (None, Some(name)) => {
+            let commit = resolve_commit(repo, &name)?;
+            (RefValue::Direct(commit), commit, None)
+        }
+        (None, None) if detach => {
+            let commit = resolve_commit(repo, "HEAD")?;
+            (RefValue::Direct(commit), commit, None)
+        }
+        (None, None) => match branch_exists(&basename)? {
+            Some(_) => {
+                let commit = resolve_commit(repo, &basename)?;
+                (
+                    RefValue::Symbolic(format!("refs/heads/{basename}")),
+                    commit,
+                    None,
+                )
+            }
+            None => {
+                let commit = resolve_commit(repo, "HEAD")?;
+                let head = RefValue::Symbolic(format!("refs/heads/{basename}"));
+                (head, commit, Some(basename.clone()))
+            }
+        },
+    };
+
+    if let RefValue::Symbolic(name) = &head {
+        for worktree in repo.worktrees()? {
+            let refs = RefStore::new(&worktree.gitdir, repo.commondir());
+            if !worktree.bare && refs.read("HEAD").ok().flatten().as_ref() == Some(&head) {
+                let short = name.trim_start_matches("refs/heads/").to_owned();
+                return Err(WorktreeError::BranchCheckedOut(short, display_path(&worktree)).into());
+            }
+        }
+    }
+    match (&new_branch, &head) {
+        (Some(branch), _) => eprintln!("Preparing worktree (new branch '{branch}')"),
+        (None, RefValue::Symbolic(name)) => eprintln!(
+            "Preparing worktree (checking out '{}')",
+            name.trim_start_matches("refs/heads/")
+        ),
+        (None, RefValue::Direct(id)) => {
+            eprintln!(
+                "Preparing worktree (detached HEAD {})",
+                &id.to_string()[..7]
+            )
+        }
+    }
+
+    // the administrative directory is locked while it is being set up
+    let worktrees = fs::canonicalize(repo.commondir())?.join("worktrees");
+    let stem = basename.replace(
+        |c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '-' && c != '_',
+        "-",
+    );
+    let mut name = stem.clone();
+    let mut counter = 0;
+    while worktrees.join(&name).exists() {
+        counter += 1;
+        name = format!("{stem}{counter}");
+    }
+    let admin = worktrees.join(&name);
+    fs::create_dir_all(&admin)?;
+    fs::write(admin.join("locked"), "initializing")?;
+
+    fs::create_dir_all(path)?;
+    let path = fs::canonicalize(path)?;
+    fs::write(
+        admin.join("gitdir"),
+        format!("{}\n", path.join(".git").display()),
+    )?;
+    fs::write(path.join(".git"), format!("gitdir: {}\n", admin.display()))?;
+    fs::write(admin.join("commondir"), "../..\n")?;
+    // the new worktree's `HEAD`, and the branch it may create
+    let mut transaction = repo.transaction_for(RefStore::new(&admin, repo.commondir()))?;
+    if let Some(branch) = &new_branch {
+        let update = RefUpdate::create(format!("refs/heads/{branch}"), commit);
+        transaction.add(update.with_message(message));
+    }
+    transaction.add(RefUpdate::new("HEAD", RefChange::Set(head), None).no_deref());
+    transaction.commit()?;
+
+    let GitObject::Commit(commit) = repo.objects().read_object(&commit)? else {
+        unreachable!("resolve_commit only returns commits");
+    };
+    let mut index = Index::new(repo.object_format());
+    checkout_tree(&repo.objects(), &commit.tree()?, &path, &[], &mut index)?;
+    index.sort();
+    index.write(admin.join("index"))?;
+
+    match lock {
+        Some(reason) => fs::write(admin.join("locked"), reason)?,
+        None => fs::remove_file(admin.join("locked"))?,
+    }
+    Ok(())
+}
+
+/// Writes the contents of `tree` below `dir`, recording each file in `index`.
+fn checkout_tree(
+    objects: &impl ObjectStore,
+    tree: &ObjectId,
+    dir: &Path,
+    prefix: &[u8],
+    index: &mut Index,
+) -> Result<(), Box<dyn Error>> {
+    let GitObject::Tree(tree) = objects.read_object(tree)? else {
+        return Err(WorktreeError::InvalidReference(tree.to_string()).into());
+    };
+    for entry in tree.entries {
+        let name = String::from_utf8(entry.name.clone())
+            .ok()
+            .filter(|name| {
+                !name.is_empty()
+                    && !name.contains('/')
+                    && name != "."
+                    && name != ".."
+                    && !name.eq_ignore_ascii_case(".git")
+            })
+            .ok_or_else(|| {
+                WorktreeError::InvalidPath(String::from_utf8_lossy(&entry.name).into_owned())
+            })?;
+        let path = dir.join(&name);
+        let mut full = prefix.to_vec();
+        if !full.is_empty() {
+            full.push(b'/');
+        }
+        full.extend_from_slice(name.as_bytes());
+
+        match entry.mode.object_type() {
+            ObjectType::Tree => {
+                fs::create_dir_all(&path)?;
+                checkout_tree(objects, &entry.id, &path, &full, index)?;
+            }
+            ObjectType::Commit => {
+                // submodules are left as empty directories
+                fs::create_dir_all(&path)?;
+                index.entries.push(IndexEntry {
+                    mode: entry.mode.bits(),
+                    id: entry.id,
+                    flags: full.len().min(0xfff) as u16,
+                    path: full,
+                    ..IndexEntry::default()
+                });
+            }
+            ObjectType::Blob | ObjectType::Tag => {
+                let data = objects.read(&entry.id)?.data;
+                let mode = match entry.mode.bits() {
+                    0o120000 => 0o120000,
+                    bits if bits & 0o100 != 0 => 0o100755,
+                    _ => 0o100644,
+                };
+                write_file(&path, &data, mode)?;
+                let metadata = fs::symlink_metadata(&path)?;
+                index
+                    .entries
+                    .push(IndexEntry::from_metadata(full, entry.id, mode, &metadata));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_file(path: &Path, data: &[u8], mode: u32) -> io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt as _, fs::PermissionsExt as _};
+
+    if mode == 0o120000 {
+        return std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(data), path);
+    }
+    fs::write(path, data)?;
+    if mode == 0o100755 {
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_file(path: &Path, data: &[u8], _mode: u32) -> io::Result<()> {
+    fs::write(path, data)
+}
+
+fn list(repo: &RealRepo, porcelain: bool) -> Result<(), Box<dyn Error>> {
+    let worktrees = repo.worktrees()?;
+    let mut stdout = io::stdout().lock();
+    if porcelain {
+        for worktree in &worktrees {
+            writeln!(stdout, "worktree {}", display_path(worktree).display())?;
+            if worktree.bare {
+                writeln!(stdout, "bare")?;
+            } else {
+                let refs = RefStore::new(&worktree.gitdir, repo.commondir());
+                let head = refs.read("HEAD").ok().flatten();
+                let id = refs.resolve("HEAD").ok().flatten();
+                writeln!(stdout, "HEAD {}", id.unwrap_or_default())?;
+                match head {
+                    Some(RefValue::Symbolic(name)) => writeln!(stdout, "branch {name}")?,
+                    _ => writeln!(stdout, "detached")?,
+                }
+            }
+            match worktree.locked.as_deref() {
+                Some("") => writeln!(stdout, "locked")?,
+                Some(reason) => writeln!(stdout, "locked {reason}")?,
+                None => {}
+            }
+            if let Some(reason) = &worktree.prunable {
+                writeln!(stdout, "prunable {reason}")?;
+            }
+            writeln!(stdout)?;
+        }
+        return Ok(());
+    }
+
+    let paths: Vec<_> = worktrees
+        .iter()
+        .map(|worktree| display_path(worktree).display().to_string())
+        .collect();
+    let width = paths.iter().map(String::len).max().unwrap_or_default();
+    for (worktree, path) in worktrees.iter().zip(&paths) {
+        write!(stdout, "{path:width$}  ")?;
+        if worktree.bare {
+            write!(stdout, "(bare)")?;
+        } else {
+            let refs = RefStore::new(&worktree.gitdir, repo.commondir());
+            let id = refs.resolve("HEAD").ok().flatten();
+            write!(stdout, "{} ", &id.unwrap_or_default().to_string()[..7])?;
+            match refs.read("HEAD").ok().flatten() {
+                Some(RefValue::Symbolic(name)) => {
+                    write!(stdout, "[{}]", name.trim_start_matches("refs/heads/"))?
+                }
+                _ => write!(stdout, "(detached HEAD)")?,
+            }
+        }
+        if worktree.locked.is_some() {
+            write!(stdout, " locked")?;
+        }
+        if worktree.prunable.is_some() {
+            write!(stdout, " prunable")?;
+        }
+        writeln!(stdout)?;
+    }
+    Ok(())
+}
+
+fn prune(repo: &RealRepo, dry_run: bool, verbose: bool) -> Result<(), Box<dyn Error>> {
+    for worktree in repo.worktrees()? {
+        let (Some(name), Some(reason)) = (&worktree.name, &worktree.prunable) else {
+            continue;
+        };
+        if dry_run || verbose {
+            println!("Removing worktrees/{name}: {reason}");
+        }
+        if !dry_run {
+            fs::remove_dir_all(&worktree.gitdir)?;
+        }
+    }
+
+    // like git, drop `worktrees/` once it is empty
+    let worktrees = repo.commondir().join("worktrees");
+    if !dry_run && fs::read_dir(&worktrees).is_ok_and(|mut entries| entries.next().is_none()) {
+        fs::remove_dir(worktrees)?;
+    }
+    Ok(())
+}
+
+fn remove(repo: &RealRepo, worktree: &Worktree, force: u8) -> Result<(), Box<dyn Error>> {
+    if worktree.locked.is_some() && force < 2 {
+        return Err(WorktreeError::Locked.into());
+    }
+    if let Some(path) = worktree.path.as_deref().filter(|path| path.exists()) {
+        if force == 0
+            && is_dirty(
+                path,
+                &Index::read(worktree.gitdir.join("index"), repo.object_format())?,
+            )?
+        {
+            return Err(WorktreeError::Dirty(path.to_owned()).into());
+        }
+        fs::remove_dir_all(path)?;
+    }
+    fs::remove_dir_all(&worktree.gitdir)?;
+    Ok(())
+}
+
+/// Whether any tracked file differs from the index, or any untracked file exists.
+fn is_dirty(worktree: &Path, index: &Index) -> Result<bool, Box<dyn Error>> {
+    let mut tracked = HashSet::new();
+    for entry in &index.entries {
+        let relative = String::from_utf8_lossy(&entry.path).into_owned();
+        let path = worktree.join(&relative);
+        tracked.insert(PathBuf::from(relative));
+        if entry.mode == 0o160000 {
+            continue;
+        }
+
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(true),
+            Err(err) => return Err(err.into()),
+        };
+        let data = if metadata.file_type().is_symlink() {
+            fs::read_link(&path)?
+                .to_string_lossy()
+                .into_owned()
+                .into_bytes()
+        } else {
+            fs::read(&path)?
+        };
+        if ObjectId::hash(entry.id.algorithm(), ObjectType::Blob, &data) != entry.id {
+            return Ok(true);
+        }
+    }
+
+    let mut dirs = vec![PathBuf::new()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(worktree.join(&dir))? {
+            let entry = entry?;
+            let relative = dir.join(entry.file_name());
+            if relative == Path::new(".git") || tracked.contains(&relative) {
+                continue;
+            }
+            if entry.file_type()?.is_dir() {
+                dirs.push(relative);
+            } else {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Finds a linked worktree by its path or by its name under `worktrees/`.
+fn find_linked(repo: &RealRepo, cwd: &Path, arg: &Path) -> Result<Worktree, Box<dyn Error>> {
+    let wanted = fs::canonicalize(cwd.join(arg)).ok();
+    for worktree in repo.worktrees()? {
+        let by_path = wanted.is_some()
+            && worktree
+                .path
+                .as_deref()
+                .and_then(|path| fs::canonicalize(path).ok())
+                == wanted;
+        let by_name = worktree.name.as_deref().map(Path::new) == Some(arg);
+        if by_path || by_name {
+            if worktree.name.is_none() {
+                return Err(WorktreeError::MainWorktree(arg.to_owned()).into());
+            }
+            return Ok(worktree);
+        }
+    }
+    Err(WorktreeError::NotAWorktree(arg.to_owned()).into())
+}
+
+fn display_path(worktree: &Worktree) -> PathBuf {
+    worktree
+        .path
+        .clone()
+        .unwrap_or_else(|| worktree.gitdir.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::repo::{InitOptions, RealRepoCreator, RefTransaction, RepoCreator as _};
+
+    /// A repository whose `HEAD` is a commit of one file, `a`.
+    fn repo(root: &Path) -> RealRepo {
+        let repo = RealRepoCreator::create(root.join("main"), &InitOptions::default()).unwrap();
+        let objects = repo.objects();
+        let blob = objects.write(ObjectType::Blob, b"a\n").unwrap();
+        let mut tree = b"100644 a\0".to_vec();
+        tree.extend_from_slice(blob.as_bytes());
+        let tree = objects.write(ObjectType::Tree, &tree).unwrap();
+        let commit =
+            format!("tree {tree}\nauthor A <a@b> 0 +0000\ncommitter A <a@b> 0 +0000\n\nm\n");
+        let commit = objects
+            .write(ObjectType::Commit, commit.as_bytes())
+            .unwrap();
+        let mut transaction = RefTransaction::new(repo.refs());
+        transaction.add(RefUpdate::create("HEAD", commit));
+        transaction.commit().unwrap();
+        repo
+    }
+
+    fn linked(repo: &RealRepo, name: &str) -> Worktree {
+        let worktrees = repo.worktrees().unwrap();
+        let found = worktrees
+            .into_iter()
+            .find(|w| w.name.as_deref() == Some(name));
+        found.unwrap()
+    }
+
+    fn error(result: Result<(), Box<dyn Error>>) -> WorktreeError {
+        *result.unwrap_err().downcast().unwrap()
+    }
+
+    #[test]
+    fn add_names() {
+        let tempdir = TempDir::new().unwrap();
+        let root = fs::canonicalize(tempdir.path()).unwrap();
+        let repo = repo(&root);
+
+        for parent in ["one", "two", "three"] {
+            let path = root.join(parent).join("my tree");
+            add(&repo, &path, None, true, None, None).unwrap();
+            assert_eq!(fs::read_to_string(path.join("a")).unwrap(), "a\n");
+        }
+        // names that collide keep the sanitised stem
+        for (name, parent) in [
+            ("my-tree", "one"),
+            ("my-tree1", "two"),
+            ("my-tree2", "three"),
+        ] {
+            let worktree = linked(&repo, name);
+            assert_eq!(worktree.path, Some(root.join(parent).join("my tree")));
+            assert_eq!(worktree.locked, None);
+        }
+        assert!(matches!(
+            error(add(
+                &repo,
+                &root.join("one/my tree"),
+                None,
+                true,
+                None,
+                None
+            )),
+            WorktreeError::AlreadyExists(_)
+        ));
+    }
+
+    #[test]
+    fn lock_prune_and_remove() {
+        let tempdir = TempDir::new().unwrap();
+        let root = fs::canonicalize(tempdir.path()).unwrap();
+        let repo = repo(&root);
+        add(
+            &repo,
+            &root.join("kept"),
+            None,
+            true,
+            None,
+            Some("usb".to_owned()),
+        )
+        .unwrap();
+        add(&repo, &root.join("gone"), None, true, None, None).unwrap();
+        assert_eq!(linked(&repo, "kept").locked.as_deref(), Some("usb"));
+
+        // a locked worktree survives its directory going missing
+        fs::remove_dir_all(root.join("kept")).unwrap();
+        fs::remove_dir_all(root.join("gone")).unwrap();
+        prune(&repo, false, false).unwrap();
+        assert!(linked(&repo, "kept").gitdir.is_dir());
+        assert!(!repo.commondir().join("worktrees/gone").exists());
+
+        // and needs --force twice to be removed
+        for force in [0, 1] {
+            let result = remove(&repo, &linked(&repo, "kept"), force);
+            assert!(matches!(error(result), WorktreeError::Locked));
+        }
+        remove(&repo, &linked(&repo, "kept"), 2).unwrap();
+        assert!(!repo.commondir().join("worktrees/kept").exists());
+    }
+
+    #[test]
+    fn remove_dirty() {
+        let tempdir = TempDir::new().unwrap();
+        let root = fs::canonicalize(tempdir.path()).unwrap();
+        let repo = repo(&root);
+        let path = root.join("linked");
+        add(&repo, &path, None, true, None, None).unwrap();
+        let index = || {
+            Index::read(
+                linked(&repo, "linked").gitdir.join("index"),
+                repo.object_format(),
+            )
+        };
+        assert!(!is_dirty(&path, &index().unwrap()).unwrap());
+
+        // untracked and modified files are both local changes
+        fs::write(path.join("new"), "").unwrap();
+        assert!(matches!(
+            error(remove(&repo, &linked(&repo, "linked"), 0)),
+            WorktreeError::Dirty(_)
+        ));
+        fs::remove_file(path.join("new")).unwrap();
+        fs::write(path.join("a"), "changed\n").unwrap();
+        assert!(is_dirty(&path, &index().unwrap()).unwrap());
+
+        remove(&repo, &linked(&repo, "linked"), 1).unwrap();
+        assert!(!path.exists());
+        assert!(!repo.commondir().join("worktrees/linked").exists());
+    }
+}